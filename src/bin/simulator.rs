@@ -0,0 +1,102 @@
+// runs the swarm headless against a room layout and prints how the colony did.
+//
+//   cargo run --bin simulator -- simulator/layouts/basic.json --until-rcl 3
+use std::{env, fs, process};
+
+use swarm::simulator::{run_simulation, RoomLayout, SimulationOptions};
+
+const USAGE: &str = "usage: simulator <layout.json> [--ticks N] [--until-rcl N] [--report-every N] [--verbose]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (layout_path, options, verbose) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let level = if verbose {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    };
+    fern::Dispatch::new()
+        .level(level)
+        .format(|out, message, record| {
+            out.finish(format_args!("({}) {}: {}", record.level(), record.target(), message))
+        })
+        .chain(std::io::stdout())
+        .apply()
+        .expect("logger is set up once");
+
+    let layout = fs::read_to_string(&layout_path)
+        .map_err(|e| format!("read {} failed. {}", layout_path, e))
+        .and_then(|json| RoomLayout::from_json(&json).map_err(|e| format!("{:?}", e)));
+    let layout = match layout {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let report = match run_simulation(&layout, &options) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("simulation failed. {:?}", e);
+            process::exit(1);
+        }
+    };
+
+    println!("ticks: {}", report.ticks);
+    println!(
+        "rcl: {} ({}/{})",
+        report.rcl, report.progress, report.progress_total
+    );
+    for (rcl, tick) in &report.stats.rcl_reached {
+        println!("ticks to rcl {}: {}", rcl, tick);
+    }
+    println!("creeps alive: {}", report.creeps);
+    println!("creeps spawned: {}", report.stats.creeps_spawned);
+    println!("creeps died: {}", report.stats.creeps_died);
+    println!("structures built: {}", report.stats.structures_built);
+    println!("energy harvested: {}", report.stats.energy_harvested);
+    println!("energy spent on spawning: {}", report.stats.energy_spent_on_spawning);
+    println!("energy spent on building: {}", report.stats.energy_spent_on_building);
+    println!("energy spent on upgrading: {}", report.stats.energy_spent_on_upgrading);
+    println!("energy spent on repairing: {}", report.stats.energy_spent_on_repairing);
+
+    if options.until_rcl.is_some_and(|rcl| report.rcl < rcl) {
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(String, SimulationOptions, bool), String> {
+    let mut layout_path = None;
+    let mut options = SimulationOptions::default();
+    let mut verbose = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .and_then(|value| value.parse::<u32>().ok())
+                .ok_or(format!("{} expects a number", name))
+        };
+        match arg.as_str() {
+            "--ticks" => options.max_ticks = value("--ticks")?,
+            "--until-rcl" => options.until_rcl = Some(value("--until-rcl")?.min(8) as u8),
+            "--report-every" => options.report_every = value("--report-every")?,
+            "--verbose" => verbose = true,
+            path if layout_path.is_none() && !path.starts_with("--") => {
+                layout_path = Some(path.to_string())
+            }
+            other => return Err(format!("unexpected argument {}", other)),
+        }
+    }
+
+    let layout_path = layout_path.ok_or("missing layout file".to_string())?;
+    Ok((layout_path, options, verbose))
+}
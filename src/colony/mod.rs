@@ -1,20 +1,27 @@
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::BTreeMap;
 use std::{cell::RefCell, rc::Rc};
 
 use log::*;
-use screeps::{game, ErrorCode, FindConstant, HasTypedId, Room, RoomName};
+use screeps::RoomName;
 use serde::{Deserialize, Serialize};
-use wasm_bindgen::JsValue;
-use web_sys::console::warn;
 
-use crate::creep_setup::CreepSetup;
 use crate::error::SwarmError;
-use crate::hive::{self, Hive};
+use crate::hive::{Hive, SpawnRequest};
+use crate::overlord::defense::DefenseOverlord;
 use crate::overlord::mine::MineOverlord;
-use crate::overlord::{Overlord, OverlordType};
+use crate::overlord::transport::TransportOverlord;
+use crate::overlord::upgrade::UpgradeOverlord;
+use crate::overlord::work::WorkOverlord;
+use crate::overlord::{Overlord, OverlordCache, OverlordType};
+use crate::planner::RoomPlan;
+use crate::profiler;
+use crate::world::{CreepInfo, World};
+
+// ticks between checks whether the base layout changed under the roads
+const ROAD_CHECK_INTERVAL: u32 = 100;
 
 // according to https://docs.screeps.com/control.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ColonyStage {
     Initialize,
     WithStorage,  // Level 4
@@ -22,24 +29,51 @@ pub enum ColonyStage {
     Mature,       // Level 8
 }
 
+impl ColonyStage {
+    pub fn from_rcl(rcl: u8) -> ColonyStage {
+        if rcl < 4 {
+            return ColonyStage::Initialize;
+        }
+        if rcl < 6 {
+            return ColonyStage::WithStorage;
+        }
+        if rcl < 8 {
+            return ColonyStage::WithTerminal;
+        }
+        ColonyStage::Mature
+    }
+}
+
+// persisted form of a colony, overlords are keyed by overlord name.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ColonyCache {
+    pub overlords: BTreeMap<String, OverlordCache>,
+    #[serde(default)]
+    pub spawn_queue: Vec<SpawnRequest>,
+    #[serde(default)]
+    pub room_plan: Option<RoomPlan>,
+}
+
 pub struct Colony {
     pub rcl: u8,
     pub stage: ColonyStage,
     pub central_room_name: String,
-    pub room: Room,
+    pub room_name: RoomName,
+    pub world: Rc<dyn World>,
     pub hive: Rc<RefCell<Hive>>,
-    pub overlords: HashMap<String, Box<dyn Overlord>>,
+    pub overlords: BTreeMap<String, Box<dyn Overlord>>,
+    // none if the room has no space for a base
+    pub plan: Option<RoomPlan>,
 }
 
 impl Colony {
     pub fn new_from_room_name(
+        world: Rc<dyn World>,
         central_room_name: String,
+        cache: Option<&ColonyCache>,
     ) -> Result<Rc<RefCell<Self>>, SwarmError> {
-        let room_name: RoomName;
-        match RoomName::new(&central_room_name) {
-            Ok(res_room_name) => {
-                room_name = res_room_name;
-            }
+        let room_name = match RoomName::new(&central_room_name) {
+            Ok(res_room_name) => res_room_name,
             Err(e) => {
                 warn!(
                     "Colony parse room name failed. {:?}, error: {:?}",
@@ -49,125 +83,551 @@ impl Colony {
                     "parse colony room name failed.".to_string(),
                 ));
             }
+        };
+
+        Self::new_from_room(world, room_name, cache)
+    }
+
+    pub fn new_from_room(
+        world: Rc<dyn World>,
+        room_name: RoomName,
+        cache: Option<&ColonyCache>,
+    ) -> Result<Rc<RefCell<Self>>, SwarmError> {
+        Self::new_internal(world, room_name, cache)
+    }
+
+    fn new_internal(
+        world: Rc<dyn World>,
+        room_name: RoomName,
+        cache: Option<&ColonyCache>,
+    ) -> Result<Rc<RefCell<Self>>, SwarmError> {
+        let rcl = Self::get_rcl(world.as_ref(), room_name)?;
+
+        let hive = Self::initialize_hive(world.clone(), room_name)?;
+        if let Some(cache) = cache {
+            hive.as_ref()
+                .borrow_mut()
+                .hatcherys
+                .restore_queue(cache.spawn_queue.clone());
         }
 
-        let room = game::rooms()
-            .get(room_name)
-            .ok_or(SwarmError::InternalAssertionFailed(
-                "get room failed".to_string(),
-            ))?;
-
-        Self::new_from_room(room)
-    }
-
-    pub fn new_from_room(room: Room) -> Result<Rc<RefCell<Self>>, SwarmError> {
-      Self::new_internal(room)
-    }
-
-    fn new_internal(room: Room) -> Result<Rc<RefCell<Self>>, SwarmError> {
-        let rcl = room.controller().unwrap().level();
-
-        let hive = Self::initialize_hive(&room)?;
-
-        // let colony_cache = if let Some(cache_value) = cache {
-        //     let result_cache: Result<ColonyCache, _> = serde_wasm_bindgen::from_value(cache_value);
-        //     if result_cache.is_err() {
-        //         warn!(
-        //             "parse colony cache failed. {:?}",
-        //             result_cache.err().unwrap()
-        //         );
-        //         return Err(SwarmError::InternalAssertionFailed(
-        //             "parse colony cache failed".to_string(),
-        //         ));
-        //     }
-        //     Some(result_cache.unwrap())
-        // } else {
-        //     None
-        // };
-
-        let overlords = Self::initialize_overlords(hive.clone())?;
-
-        let colony = Colony {
-            rcl: rcl,
-            stage: Colony::get_colony_stage_by_rcl(rcl),
-            central_room_name: room.name().to_string(),
-            room: room,
-            hive: hive,
-            overlords: overlords,
+        let overlords = Self::initialize_overlords(world.clone(), hive.clone(), cache)?;
+        let plan = Self::initialize_plan(world.as_ref(), room_name, cache);
+
+        let mut colony = Colony {
+            rcl,
+            stage: ColonyStage::from_rcl(rcl),
+            central_room_name: room_name.to_string(),
+            room_name,
+            world,
+            hive,
+            overlords,
+            plan,
         };
+        colony.update_roads();
 
-        debug!("initialize colony done {}", colony.room.name());
+        debug!("initialize colony done {}", colony.room_name);
 
         Ok(Rc::new(RefCell::new(colony)))
     }
 
+    // planning is expensive, it only happens once per room
+    fn initialize_plan(
+        world: &dyn World,
+        room_name: RoomName,
+        cache: Option<&ColonyCache>,
+    ) -> Option<RoomPlan> {
+        if let Some(plan) = cache.and_then(|cache| cache.room_plan.clone()) {
+            return Some(plan);
+        }
+        match RoomPlan::for_room(world, room_name) {
+            Ok(plan) => {
+                info!("planned base of {} around {:?}", room_name, plan.anchor);
+                Some(plan)
+            }
+            Err(e) => {
+                warn!("plan base of {} failed: {:?}", room_name, e);
+                None
+            }
+        }
+    }
+
+    // plans the roads again when the layout changed, the repairs keep up the
+    // planned roads only
+    fn update_roads(&mut self) {
+        let plan = match self.plan.as_mut() {
+            Some(plan) => plan,
+            None => return,
+        };
+        if plan.update_roads(self.world.as_ref(), self.room_name) {
+            info!("planned {} roads of {}", plan.roads.len(), self.room_name);
+        }
+        self.hive
+            .as_ref()
+            .borrow_mut()
+            .repairs
+            .set_wanted_roads(plan.wanted_roads(self.room_name));
+    }
+
+    fn get_rcl(world: &dyn World, room_name: RoomName) -> Result<u8, SwarmError> {
+        world
+            .controller(room_name)
+            .filter(|controller| controller.my)
+            .map(|controller| controller.level)
+            .ok_or(SwarmError::InternalAssertionFailed(
+                "colony lost its controller".to_string(),
+            ))
+    }
+
+    // refresh the state of a colony kept on the heap. fails if the central room
+    // is no longer visible or no longer ours.
+    pub fn refresh(&mut self) -> Result<(), SwarmError> {
+        let rcl = Self::get_rcl(self.world.as_ref(), self.room_name)?;
+        self.rcl = rcl;
+        self.stage = ColonyStage::from_rcl(rcl);
+
+        let spawned = {
+            let mut hive = self.hive.as_ref().borrow_mut();
+            hive.refresh()?;
+            hive.hatcherys.take_spawned()
+        };
+        if self.world.time().is_multiple_of(ROAD_CHECK_INTERVAL) {
+            self.update_roads();
+        }
+
+        for (name, overlord) in self.overlords.iter_mut() {
+            if let Err(e) = overlord.refresh() {
+                warn!("refresh overlord {} failed: {:?}", name, e);
+            }
+        }
+
+        for (overlord_name, creep_name) in spawned {
+            match self.overlords.get_mut(&overlord_name) {
+                Some(overlord) => overlord.assign_creep(creep_name),
+                None => info!(
+                    "spawned creep {} for unknown overlord {} goes idle",
+                    creep_name, overlord_name
+                ),
+            }
+        }
+
+        let idle = self.idle_creeps();
+        self.hive.as_ref().borrow_mut().hatcherys.set_idle_creeps(idle);
+
+        Ok(())
+    }
+
+    // creeps of the colony no overlord owns: their overlord is gone with its
+    // source or was renamed, or it released them. creeps from before they knew
+    // their colony belong to the room they are in.
+    fn idle_creeps(&self) -> Vec<CreepInfo> {
+        self.world
+            .creeps()
+            .into_iter()
+            .filter(|creep| match &creep.memory {
+                Some(memory) => {
+                    let ours = if memory.colony.is_empty() {
+                        creep.pos.room_name() == self.room_name
+                    } else {
+                        memory.colony == self.central_room_name
+                    };
+                    ours && !self.overlords.contains_key(&memory.overlord)
+                }
+                None => false,
+            })
+            .collect()
+    }
+
     pub fn run(&self) {
-      // first run overlords
-      for overlord in self.overlords.values() {
-        let overlord_run_result = overlord.run();
+      let world = self.world.as_ref();
+      // first run overlords, those that can wait give way when the bucket runs low
+      let budget = profiler::budget(world);
+      for (name, overlord) in self.overlords.iter() {
+        if !budget.allows(overlord.priority(), world.time()) {
+          debug!("overlord {} waits, cpu budget {:?}", name, budget);
+          continue;
+        }
+        let overlord_run_result = profiler::measure(world, name, || overlord.run());
         if overlord_run_result.is_err() {
           warn!("run overlord failed: {:?}", overlord_run_result)
         }
       }
       // then run hive
-      self.hive.as_ref().borrow_mut().run();
+      let hive = format!("{}/hive", self.central_room_name);
+      profiler::measure(world, &hive, || self.hive.as_ref().borrow_mut().run());
+      if let Some(plan) = &self.plan {
+        plan.place_sites(self.world.as_ref(), self.room_name, self.rcl);
+      }
     }
 
-    fn get_colony_stage_by_rcl(rcl: u8) -> ColonyStage {
-        if rcl < 4 {
-            return ColonyStage::Initialize;
-        }
-        if rcl < 6 {
-            return ColonyStage::WithStorage;
-        }
-        if rcl < 8 {
-            return ColonyStage::WithTerminal;
+    pub fn get_cache(&self) -> ColonyCache {
+        ColonyCache {
+            overlords: self
+                .overlords
+                .iter()
+                .map(|(name, overlord)| {
+                    (
+                        name.clone(),
+                        OverlordCache {
+                            overlord_type: overlord.get_type(),
+                            cache: overlord.get_cache(),
+                        },
+                    )
+                })
+                .collect(),
+            spawn_queue: self.hive.as_ref().borrow().hatcherys.get_cache(),
+            room_plan: self.plan.clone(),
         }
-        return ColonyStage::Mature;
     }
 
-    fn initialize_hive(room: &Room) -> Result<Rc<RefCell<Hive>>, SwarmError> {
-        Ok(Hive::new(room)?)
+    fn initialize_hive(
+        world: Rc<dyn World>,
+        room_name: RoomName,
+    ) -> Result<Rc<RefCell<Hive>>, SwarmError> {
+        Hive::new(world, room_name)
     }
 
     fn initialize_overlords(
+        world: Rc<dyn World>,
         hive: Rc<RefCell<Hive>>,
-    ) -> Result<HashMap<String, Box<dyn Overlord>>, SwarmError> {
+        cache: Option<&ColonyCache>,
+    ) -> Result<BTreeMap<String, Box<dyn Overlord>>, SwarmError> {
         // initialize mine overlord
-        let mut overlord_map: HashMap<String, Box<dyn Overlord>> = HashMap::new();
-        // if cache.is_some() && cache.as_ref().unwrap().mine_overlord_cache.is_some() {
-        //     Self::initialize_mine_overlord_by_cache(
-        //         &mut overlord_map,
-        //         hive,
-        //         cache
-        //             .as_ref()
-        //             .unwrap()
-        //             .mine_overlord_cache
-        //             .as_ref()
-        //             .unwrap(),
-        //     )?;
-        Self::initialize_mine_overlord(&mut overlord_map, hive)?;
+        let mut overlord_map: BTreeMap<String, Box<dyn Overlord>> = BTreeMap::new();
+        Self::initialize_mine_overlord(&mut overlord_map, world.clone(), hive.clone(), cache)?;
+        // after the mines, so it takes over haulers they spawned
+        Self::initialize_transport_overlord(&mut overlord_map, world.clone(), hive.clone(), cache)?;
+        Self::initialize_upgrade_overlord(&mut overlord_map, world.clone(), hive.clone(), cache)?;
+        Self::initialize_work_overlord(&mut overlord_map, world.clone(), hive.clone(), cache)?;
+        Self::initialize_defense_overlord(&mut overlord_map, world, hive, cache)?;
 
         Ok(overlord_map)
     }
 
+    // overlords found in the cache are restored from it, a cache that fails to
+    // parse is dropped and the overlord is created from scratch.
     fn initialize_mine_overlord(
-        overlord_map: &mut HashMap<String, Box<dyn Overlord>>,
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        world: Rc<dyn World>,
         hive: Rc<RefCell<Hive>>,
+        cache: Option<&ColonyCache>,
     ) -> Result<(), SwarmError> {
-        let sources = hive
-            .as_ref()
-            .borrow()
-            .hatcherys
-            .room
-            .find(screeps::find::SOURCES_ACTIVE, None);
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let sources = world.sources(room_name);
         for source in sources {
-            let overlord = MineOverlord::new(source.id(), hive.clone())?;
-            let old_value = overlord_map.insert(overlord.get_name(), overlord);
-            if old_value.is_some() {
-                warn!("overlord has dup name: {}", old_value.unwrap().get_name())
+            let name = MineOverlord::get_name_by_source(room_name, source.id);
+            let overlord_cache = cache
+                .and_then(|cache| cache.overlords.get(&name))
+                .filter(|overlord_cache| overlord_cache.overlord_type == OverlordType::Mine);
+            let overlord = match overlord_cache {
+                Some(overlord_cache) => MineOverlord::new_from_cache(
+                    world.clone(),
+                    &overlord_cache.cache,
+                    hive.clone(),
+                )
+                .or_else(|_| MineOverlord::new(world.clone(), source.id, hive.clone()))?,
+                None => MineOverlord::new(world.clone(), source.id, hive.clone())?,
+            };
+            if let Some(old_value) = overlord_map.insert(overlord.get_name(), overlord) {
+                warn!("overlord has dup name: {}", old_value.get_name())
             }
         }
         Ok(())
     }
+
+    fn initialize_transport_overlord(
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cache: Option<&ColonyCache>,
+    ) -> Result<(), SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        Self::initialize_colony_overlord(
+            overlord_map,
+            TransportOverlord::get_name_by_room(room_name),
+            OverlordType::Transport,
+            cache,
+            |overlord_cache| {
+                TransportOverlord::new_from_cache(world.clone(), overlord_cache, hive.clone())
+                    .map(|overlord| overlord as Box<dyn Overlord>)
+            },
+            || TransportOverlord::new(world.clone(), hive.clone()).map(|overlord| overlord as Box<dyn Overlord>),
+        )
+    }
+
+    fn initialize_upgrade_overlord(
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cache: Option<&ColonyCache>,
+    ) -> Result<(), SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        Self::initialize_colony_overlord(
+            overlord_map,
+            UpgradeOverlord::get_name_by_room(room_name),
+            OverlordType::Upgrade,
+            cache,
+            |overlord_cache| {
+                UpgradeOverlord::new_from_cache(world.clone(), overlord_cache, hive.clone())
+                    .map(|overlord| overlord as Box<dyn Overlord>)
+            },
+            || UpgradeOverlord::new(world.clone(), hive.clone()).map(|overlord| overlord as Box<dyn Overlord>),
+        )
+    }
+
+    fn initialize_work_overlord(
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cache: Option<&ColonyCache>,
+    ) -> Result<(), SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        Self::initialize_colony_overlord(
+            overlord_map,
+            WorkOverlord::get_name_by_room(room_name),
+            OverlordType::Work,
+            cache,
+            |overlord_cache| {
+                WorkOverlord::new_from_cache(world.clone(), overlord_cache, hive.clone())
+                    .map(|overlord| overlord as Box<dyn Overlord>)
+            },
+            || WorkOverlord::new(world.clone(), hive.clone()).map(|overlord| overlord as Box<dyn Overlord>),
+        )
+    }
+
+    fn initialize_defense_overlord(
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cache: Option<&ColonyCache>,
+    ) -> Result<(), SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        Self::initialize_colony_overlord(
+            overlord_map,
+            DefenseOverlord::get_name_by_room(room_name),
+            OverlordType::Defense,
+            cache,
+            |overlord_cache| {
+                DefenseOverlord::new_from_cache(world.clone(), overlord_cache, hive.clone())
+                    .map(|overlord| overlord as Box<dyn Overlord>)
+            },
+            || DefenseOverlord::new(world.clone(), hive.clone()).map(|overlord| overlord as Box<dyn Overlord>),
+        )
+    }
+
+    // overlords a colony has exactly one of. restored from the cache like the
+    // mine overlords, created from scratch when the cache is missing or broken.
+    fn initialize_colony_overlord<C, N>(
+        overlord_map: &mut BTreeMap<String, Box<dyn Overlord>>,
+        name: String,
+        overlord_type: OverlordType,
+        cache: Option<&ColonyCache>,
+        from_cache: C,
+        new: N,
+    ) -> Result<(), SwarmError>
+    where
+        C: FnOnce(&str) -> Result<Box<dyn Overlord>, SwarmError>,
+        N: Fn() -> Result<Box<dyn Overlord>, SwarmError>,
+    {
+        let overlord_cache = cache
+            .and_then(|cache| cache.overlords.get(&name))
+            .filter(|overlord_cache| overlord_cache.overlord_type == overlord_type);
+        let overlord = match overlord_cache {
+            Some(overlord_cache) => from_cache(&overlord_cache.cache).or_else(|_| new())?,
+            None => new()?,
+        };
+        overlord_map.insert(name, overlord);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::creep_setup_templates::{ROLE_DRONE, ROLE_UPGRADER},
+        world::mock::{position, MockAction, MockWorld},
+        zerg::CreepMemory,
+    };
+    use screeps::Part;
+
+    #[test]
+    fn colony_creates_one_mine_overlord_per_source() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        world.add_controller(position("W1N1", 10, 10), 2);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        world.add_source(position("W1N1", 5, 40));
+        world.add_source(position("W1N1", 40, 5));
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        let colony = colony.as_ref().borrow();
+        assert_eq!(colony.rcl, 2);
+        // and the transport, upgrade, work and defense overlords
+        assert_eq!(colony.overlords.len(), 6);
+        assert_eq!(colony.get_cache().overlords.len(), 6);
+    }
+
+    #[test]
+    fn spawn_queue_survives_in_cache() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        // a lone drone per source and the upgraders at RCL 1
+        world.add_controller(position("W1N1", 10, 10), 1);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        world.add_source(position("W1N1", 5, 40));
+        // the spawn is busy, so the request stays queued
+        world
+            .state
+            .borrow_mut()
+            .rooms
+            .values_mut()
+            .for_each(|room| room.spawns[0].spawning = true);
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        colony.as_ref().borrow().run();
+        let cache = colony.as_ref().borrow().get_cache();
+        assert_eq!(cache.spawn_queue.len(), 2);
+
+        let restored =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), Some(&cache)).unwrap();
+        let restored = restored.as_ref().borrow();
+        assert_eq!(restored.hive.as_ref().borrow().hatcherys.spawn_queue().len(), 2);
+    }
+
+    #[test]
+    fn refresh_fails_when_controller_is_lost() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        world.add_controller(position("W1N1", 10, 10), 2);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        world
+            .state
+            .borrow_mut()
+            .rooms
+            .values_mut()
+            .for_each(|room| room.controller.as_mut().unwrap().my = false);
+        assert!(colony.as_ref().borrow_mut().refresh().is_err());
+    }
+
+    #[test]
+    fn orphaned_creeps_are_claimed_before_spawning() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        world.add_controller(position("W1N1", 10, 10), 1);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        world.add_source(position("W1N1", 5, 40));
+        // its source is gone
+        world.add_creep(
+            "orphan",
+            position("W1N1", 20, 20),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: "mine-W1N1-gone".to_string(),
+                role: ROLE_DRONE.to_string(),
+                colony: String::new(),
+                state: None,
+                task: None,
+            },
+        );
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        colony.as_ref().borrow_mut().refresh().unwrap();
+        assert_eq!(
+            colony.as_ref().borrow().hive.as_ref().borrow().hatcherys.idle_creeps().len(),
+            1
+        );
+        colony.as_ref().borrow().run();
+        assert!(colony
+            .as_ref()
+            .borrow()
+            .hive
+            .as_ref()
+            .borrow()
+            .hatcherys
+            .spawn_queue()
+            .is_empty());
+
+        colony.as_ref().borrow_mut().refresh().unwrap();
+        let memory = world.creep("orphan").unwrap().memory.unwrap();
+        assert!(memory.overlord.starts_with("mine-W1N1"));
+        assert_eq!(memory.colony, "W1N1");
+        let colony = colony.as_ref().borrow();
+        assert!(colony.hive.as_ref().borrow().hatcherys.idle_creeps().is_empty());
+        assert!(colony.get_cache().overlords[&memory.overlord].cache.contains("orphan"));
+    }
+
+    #[test]
+    fn low_bucket_leaves_out_low_priority_overlords() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        world.add_controller(position("W1N1", 10, 10), 2);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        world.add_source(position("W1N1", 5, 40));
+        world.state.borrow_mut().time = 1;
+        world.state.borrow_mut().cpu_bucket = Some(0);
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        colony.as_ref().borrow().run();
+        profiler::finish_tick(world.as_ref());
+        let report = profiler::report(world.as_ref());
+        assert!(report.contains("defense-W1N1"));
+        assert!(report.contains("W1N1/hatchery"));
+        assert!(!report.contains("mine-W1N1"));
+        assert!(!report.contains("upgrade-W1N1"));
+    }
+
+    #[test]
+    fn low_bucket_still_saves_the_controller_from_downgrading() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        world.add_controller(position("W1N1", 10, 10), 2);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        world.add_source(position("W1N1", 5, 40));
+        world.state.borrow_mut().time = 1;
+        world.state.borrow_mut().cpu_bucket = Some(0);
+        world
+            .state
+            .borrow_mut()
+            .rooms
+            .values_mut()
+            .for_each(|room| room.controller.as_mut().unwrap().ticks_to_downgrade = 1000);
+
+        let colony =
+            Colony::new_from_room_name(world.clone(), "W1N1".to_string(), None).unwrap();
+        colony.as_ref().borrow().run();
+        let spawned: Vec<String> = world
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                MockAction::SpawnCreep { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(spawned.len(), 1);
+        assert!(spawned[0].contains(ROLE_UPGRADER));
+
+        // the upgrader goes to work, the bucket still empty
+        world.state.borrow_mut().time = 2;
+        {
+            let mut state = world.state.borrow_mut();
+            let creep = state.creeps.get_mut(&spawned[0]).unwrap();
+            creep.spawning = false;
+            creep.pos = position("W1N1", 11, 11);
+        }
+        world.set_creep_energy(&spawned[0], 50);
+        colony.as_ref().borrow_mut().refresh().unwrap();
+        colony.as_ref().borrow().run();
+        assert!(world.take_actions().contains(&MockAction::UpgradeController {
+            creep: spawned[0].clone(),
+            target: world.controller(RoomName::new("W1N1").unwrap()).unwrap().id,
+        }));
+    }
 }
@@ -1,4 +1,5 @@
 // priority
 pub const TOP_MOST_PRIORITY: u32 = 100;
 pub const HIGH_PRIORITY: u32 = 10;
-pub const DEFAULT_PRIORITY: u32 = 0;
+pub const DEFAULT_PRIORITY: u32 = 1;
+pub const LOW_PRIORITY: u32 = 0;
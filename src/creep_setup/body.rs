@@ -0,0 +1,225 @@
+use screeps::{constants::MAX_CREEP_SIZE, Part};
+
+use crate::error::SwarmError;
+
+// which energy figure of the room a body is scaled to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnergyCap {
+    // the room when all spawns and extensions are full, bodies grow with RCL
+    Capacity,
+    // what the room holds right now, for bootstrapping and emergencies
+    Available,
+}
+
+// describes how to grow a body: `prefix`, then `pattern` repeated as often as
+// the energy, `max_repeats` and the 50 part limit allow, then `suffix`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodySetup {
+    pub prefix: Vec<Part>,
+    pub pattern: Vec<Part>,
+    pub suffix: Vec<Part>,
+    pub max_repeats: u32,
+    // group the parts, see `sort_body`. otherwise the parts keep the order above
+    pub sort: bool,
+    pub energy_cap: EnergyCap,
+}
+
+impl Default for BodySetup {
+    fn default() -> Self {
+        BodySetup {
+            prefix: Vec::new(),
+            pattern: Vec::new(),
+            suffix: Vec::new(),
+            max_repeats: MAX_CREEP_SIZE,
+            sort: true,
+            energy_cap: EnergyCap::Capacity,
+        }
+    }
+}
+
+impl BodySetup {
+    pub fn new(pattern: Vec<Part>) -> Self {
+        BodySetup {
+            pattern,
+            ..Default::default()
+        }
+    }
+
+    pub fn energy(&self, energy_capacity_available: u32, energy_available: u32) -> u32 {
+        match self.energy_cap {
+            EnergyCap::Capacity => energy_capacity_available,
+            EnergyCap::Available => energy_available,
+        }
+    }
+
+    // the biggest body `energy` pays for. none if the room can't afford the
+    // prefix, the suffix and at least one repeat of the pattern.
+    pub fn generate(&self, energy: u32) -> Option<Vec<Part>> {
+        let fixed_cost = body_cost(&self.prefix) + body_cost(&self.suffix);
+        let fixed_size = (self.prefix.len() + self.suffix.len()) as u32;
+        if fixed_cost > energy || fixed_size > MAX_CREEP_SIZE {
+            return None;
+        }
+
+        let repeats = if self.pattern.is_empty() {
+            0
+        } else {
+            let by_energy = (energy - fixed_cost) / body_cost(&self.pattern).max(1);
+            let by_size = (MAX_CREEP_SIZE - fixed_size) / self.pattern.len() as u32;
+            let repeats = by_energy.min(by_size).min(self.max_repeats);
+            if repeats == 0 {
+                return None;
+            }
+            repeats
+        };
+
+        let mut body = self.prefix.clone();
+        for _ in 0..repeats {
+            body.extend_from_slice(&self.pattern);
+        }
+        body.extend_from_slice(&self.suffix);
+        if body.is_empty() {
+            return None;
+        }
+        if self.sort {
+            sort_body(&mut body);
+        }
+        Some(body)
+    }
+}
+
+pub fn body_cost(body: &[Part]) -> u32 {
+    body.iter().map(|part| part.cost()).sum()
+}
+
+// MOVE parts per other part. 1.0 moves at full speed on plain terrain, 0.5 on roads.
+pub fn move_ratio(body: &[Part]) -> f32 {
+    let moves = body.iter().filter(|part| **part == Part::Move).count();
+    let others = body.len() - moves;
+    if others == 0 {
+        return f32::INFINITY;
+    }
+    moves as f32 / others as f32
+}
+
+// tough parts soak up damage first, heal parts are lost last.
+pub fn sort_body(body: &mut [Part]) {
+    body.sort_by_key(|part| match part {
+        Part::Tough => 0,
+        Part::Work => 1,
+        Part::Carry => 2,
+        Part::Claim => 3,
+        Part::Attack => 4,
+        Part::RangedAttack => 5,
+        Part::Move => 6,
+        Part::Heal => 7,
+        _ => 8,
+    });
+}
+
+// checks a body the game would accept, `energy` pays for and that moves at
+// least at `min_move_ratio`.
+pub fn validate_body(body: &[Part], energy: u32, min_move_ratio: f32) -> Result<(), SwarmError> {
+    if body.is_empty() || body.len() as u32 > MAX_CREEP_SIZE {
+        return Err(SwarmError::InternalAssertionFailed(format!(
+            "body has {} parts, must have 1 to {}",
+            body.len(),
+            MAX_CREEP_SIZE
+        )));
+    }
+    let cost = body_cost(body);
+    if cost > energy {
+        return Err(SwarmError::InternalAssertionFailed(format!(
+            "body costs {}, only {} available",
+            cost, energy
+        )));
+    }
+    let ratio = move_ratio(body);
+    if ratio < min_move_ratio {
+        return Err(SwarmError::InternalAssertionFailed(format!(
+            "body move ratio {:.2} below {:.2}",
+            ratio, min_move_ratio
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_pattern_as_energy_allows() {
+        let setup = BodySetup::new(vec![Part::Work, Part::Carry, Part::Move]);
+        assert_eq!(setup.generate(199), None);
+        assert_eq!(setup.generate(200).unwrap().len(), 3);
+        assert_eq!(
+            setup.generate(650).unwrap(),
+            vec![
+                Part::Work,
+                Part::Work,
+                Part::Work,
+                Part::Carry,
+                Part::Carry,
+                Part::Carry,
+                Part::Move,
+                Part::Move,
+                Part::Move
+            ]
+        );
+    }
+
+    #[test]
+    fn respects_max_repeats_and_size_limit() {
+        let mut setup = BodySetup::new(vec![Part::Carry, Part::Move]);
+        setup.max_repeats = 2;
+        assert_eq!(setup.generate(10_000).unwrap().len(), 4);
+
+        setup.max_repeats = MAX_CREEP_SIZE;
+        assert_eq!(setup.generate(10_000).unwrap().len(), 50);
+    }
+
+    #[test]
+    fn adds_prefix_and_suffix() {
+        let setup = BodySetup {
+            prefix: vec![Part::Carry],
+            pattern: vec![Part::Work],
+            suffix: vec![Part::Move],
+            sort: false,
+            ..Default::default()
+        };
+        assert_eq!(setup.generate(99), None);
+        assert_eq!(
+            setup.generate(300).unwrap(),
+            vec![Part::Carry, Part::Work, Part::Work, Part::Move]
+        );
+    }
+
+    #[test]
+    fn picks_energy_by_cap() {
+        let mut setup = BodySetup::new(vec![Part::Work]);
+        assert_eq!(setup.energy(800, 300), 800);
+        setup.energy_cap = EnergyCap::Available;
+        assert_eq!(setup.energy(800, 300), 300);
+    }
+
+    #[test]
+    fn sorts_tough_first_heal_last() {
+        let mut body = vec![Part::Heal, Part::Move, Part::Attack, Part::Tough];
+        sort_body(&mut body);
+        assert_eq!(body, vec![Part::Tough, Part::Attack, Part::Move, Part::Heal]);
+    }
+
+    #[test]
+    fn validates_cost_size_and_move_ratio() {
+        let body = vec![Part::Work, Part::Work, Part::Carry, Part::Move];
+        assert_eq!(body_cost(&body), 300);
+        assert!((move_ratio(&body) - 1.0 / 3.0).abs() < f32::EPSILON);
+
+        assert!(validate_body(&body, 300, 0.3).is_ok());
+        assert!(validate_body(&body, 299, 0.3).is_err());
+        assert!(validate_body(&body, 300, 0.5).is_err());
+        assert!(validate_body(&[], 300, 0.0).is_err());
+        assert!(validate_body(&[Part::Move; 51], 10_000, 0.0).is_err());
+    }
+}
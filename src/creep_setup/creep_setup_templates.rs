@@ -1,14 +1,286 @@
 use screeps::Part;
 
-use super::CreepSetup;
+use super::{BodySetup, CreepTemplate};
 
+pub const ROLE_DRONE: &str = "drone";
+pub const ROLE_MINER: &str = "miner";
+pub const ROLE_HAULER: &str = "hauler";
+pub const ROLE_UPGRADER: &str = "upgrader";
+pub const ROLE_WORKER: &str = "worker";
+pub const ROLE_QUEEN: &str = "queen";
+pub const ROLE_MANAGER: &str = "manager";
+pub const ROLE_SCOUT: &str = "scout";
+pub const ROLE_CLAIMER: &str = "claimer";
+pub const ROLE_RESERVER: &str = "reserver";
+pub const ROLE_DEFENDER: &str = "defender";
+pub const ROLE_HEALER: &str = "healer";
+pub const ROLE_REMOTE_MINER: &str = "remote_miner";
+
+// bodies for every role. the bodies grow with the energy capacity of the room,
+// the `rcl` arguments cap roles whose useful size depends on the level.
 pub struct CreepSetupTemplate {}
 
+fn template(role: &str, body: BodySetup) -> CreepTemplate {
+    CreepTemplate {
+        role: role.to_string(),
+        body,
+    }
+}
+
 impl CreepSetupTemplate {
-    pub fn drone() -> CreepSetup {
-        CreepSetup {
-            role: "drone".to_string(),
-            pattern: vec![Part::Work, Part::Work, Part::Move, Part::Carry],
+    // does everything a bit, for a colony that has nothing else yet
+    pub fn drone() -> CreepTemplate {
+        template(
+            ROLE_DRONE,
+            BodySetup {
+                pattern: vec![Part::Work, Part::Work, Part::Carry, Part::Move],
+                max_repeats: 3,
+                ..Default::default()
+            },
+        )
+    }
+
+    // sits on a source, 6 WORK drain a source before it regenerates. the CARRY
+    // part lets it fill a link or repair its container.
+    pub fn static_miner() -> CreepTemplate {
+        template(
+            ROLE_MINER,
+            BodySetup {
+                prefix: vec![Part::Carry],
+                pattern: vec![Part::Work, Part::Work, Part::Move],
+                max_repeats: 3,
+                ..Default::default()
+            },
+        )
+    }
+
+    // travels to a source in another room, so it moves at full speed off road
+    pub fn remote_miner() -> CreepTemplate {
+        template(
+            ROLE_REMOTE_MINER,
+            BodySetup {
+                prefix: vec![Part::Carry, Part::Move],
+                pattern: vec![Part::Work, Part::Move],
+                max_repeats: 6,
+                ..Default::default()
+            },
+        )
+    }
+
+    // carries energy around, full speed on roads. small rooms have no roads yet.
+    pub fn hauler(rcl: u8) -> CreepTemplate {
+        template(
+            ROLE_HAULER,
+            BodySetup {
+                pattern: vec![Part::Carry, Part::Carry, Part::Move],
+                max_repeats: if rcl < 4 { 5 } else { 16 },
+                ..Default::default()
+            },
+        )
+    }
+
+    // stays at the controller. at RCL 8 the controller takes at most 15 energy a tick.
+    pub fn upgrader(rcl: u8) -> CreepTemplate {
+        let body = if rcl >= 8 {
+            BodySetup {
+                prefix: vec![Part::Carry, Part::Move],
+                pattern: vec![Part::Work, Part::Work, Part::Work, Part::Move],
+                max_repeats: 5,
+                ..Default::default()
+            }
+        } else {
+            BodySetup {
+                prefix: vec![Part::Carry],
+                pattern: vec![Part::Work, Part::Work, Part::Move],
+                max_repeats: if rcl < 4 { 4 } else { 12 },
+                ..Default::default()
+            }
+        };
+        template(ROLE_UPGRADER, body)
+    }
+
+    // builds and repairs
+    pub fn worker(rcl: u8) -> CreepTemplate {
+        template(
+            ROLE_WORKER,
+            BodySetup {
+                pattern: vec![Part::Work, Part::Carry, Part::Move],
+                max_repeats: if rcl < 4 { 4 } else { 10 },
+                ..Default::default()
+            },
+        )
+    }
+
+    // fills spawns, extensions and towers
+    pub fn queen(rcl: u8) -> CreepTemplate {
+        template(
+            ROLE_QUEEN,
+            BodySetup {
+                pattern: vec![Part::Carry, Part::Carry, Part::Move],
+                max_repeats: if rcl < 7 { 4 } else { 8 },
+                ..Default::default()
+            },
+        )
+    }
+
+    // moves energy between storage, terminal and links from a fixed spot
+    pub fn manager() -> CreepTemplate {
+        template(
+            ROLE_MANAGER,
+            BodySetup {
+                prefix: vec![Part::Move],
+                pattern: vec![Part::Carry, Part::Carry],
+                max_repeats: 8,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn scout() -> CreepTemplate {
+        template(
+            ROLE_SCOUT,
+            BodySetup {
+                pattern: vec![Part::Move],
+                max_repeats: 1,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn claimer() -> CreepTemplate {
+        template(
+            ROLE_CLAIMER,
+            BodySetup {
+                pattern: vec![Part::Claim, Part::Move],
+                max_repeats: 1,
+                ..Default::default()
+            },
+        )
+    }
+
+    // two CLAIM parts keep a reservation growing
+    pub fn reserver() -> CreepTemplate {
+        template(
+            ROLE_RESERVER,
+            BodySetup {
+                pattern: vec![Part::Claim, Part::Move],
+                max_repeats: 2,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn defender() -> CreepTemplate {
+        template(
+            ROLE_DEFENDER,
+            BodySetup {
+                pattern: vec![Part::Tough, Part::Attack, Part::Move, Part::Move],
+                max_repeats: 8,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn healer() -> CreepTemplate {
+        template(
+            ROLE_HEALER,
+            BodySetup {
+                pattern: vec![Part::Heal, Part::Move],
+                max_repeats: 10,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::body::{move_ratio, validate_body},
+        world::RoomInfo,
+    };
+    use screeps::{constants::extension_energy_capacity, RoomName, StructureType};
+
+    // energy capacity of a fully built room
+    fn room_at(rcl: u8) -> RoomInfo {
+        let rcl = rcl as u32;
+        let capacity = StructureType::Spawn.controller_structures(rcl) * 300
+            + StructureType::Extension.controller_structures(rcl) * extension_energy_capacity(rcl);
+        RoomInfo {
+            name: RoomName::new("W1N1").unwrap(),
+            energy_available: capacity,
+            energy_capacity_available: capacity,
+        }
+    }
+
+    fn templates(rcl: u8) -> Vec<CreepTemplate> {
+        vec![
+            CreepSetupTemplate::drone(),
+            CreepSetupTemplate::static_miner(),
+            CreepSetupTemplate::remote_miner(),
+            CreepSetupTemplate::hauler(rcl),
+            CreepSetupTemplate::upgrader(rcl),
+            CreepSetupTemplate::worker(rcl),
+            CreepSetupTemplate::queen(rcl),
+            CreepSetupTemplate::manager(),
+            CreepSetupTemplate::scout(),
+            CreepSetupTemplate::claimer(),
+            CreepSetupTemplate::reserver(),
+            CreepSetupTemplate::defender(),
+            CreepSetupTemplate::healer(),
+        ]
+    }
+
+    #[test]
+    fn every_template_builds_a_valid_body_at_rcl_4() {
+        let room = room_at(4);
+        for template in templates(4) {
+            let setup = template.generate(&room).unwrap();
+            // stationary roles like the manager only need to get into place
+            assert!(
+                validate_body(&setup.body, room.energy_capacity_available, 0.05).is_ok(),
+                "{} {:?}",
+                template.role,
+                setup.body
+            );
+        }
+    }
+
+    #[test]
+    fn bodies_grow_with_rcl() {
+        let small = CreepSetupTemplate::worker(2).generate(&room_at(2)).unwrap();
+        let big = CreepSetupTemplate::worker(6).generate(&room_at(6)).unwrap();
+        assert!(big.body.len() > small.body.len());
+    }
+
+    #[test]
+    fn static_miner_has_enough_work_for_a_source() {
+        let setup = CreepSetupTemplate::static_miner().generate(&room_at(3)).unwrap();
+        let work = setup.body.iter().filter(|part| **part == Part::Work).count();
+        assert!(work >= 5);
+    }
+
+    #[test]
+    fn upgrader_at_rcl_8_is_capped_at_15_work() {
+        let setup = CreepSetupTemplate::upgrader(8).generate(&room_at(8)).unwrap();
+        let work = setup.body.iter().filter(|part| **part == Part::Work).count();
+        assert_eq!(work, 15);
+    }
+
+    #[test]
+    fn travelling_roles_move_at_full_speed() {
+        let room = room_at(5);
+        for template in [
+            CreepSetupTemplate::remote_miner(),
+            CreepSetupTemplate::scout(),
+            CreepSetupTemplate::claimer(),
+            CreepSetupTemplate::reserver(),
+            CreepSetupTemplate::defender(),
+            CreepSetupTemplate::healer(),
+        ] {
+            let setup = template.generate(&room).unwrap();
+            assert!(move_ratio(&setup.body) >= 1.0, "{}", template.role);
         }
     }
 }
@@ -1,15 +1,53 @@
 use screeps::Part;
+use serde::{Deserialize, Serialize};
 
+use crate::world::RoomInfo;
+
+pub mod body;
 pub mod creep_setup_templates;
 
-#[derive(Debug, PartialEq, Eq)]
+pub use body::{BodySetup, EnergyCap};
+
+// a concrete body for a role, ready to be spawned
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CreepSetup {
     pub role: String,
-    pub pattern: Vec<Part>,
+    pub body: Vec<Part>,
 }
 
 impl CreepSetup {
+    pub fn new(role: &str, body: Vec<Part>) -> Self {
+        CreepSetup {
+            role: role.to_string(),
+            body,
+        }
+    }
+
     pub fn spawn_cost(&self) -> u32 {
-        self.pattern.iter().map(|part| Part::cost(*part)).sum()
+        body::body_cost(&self.body)
+    }
+}
+
+// a role and how its body grows with the energy of the room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreepTemplate {
+    pub role: String,
+    pub body: BodySetup,
+}
+
+impl CreepTemplate {
+    // none if the room can't afford even the smallest body
+    pub fn generate(&self, room: &RoomInfo) -> Option<CreepSetup> {
+        let energy = self
+            .body
+            .energy(room.energy_capacity_available, room.energy_available);
+        self.body
+            .generate(energy)
+            .map(|body| CreepSetup::new(&self.role, body))
+    }
+
+    pub fn with_energy_cap(mut self, energy_cap: EnergyCap) -> Self {
+        self.body.energy_cap = energy_cap;
+        self
     }
 }
@@ -1,130 +1,630 @@
-use std::{cell::RefCell, cmp::Ordering, collections::BinaryHeap, rc::Rc};
+use std::rc::Rc;
 
 use crate::{
-    creep_setup::CreepSetup, error::SwarmError, util::cast_room_object_into, zerg::CreepMemory,
+    creep_setup::{
+        creep_setup_templates::{ROLE_DRONE, ROLE_HAULER, ROLE_QUEEN},
+        CreepSetup, CreepTemplate, EnergyCap,
+    },
+    error::SwarmError,
+    world::{CreepInfo, SpawnInfo, World},
+    zerg::CreepMemory,
 };
-use log::{debug, warn};
-use screeps::{
-    find::{Find, RoomObject},
-    HasTypedId, Room, SpawnOptions, StructureSpawn, game,
-};
-use web_sys::console::warn;
-
-pub struct Hatchery {
-    spawns: Vec<StructureSpawn>,
-    spawn_queue: BinaryHeap<SpawnRequests>,
-    pub room: Room,
-}
+use log::{debug, info, warn};
+use screeps::{ErrorCode, Position, RoomName};
 
-#[derive(Debug, Eq, PartialEq)]
-struct SpawnRequests {
-    priority: u32,
-    setup: CreepSetup,
-    overlord: String,
-    colony: String,
-}
-
-impl Ord for SpawnRequests {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.priority.cmp(&other.priority)
-    }
-}
+use super::spawn_queue::{SpawnQueue, SpawnRequest};
 
-impl PartialOrd for SpawnRequests {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+pub struct Hatchery {
+    world: Rc<dyn World>,
+    spawns: Vec<SpawnInfo>,
+    spawn_queue: SpawnQueue,
+    pub room_name: RoomName,
+    // (overlord, creep name) of creeps spawned or claimed since the last refresh
+    spawned: Vec<(String, String)>,
+    // creeps of the colony no overlord owns, see `set_idle_creeps`
+    idle_creeps: Vec<CreepInfo>,
 }
 
 impl Hatchery {
-    pub fn new(room: &Room) -> Result<Self, SwarmError> {
-        let spawns = room.find(RoomObject::MySpawns, None);
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Result<Self, SwarmError> {
+        let spawns = world.spawns(room_name);
 
         if spawns.is_empty() {
-            warn!("Hatchery::new. failed to find any spawn. {}", room.name());
+            warn!("Hatchery::new. failed to find any spawn. {}", room_name);
             return Err(SwarmError::InternalAssertionFailed(
                 "no valid spawn".to_string(),
             ));
         }
 
         Ok(Hatchery {
-            spawns: spawns
-                .into_iter()
-                .map(|spawn| cast_room_object_into::<StructureSpawn>(spawn))
-                .collect(),
-            spawn_queue: BinaryHeap::new(),
-            // todo(sheep): maybe use rc?
-            room: room.clone(),
+            world,
+            spawns,
+            spawn_queue: SpawnQueue::new(),
+            room_name,
+            spawned: Vec::new(),
+            idle_creeps: Vec::new(),
         })
     }
 
-    pub fn request_for_spawn(self: &mut Self, setup: CreepSetup, overlord: String, priority: u32) {
-        if self.room.energy_available() < setup.spawn_cost() {
-            debug!("colony:request_for_spawn: ignore spawn request due to lack of energy avail: {}, need: {}.", self.room.energy_available(), setup.spawn_cost());
-            return;
+    // requests persisted by `get_cache`, restored after a global reset
+    pub fn restore_queue(&mut self, requests: Vec<SpawnRequest>) {
+        self.spawn_queue = SpawnQueue::from_requests(requests);
+    }
+
+    pub fn refresh(&mut self) -> Result<(), SwarmError> {
+        self.spawn_queue.drop_expired(self.world.time());
+
+        self.spawns = self.world.spawns(self.room_name);
+        if self.spawns.is_empty() {
+            warn!("Hatchery::refresh. failed to find any spawn. {}", self.room_name);
+            return Err(SwarmError::InternalAssertionFailed(
+                "no valid spawn".to_string(),
+            ));
         }
+        Ok(())
+    }
 
-        self.spawn_queue.push(SpawnRequests {
-            priority: priority,
-            setup: setup,
-            overlord: overlord,
-            colony: self.room.name().to_string(),
-        })
+    pub fn take_spawned(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.spawned)
     }
 
-    pub fn run(&mut self) {
-        for spawn in &self.spawns {
-            Self::run_spawn(&self.room, &mut self.spawn_queue, spawn);
-        }
+    // the colony hands over the creeps no overlord owns every tick, requests
+    // claim them by role before anything is spawned
+    pub fn set_idle_creeps(&mut self, creeps: Vec<CreepInfo>) {
+        self.idle_creeps = creeps;
+    }
+
+    pub fn idle_creeps(&self) -> &[CreepInfo] {
+        &self.idle_creeps
+    }
+
+    pub fn spawn_queue(&self) -> &SpawnQueue {
+        &self.spawn_queue
+    }
+
+    pub fn get_cache(&self) -> Vec<SpawnRequest> {
+        self.spawn_queue.requests().to_vec()
+    }
+
+    fn energy_available(&self) -> u32 {
+        self.world
+            .room(self.room_name)
+            .map(|room| room.energy_available)
+            .unwrap_or(0)
+    }
+
+    fn energy_capacity_available(&self) -> u32 {
+        self.world
+            .room(self.room_name)
+            .map(|room| room.energy_capacity_available)
+            .unwrap_or(0)
     }
 
-    fn run_spawn(room: &Room, binary_heap: &mut BinaryHeap<SpawnRequests>, spawn: &StructureSpawn) {
-        if spawn.spawning().is_some() {
+    // overlords issue their requests every tick while they still need the creep,
+    // a request that is issued again only refreshes the queued one. `site` is
+    // where the creep is going to work.
+    pub fn request_for_spawn(
+        &mut self,
+        setup: CreepSetup,
+        overlord: String,
+        priority: u32,
+        site: Option<Position>,
+    ) {
+        if setup.body.is_empty() {
+            warn!("ignore spawn request of {} with empty body", overlord);
             return;
         }
-
-        if binary_heap.is_empty() {
+        let energy_capacity = self.energy_capacity_available();
+        if energy_capacity < setup.spawn_cost() {
+            debug!("colony:request_for_spawn: ignore spawn request the room can never afford. capacity: {}, need: {}.", energy_capacity, setup.spawn_cost());
             return;
         }
 
-        let spawn_request = binary_heap.peek();
-        if spawn_request.is_none() {
+        let time = self.world.time();
+        self.spawn_queue.push(SpawnRequest {
+            priority,
+            setup,
+            overlord,
+            colony: self.room_name.to_string(),
+            site,
+            enqueued_at: time,
+            last_requested: time,
+        })
+    }
+
+    // requests another creep of `template` while the overlord has fewer than
+    // `count` of them alive. idle creeps of the role are claimed first, the
+    // overlord gets them with the next refresh. without any creep of the role
+    // the room may not refill, so the first one is scaled to the energy the
+    // room holds now.
+    pub fn request_creeps(
+        &mut self,
+        template: CreepTemplate,
+        count: usize,
+        mut alive: usize,
+        overlord: String,
+        priority: u32,
+        site: Option<Position>,
+    ) {
+        if alive >= count {
             return;
         }
-        let spawn_request = spawn_request.unwrap();
-
-        if room.energy_available() < spawn_request.setup.spawn_cost() {
-            debug!(
-                "failed to spawn creep due to lack of energy. {:?} {:?}",
-                spawn_request,
-                room.energy_available()
-            );
+        while alive < count && self.claim_idle(&template.role, &overlord, site) {
+            alive += 1;
+        }
+        if alive >= count {
+            self.spawn_queue.remove(&overlord, &template.role);
             return;
         }
+        let room = match self.world.room(self.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        let template = if alive == 0 {
+            template.with_energy_cap(EnergyCap::Available)
+        } else {
+            template
+        };
+        match template.generate(&room) {
+            Some(setup) => self.request_for_spawn(setup, overlord, priority, site),
+            None => debug!("can't afford a {} yet. {}", template.role, overlord),
+        }
+    }
+
+    // hands the idle creep of `role` closest to `site` to the overlord
+    fn claim_idle(&mut self, role: &str, overlord: &str, site: Option<Position>) -> bool {
+        let index = self
+            .idle_creeps
+            .iter()
+            .enumerate()
+            .filter(|(_, creep)| creep.memory.as_ref().is_some_and(|memory| memory.role == role))
+            .min_by_key(|(_, creep)| (site.map(|site| creep.pos.get_range_to(site)), creep.name.clone()))
+            .map(|(index, _)| index);
+        let creep = match index {
+            Some(index) => self.idle_creeps.remove(index),
+            None => return false,
+        };
+        let mut memory = match creep.memory {
+            Some(memory) => memory,
+            None => return false,
+        };
+        memory.overlord = overlord.to_string();
+        memory.colony = self.room_name.to_string();
+        memory.state = None;
+        memory.task = None;
+        self.world.set_creep_memory(&creep.name, &memory);
+        info!("{} claimed idle {} {}", overlord, role, creep.name);
+        self.spawned.push((overlord.to_string(), creep.name));
+        true
+    }
+
+    // hands out requests in queue order, each idle spawn takes at most one. the
+    // top request reserves the energy of the room, nothing below it is spawned
+    // until it is affordable. without creeps to refill the room the energy may
+    // never come, the requests below go first then.
+    pub fn run(&mut self) {
+        let mut energy_available = self.energy_available();
+        let mut idle_spawns: Vec<&SpawnInfo> =
+            self.spawns.iter().filter(|spawn| !spawn.spawning).collect();
+        let requests = self.spawn_queue.requests().to_vec();
+        let reserve = self.has_fillers();
+
+        for request in requests {
+            if idle_spawns.is_empty() {
+                return;
+            }
+            let cost = request.setup.spawn_cost();
+            if energy_available < cost {
+                debug!(
+                    "save energy for {} of {}. {}/{}",
+                    request.setup.role, request.overlord, energy_available, cost
+                );
+                if reserve {
+                    return;
+                }
+                continue;
+            }
+
+            match Self::dispatch(self.world.as_ref(), self.room_name, &request, &mut idle_spawns) {
+                SpawnOutcome::Spawned(name) => {
+                    self.spawn_queue.remove(&request.overlord, &request.setup.role);
+                    self.spawned.push((request.overlord.clone(), name));
+                    energy_available -= cost;
+                }
+                SpawnOutcome::Drop => {
+                    self.spawn_queue.remove(&request.overlord, &request.setup.role);
+                }
+                // the energy stays reserved for the request until it spawns
+                SpawnOutcome::Retry | SpawnOutcome::OutOfEnergy if reserve => return,
+                SpawnOutcome::Retry | SpawnOutcome::OutOfEnergy => {}
+            }
+        }
+    }
+
+    // creeps of the colony that bring energy to the spawns and extensions
+    fn has_fillers(&self) -> bool {
+        let colony = self.room_name.to_string();
+        self.world.creeps().into_iter().any(|creep| {
+            creep.memory.is_some_and(|memory| {
+                memory.colony == colony
+                    && matches!(memory.role.as_str(), ROLE_HAULER | ROLE_QUEEN | ROLE_DRONE)
+            })
+        })
+    }
+
+    // tries the idle spawns closest to the site of the request first. spawns that
+    // turn out to be unusable this tick are taken out of `idle_spawns`.
+    fn dispatch(
+        world: &dyn World,
+        room_name: RoomName,
+        request: &SpawnRequest,
+        idle_spawns: &mut Vec<&SpawnInfo>,
+    ) -> SpawnOutcome {
+        if let Some(site) = request.site {
+            idle_spawns.sort_by_key(|spawn| (spawn.pos.get_range_to(site), spawn.name.clone()));
+        }
 
-        let body_pattern = &spawn_request.setup.pattern;
-        let name = format!(
-            "{}-{}-{}-{}",
-            room.name().to_string(),
-            spawn_request.setup.role,
-            spawn.id().to_u128(),
-            game::time()
-        );
         let memory = CreepMemory {
-            overlord: spawn_request.overlord.clone(),
-            role: spawn_request.setup.role.clone(),
+            overlord: request.overlord.clone(),
+            role: request.setup.role.clone(),
+            colony: room_name.to_string(),
             state: None,
+            task: None,
         };
-        let spawn_opts = SpawnOptions::new().memory(memory.into_value());
-
-        let result = spawn.spawn_creep_with_options(&body_pattern, &name, &spawn_opts);
-        if result.is_err() {
-            warn!(
-                "spawn creep failed. room name: {}, result: {:?}, spawn request: {:?}",
-                room.name().to_string(),
-                result.unwrap_err(),
-                spawn_request
+        let mut index = 0;
+        while index < idle_spawns.len() {
+            let spawn = idle_spawns[index];
+            let name = format!(
+                "{}-{}-{}-{}",
+                room_name,
+                request.setup.role,
+                u128::from(spawn.id),
+                world.time()
             );
+
+            match world.spawn_creep(spawn.id, &request.setup.body, &name, &memory) {
+                Ok(()) => {
+                    idle_spawns.remove(index);
+                    return SpawnOutcome::Spawned(name);
+                }
+                // this spawn can't be used this tick, another one may do
+                Err(ErrorCode::Busy) | Err(ErrorCode::RclNotEnough) | Err(ErrorCode::NotOwner) => {
+                    debug!("spawn {} unusable this tick, try next one", spawn.name);
+                    idle_spawns.remove(index);
+                }
+                // names carry the spawn id, another spawn gets another name
+                Err(ErrorCode::NameExists) => index += 1,
+                Err(ErrorCode::NotEnough) => return SpawnOutcome::OutOfEnergy,
+                // the request itself is broken, it will never succeed
+                Err(ErrorCode::InvalidArgs) => {
+                    warn!("drop invalid spawn request. room name: {}, spawn request: {:?}", room_name, request);
+                    return SpawnOutcome::Drop;
+                }
+                Err(e) => {
+                    warn!(
+                        "spawn creep failed. room name: {}, result: {:?}, spawn request: {:?}",
+                        room_name, e, request
+                    );
+                    return SpawnOutcome::Retry;
+                }
+            }
+        }
+        SpawnOutcome::Retry
+    }
+}
+
+enum SpawnOutcome {
+    Spawned(String),
+    // keep the request, it is tried again next tick
+    Retry,
+    Drop,
+    OutOfEnergy,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::{DEFAULT_PRIORITY, HIGH_PRIORITY},
+        creep_setup::creep_setup_templates::CreepSetupTemplate,
+        world::mock::{position, MockAction, MockWorld},
+    };
+    use screeps::{Part, RawObjectId};
+
+    fn drone() -> CreepSetup {
+        CreepSetup::new("drone", vec![Part::Work, Part::Work, Part::Move, Part::Carry])
+    }
+
+    fn hatchery_with_energy(energy: u32) -> (Rc<MockWorld>, Hatchery) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", energy, 300);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        let hatchery = Hatchery::new(world.clone(), RoomName::new("W1N1").unwrap()).unwrap();
+        (world, hatchery)
+    }
+
+    #[test]
+    fn spawns_highest_priority_request() {
+        let (world, mut hatchery) = hatchery_with_energy(300);
+        let mut low = drone();
+        low.role = "low".to_string();
+        hatchery.request_for_spawn(low, "low-overlord".to_string(), DEFAULT_PRIORITY, None);
+        hatchery.request_for_spawn(
+            drone(),
+            "high-overlord".to_string(),
+            HIGH_PRIORITY,
+            None,
+        );
+        hatchery.run();
+
+        let actions = world.take_actions();
+        assert_eq!(actions.len(), 1);
+        match &actions[0] {
+            MockAction::SpawnCreep { name, .. } => assert!(name.contains("drone")),
+            action => panic!("unexpected action {:?}", action),
         }
+        assert_eq!(
+            hatchery.take_spawned(),
+            vec![("high-overlord".to_string(), "W1N1-drone-1-0".to_string())]
+        );
+    }
+
+    fn set_energy(world: &MockWorld, energy: u32) {
+        world
+            .state
+            .borrow_mut()
+            .rooms
+            .values_mut()
+            .for_each(|room| room.info.energy_available = energy);
+    }
+
+    #[test]
+    fn keeps_request_until_energy_is_saved() {
+        let (world, mut hatchery) = hatchery_with_energy(100);
+        hatchery.request_for_spawn(
+            drone(),
+            "overlord".to_string(),
+            DEFAULT_PRIORITY,
+            None,
+        );
+        hatchery.run();
+        assert!(world.take_actions().is_empty());
+
+        set_energy(&world, 300);
+        hatchery.refresh().unwrap();
+        hatchery.run();
+        assert_eq!(world.take_actions().len(), 1);
+        assert!(hatchery.spawn_queue().is_empty());
+    }
+
+    // a hauler refills the room, requests can wait for the energy
+    fn add_filler(world: &MockWorld) {
+        world.add_creep(
+            "hauler",
+            position("W1N1", 10, 10),
+            &[Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: "transport-W1N1".to_string(),
+                role: ROLE_HAULER.to_string(),
+                colony: "W1N1".to_string(),
+                state: None,
+                task: None,
+            },
+        );
+    }
+
+    #[test]
+    fn top_request_reserves_energy() {
+        let (world, mut hatchery) = hatchery_with_energy(200);
+        add_filler(&world);
+        let mut cheap = drone();
+        cheap.role = "cheap".to_string();
+        cheap.body = vec![Part::Move, Part::Carry];
+        hatchery.request_for_spawn(cheap, "low-overlord".to_string(), DEFAULT_PRIORITY, None);
+        hatchery.request_for_spawn(
+            drone(),
+            "high-overlord".to_string(),
+            HIGH_PRIORITY,
+            None,
+        );
+        hatchery.run();
+
+        assert!(world.take_actions().is_empty());
+        assert_eq!(hatchery.spawn_queue().len(), 2);
+    }
+
+    #[test]
+    fn failed_top_request_keeps_its_energy() {
+        let (world, mut hatchery) = hatchery_with_energy(300);
+        let mut cheap = drone();
+        cheap.role = "cheap".to_string();
+        cheap.body = vec![Part::Move, Part::Carry];
+        hatchery.request_for_spawn(cheap, "low-overlord".to_string(), DEFAULT_PRIORITY, None);
+        hatchery.request_for_spawn(
+            drone(),
+            "high-overlord".to_string(),
+            HIGH_PRIORITY,
+            None,
+        );
+        // the name of the top request is taken, it fails this tick
+        world.add_creep(
+            "W1N1-drone-1-0",
+            position("W1N1", 10, 10),
+            &[Part::Move],
+            CreepMemory {
+                overlord: String::new(),
+                role: "drone".to_string(),
+                colony: "W1N1".to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        hatchery.run();
+
+        assert!(world.take_actions().is_empty());
+        assert_eq!(hatchery.spawn_queue().len(), 2);
+    }
+
+    #[test]
+    fn without_fillers_the_energy_is_not_reserved() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 550);
+        world.add_spawn("Spawn1", position("W1N1", 25, 25));
+        let mut hatchery = Hatchery::new(world.clone(), RoomName::new("W1N1").unwrap()).unwrap();
+        // older and sized to the full room, the spawn alone never gets there
+        let mut upgrader = drone();
+        upgrader.role = "upgrader".to_string();
+        upgrader.body = vec![
+            Part::Work,
+            Part::Work,
+            Part::Work,
+            Part::Work,
+            Part::Carry,
+            Part::Move,
+            Part::Move,
+        ];
+        hatchery.request_for_spawn(upgrader, "upgrade-W1N1".to_string(), DEFAULT_PRIORITY, None);
+        world.state.borrow_mut().time = 1;
+        let hauler = CreepSetup::new(ROLE_HAULER, vec![Part::Carry, Part::Carry, Part::Move]);
+        hatchery.request_for_spawn(hauler, "transport-W1N1".to_string(), DEFAULT_PRIORITY, None);
+        hatchery.run();
+
+        assert!(matches!(
+            world.take_actions().as_slice(),
+            [MockAction::SpawnCreep { name, .. }] if name.contains(ROLE_HAULER)
+        ));
+        assert_eq!(hatchery.spawn_queue().len(), 1);
+    }
+
+    #[test]
+    fn ignores_request_above_capacity() {
+        let (_world, mut hatchery) = hatchery_with_energy(300);
+        let mut big = drone();
+        big.body = vec![Part::Work; 10];
+        hatchery.request_for_spawn(big, "overlord".to_string(), DEFAULT_PRIORITY, None);
+
+        assert!(hatchery.spawn_queue().is_empty());
+    }
+
+    fn two_spawn_hatchery() -> (Rc<MockWorld>, Hatchery, RawObjectId, RawObjectId) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 600, 600);
+        let first = world.add_spawn("Spawn1", position("W1N1", 10, 10));
+        let second = world.add_spawn("Spawn2", position("W1N1", 40, 40));
+        let hatchery = Hatchery::new(world.clone(), RoomName::new("W1N1").unwrap()).unwrap();
+        (world, hatchery, first, second)
+    }
+
+    fn spawned_by(actions: &[MockAction]) -> Vec<(RawObjectId, String)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                MockAction::SpawnCreep { spawn, name, .. } => Some((*spawn, name.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn each_spawn_takes_a_distinct_request() {
+        let (world, mut hatchery, first, second) = two_spawn_hatchery();
+        let mut hauler = drone();
+        hauler.role = "hauler".to_string();
+        hatchery.request_for_spawn(drone(), "a".to_string(), HIGH_PRIORITY, None);
+        hatchery.request_for_spawn(hauler, "b".to_string(), DEFAULT_PRIORITY, None);
+        hatchery.run();
+
+        let spawned = spawned_by(&world.take_actions());
+        assert_eq!(spawned.len(), 2);
+        assert_ne!(spawned[0].0, spawned[1].0);
+        assert!(spawned.iter().any(|(spawn, _)| *spawn == first));
+        assert!(spawned.iter().any(|(spawn, _)| *spawn == second));
+        assert!(hatchery.spawn_queue().is_empty());
+        assert_eq!(hatchery.take_spawned().len(), 2);
+    }
+
+    #[test]
+    fn prefers_spawn_close_to_site() {
+        let (world, mut hatchery, _, second) = two_spawn_hatchery();
+        hatchery.request_for_spawn(
+            drone(),
+            "a".to_string(),
+            DEFAULT_PRIORITY,
+            Some(position("W1N1", 42, 38)),
+        );
+        hatchery.run();
+
+        assert_eq!(spawned_by(&world.take_actions())[0].0, second);
+    }
+
+    #[test]
+    fn busy_spawn_is_skipped_for_another() {
+        let (world, mut hatchery, first, second) = two_spawn_hatchery();
+        // the first spawn started spawning after the hatchery was refreshed
+        world
+            .state
+            .borrow_mut()
+            .rooms
+            .values_mut()
+            .flat_map(|room| room.spawns.iter_mut())
+            .filter(|spawn| spawn.id == first)
+            .for_each(|spawn| spawn.spawning = true);
+        hatchery.request_for_spawn(
+            drone(),
+            "a".to_string(),
+            DEFAULT_PRIORITY,
+            Some(position("W1N1", 10, 11)),
+        );
+        hatchery.run();
+
+        assert_eq!(spawned_by(&world.take_actions())[0].0, second);
+        assert!(hatchery.spawn_queue().is_empty());
+    }
+
+    #[test]
+    fn fails_without_spawn() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room("W1N1", 300, 300);
+        assert!(Hatchery::new(world, RoomName::new("W1N1").unwrap()).is_err());
+    }
+
+    fn add_idle(world: &MockWorld, name: &str, role: &str, x: u8) {
+        world.add_creep(
+            name,
+            position("W1N1", x, 10),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: String::new(),
+                role: role.to_string(),
+                colony: "W1N1".to_string(),
+                state: None,
+                task: None,
+            },
+        );
+    }
+
+    #[test]
+    fn claims_closest_idle_creep_of_the_role() {
+        let (world, mut hatchery) = hatchery_with_energy(300);
+        add_idle(&world, "far", "worker", 40);
+        add_idle(&world, "near", "worker", 12);
+        add_idle(&world, "hauler", "hauler", 10);
+        hatchery.set_idle_creeps(world.creeps());
+
+        hatchery.request_creeps(
+            CreepSetupTemplate::worker(1),
+            1,
+            0,
+            "work".to_string(),
+            DEFAULT_PRIORITY,
+            Some(position("W1N1", 10, 10)),
+        );
+        assert!(hatchery.spawn_queue().is_empty());
+        assert_eq!(hatchery.idle_creeps().len(), 2);
+        assert_eq!(
+            hatchery.take_spawned(),
+            vec![("work".to_string(), "near".to_string())]
+        );
+        assert_eq!(world.creep("near").unwrap().memory.unwrap().overlord, "work");
     }
 }
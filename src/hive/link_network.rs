@@ -0,0 +1,240 @@
+use std::rc::Rc;
+
+use log::{debug, warn};
+use screeps::{ErrorCode, Position, RawObjectId, RoomName, StructureType};
+
+use crate::world::{StructureInfo, World};
+
+// links are sorted by what they stand next to
+const SOURCE_LINK_RANGE: u32 = 2;
+const CONTROLLER_LINK_RANGE: u32 = 3;
+const STORAGE_LINK_RANGE: u32 = 2;
+
+// moves energy from the links next to sources to the controller link, whatever
+// the controller link doesn't need goes to the storage link.
+pub struct LinkNetwork {
+    world: Rc<dyn World>,
+    room_name: RoomName,
+    source_links: Vec<StructureInfo>,
+    controller_link: Option<StructureInfo>,
+    storage_link: Option<StructureInfo>,
+}
+
+impl LinkNetwork {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Self {
+        let mut network = LinkNetwork {
+            world,
+            room_name,
+            source_links: Vec::new(),
+            controller_link: None,
+            storage_link: None,
+        };
+        network.refresh();
+        network
+    }
+
+    // a link next to the controller or the storage receives, even if it also
+    // stands next to a source
+    pub fn refresh(&mut self) {
+        let structures = self.world.structures(self.room_name);
+        let controller_pos = self.world.controller(self.room_name).map(|c| c.pos);
+        let storage_pos = structures
+            .iter()
+            .find(|s| s.structure_type == StructureType::Storage && s.my)
+            .map(|s| s.pos);
+        let sources = self.world.sources(self.room_name);
+
+        self.source_links.clear();
+        self.controller_link = None;
+        self.storage_link = None;
+        for link in structures
+            .into_iter()
+            .filter(|s| s.structure_type == StructureType::Link && s.my)
+        {
+            let near = |pos: Option<Position>, range: u32| {
+                pos.map(|pos| link.pos.get_range_to(pos) <= range) == Some(true)
+            };
+            if self.controller_link.is_none() && near(controller_pos, CONTROLLER_LINK_RANGE) {
+                self.controller_link = Some(link);
+            } else if self.storage_link.is_none() && near(storage_pos, STORAGE_LINK_RANGE) {
+                self.storage_link = Some(link);
+            } else if sources
+                .iter()
+                .any(|source| link.pos.get_range_to(source.pos) <= SOURCE_LINK_RANGE)
+            {
+                self.source_links.push(link);
+            }
+        }
+    }
+
+    pub fn source_links(&self) -> &[StructureInfo] {
+        &self.source_links
+    }
+
+    pub fn controller_link(&self) -> Option<&StructureInfo> {
+        self.controller_link.as_ref()
+    }
+
+    pub fn storage_link(&self) -> Option<&StructureInfo> {
+        self.storage_link.as_ref()
+    }
+
+    // source links are only worth filling when someone takes the energy out
+    pub fn has_receiver(&self) -> bool {
+        self.controller_link.is_some() || self.storage_link.is_some()
+    }
+
+    pub fn run(&mut self) {
+        for index in 0..self.source_links.len() {
+            let link = &self.source_links[index];
+            let energy = link.store.map(|store| store.energy).unwrap_or(0);
+            let capacity = link.store.map(|store| store.capacity).unwrap_or(0);
+            // sending small amounts wastes the cooldown
+            if energy == 0 || energy < capacity / 2 {
+                continue;
+            }
+            let target = match self.pick_receiver(energy) {
+                Some(target) => target,
+                None => continue,
+            };
+            let amount = energy.min(target.store.map(|store| store.free()).unwrap_or(0));
+            let (link_id, target_id) = (link.id, target.id);
+            match self.world.transfer_energy(link_id, target_id, Some(amount)) {
+                Ok(()) => {
+                    debug!("link {} sends {} to {}", link_id, amount, target_id);
+                    self.record_transfer(index, target_id, amount);
+                }
+                // still cooling down from the last transfer
+                Err(ErrorCode::Tired) => {}
+                Err(e) => warn!("link transfer of {} failed: {:?}", self.room_name, e),
+            }
+        }
+    }
+
+    // the controller link first while it runs low, then the storage link
+    fn pick_receiver(&self, energy: u32) -> Option<StructureInfo> {
+        let free = |link: &&StructureInfo| link.store.map(|store| store.free()).unwrap_or(0);
+        let controller_link = self.controller_link.as_ref().filter(|link| free(link) > 0);
+        let storage_link = self.storage_link.as_ref().filter(|link| free(link) > 0);
+        let controller_low = controller_link
+            .and_then(|link| link.store)
+            .map(|store| store.energy < store.capacity / 2)
+            == Some(true);
+        if controller_low {
+            return controller_link.cloned();
+        }
+        storage_link
+            .or(controller_link.filter(|link| free(link) >= energy))
+            .cloned()
+    }
+
+    // keeps the stores up to date, so two source links don't overfill one receiver
+    fn record_transfer(&mut self, index: usize, target: RawObjectId, amount: u32) {
+        if let Some(store) = self.source_links[index].store.as_mut() {
+            store.energy -= amount;
+            store.used -= amount;
+        }
+        for link in self
+            .controller_link
+            .iter_mut()
+            .chain(self.storage_link.iter_mut())
+            .filter(|link| link.id == target)
+        {
+            if let Some(store) = link.store.as_mut() {
+                store.energy += amount;
+                store.used += amount;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        mock::{position, MockAction, MockWorld},
+        StoreInfo,
+    };
+
+    const ROOM: &str = "W1N1";
+
+    fn link_store(energy: u32) -> Option<StoreInfo> {
+        Some(StoreInfo {
+            energy,
+            used: energy,
+            capacity: 800,
+        })
+    }
+
+    fn setup() -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 5);
+        world.add_source(position(ROOM, 40, 40));
+        world
+    }
+
+    #[test]
+    fn classifies_links_by_neighbours() {
+        let world = setup();
+        let source_link = world.add_structure(StructureType::Link, position(ROOM, 38, 38), link_store(0));
+        let controller_link =
+            world.add_structure(StructureType::Link, position(ROOM, 12, 12), link_store(0));
+        world.add_structure(StructureType::Link, position(ROOM, 25, 25), link_store(0));
+
+        let network = LinkNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        assert_eq!(network.source_links().len(), 1);
+        assert_eq!(network.source_links()[0].id, source_link);
+        assert_eq!(network.controller_link().map(|l| l.id), Some(controller_link));
+        assert!(network.storage_link().is_none());
+        assert!(network.has_receiver());
+    }
+
+    #[test]
+    fn full_source_link_feeds_controller_link_first() {
+        let world = setup();
+        world.add_structure(StructureType::Storage, position(ROOM, 25, 25), None);
+        let source_link = world.add_structure(StructureType::Link, position(ROOM, 38, 38), link_store(800));
+        let controller_link =
+            world.add_structure(StructureType::Link, position(ROOM, 12, 12), link_store(100));
+        world.add_structure(StructureType::Link, position(ROOM, 26, 26), link_store(0));
+
+        let mut network = LinkNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.run();
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::TransferEnergy {
+                link: source_link,
+                target: controller_link,
+            }]
+        );
+    }
+
+    #[test]
+    fn surplus_goes_to_storage_link() {
+        let world = setup();
+        world.add_structure(StructureType::Storage, position(ROOM, 25, 25), None);
+        world.add_structure(StructureType::Link, position(ROOM, 38, 38), link_store(800));
+        world.add_structure(StructureType::Link, position(ROOM, 12, 12), link_store(700));
+        let storage_link =
+            world.add_structure(StructureType::Link, position(ROOM, 26, 26), link_store(0));
+
+        let mut network = LinkNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.run();
+        assert!(matches!(
+            world.take_actions().as_slice(),
+            [MockAction::TransferEnergy { target, .. }] if *target == storage_link
+        ));
+    }
+
+    #[test]
+    fn half_empty_source_link_waits() {
+        let world = setup();
+        world.add_structure(StructureType::Link, position(ROOM, 38, 38), link_store(100));
+        world.add_structure(StructureType::Link, position(ROOM, 12, 12), link_store(0));
+
+        let mut network = LinkNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.run();
+        assert!(world.take_actions().is_empty());
+    }
+}
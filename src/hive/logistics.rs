@@ -0,0 +1,395 @@
+use std::rc::Rc;
+
+use screeps::{Position, RawObjectId, ResourceType, RoomName, StructureType};
+
+use crate::{
+    constants::{DEFAULT_PRIORITY, HIGH_PRIORITY, LOW_PRIORITY},
+    world::{CreepInfo, StructureInfo, World},
+};
+
+// what a hauler has to do at the target of an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogisticsTarget {
+    // withdraw from or transfer to a structure
+    Structure(RawObjectId),
+    // pick up from the ground
+    Dropped(RawObjectId),
+    // hand over to one of our creeps, by name
+    Creep(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogisticsEntry {
+    pub target: LogisticsTarget,
+    pub pos: Position,
+    pub resource: ResourceType,
+    pub amount: u32,
+    pub priority: u32,
+}
+
+// provide and request entries of one colony, rebuilt every tick. the network
+// posts the requests of spawns, extensions and towers itself, overlords post
+// what they have to give away. the storage is the buffer: haulers empty into
+// it when nobody requests energy and take from it when nobody provides.
+pub struct LogisticsNetwork {
+    world: Rc<dyn World>,
+    room_name: RoomName,
+    provides: Vec<LogisticsEntry>,
+    requests: Vec<LogisticsEntry>,
+    buffer: Option<StructureInfo>,
+    // carry capacity the posted provides keep busy, see `add_carry_demand`
+    carry_demand: u32,
+}
+
+impl LogisticsNetwork {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Self {
+        let mut network = LogisticsNetwork {
+            world,
+            room_name,
+            provides: Vec::new(),
+            requests: Vec::new(),
+            buffer: None,
+            carry_demand: 0,
+        };
+        network.refresh();
+        network
+    }
+
+    pub fn refresh(&mut self) {
+        self.provides.clear();
+        self.requests.clear();
+        self.carry_demand = 0;
+        self.buffer = None;
+        for structure in self.world.structures(self.room_name) {
+            if !structure.my {
+                continue;
+            }
+            let store = match structure.store {
+                Some(store) => store,
+                None => continue,
+            };
+            let priority = match structure.structure_type {
+                StructureType::Spawn | StructureType::Extension => HIGH_PRIORITY,
+                StructureType::Tower => DEFAULT_PRIORITY,
+                StructureType::Storage => {
+                    self.buffer = Some(structure);
+                    continue;
+                }
+                _ => continue,
+            };
+            if store.free() > 0 {
+                self.requests.push(LogisticsEntry {
+                    target: LogisticsTarget::Structure(structure.id),
+                    pos: structure.pos,
+                    resource: ResourceType::Energy,
+                    amount: store.free(),
+                    priority,
+                });
+            }
+        }
+    }
+
+    pub fn provide(&mut self, entry: LogisticsEntry) {
+        self.provides.push(entry);
+    }
+
+    pub fn request(&mut self, entry: LogisticsEntry) {
+        self.requests.push(entry);
+    }
+
+    // fallback requests of creeps that work with energy, the haulers feed them
+    // once the spawns, extensions and towers are full. with a storage the
+    // haulers empty into it instead.
+    pub fn request_for_creeps(&mut self, creeps: &[CreepInfo]) {
+        if self.buffer.is_some() {
+            return;
+        }
+        for creep in creeps
+            .iter()
+            .filter(|creep| !creep.spawning && creep.store.free() > 0)
+        {
+            self.requests.push(LogisticsEntry {
+                target: LogisticsTarget::Creep(creep.name.clone()),
+                pos: creep.pos,
+                resource: ResourceType::Energy,
+                amount: creep.store.free(),
+                priority: LOW_PRIORITY,
+            });
+        }
+    }
+
+    pub fn provides(&self) -> &[LogisticsEntry] {
+        &self.provides
+    }
+
+    pub fn requests(&self) -> &[LogisticsEntry] {
+        &self.requests
+    }
+
+    pub fn buffer(&self) -> Option<&StructureInfo> {
+        self.buffer.as_ref()
+    }
+
+    // overlords that post provides every tick add the carry capacity needed to
+    // keep up with them, the transport overlord sizes its haulers by it.
+    pub fn add_carry_demand(&mut self, carry: u32) {
+        self.carry_demand += carry;
+    }
+
+    pub fn carry_demand(&self) -> u32 {
+        self.carry_demand
+    }
+
+    // matches haulers to entries: the most urgent entries first, among equals
+    // the closest hauler. `delivering` haulers go to requests, `collecting` ones
+    // to provides. an entry takes haulers until their loads cover its amount.
+    pub fn assign(
+        &self,
+        collecting: &[CreepInfo],
+        delivering: &[CreepInfo],
+    ) -> Vec<(String, LogisticsEntry)> {
+        let mut requests = self.requests.clone();
+        let mut assignments = match_entries(delivering, &mut requests, |creep| creep.store.energy);
+        let mut provides = self.provides.clone();
+        let collected = match_entries(collecting, &mut provides, |creep| creep.store.free());
+
+        // left over haulers fall back on the storage
+        if let Some(buffer) = &self.buffer {
+            let store = buffer.store.unwrap_or_default();
+            let buffer_entry = |amount| LogisticsEntry {
+                target: LogisticsTarget::Structure(buffer.id),
+                pos: buffer.pos,
+                resource: ResourceType::Energy,
+                amount,
+                priority: DEFAULT_PRIORITY,
+            };
+            for creep in delivering {
+                if store.free() > 0 && !assignments.iter().any(|(name, _)| *name == creep.name) {
+                    assignments.push((creep.name.clone(), buffer_entry(store.free())));
+                }
+            }
+            // only worth it while some request is not covered by a delivery
+            let open_requests = requests.iter().any(|entry| entry.amount > 0);
+            for creep in collecting {
+                if store.energy > 0
+                    && open_requests
+                    && !collected.iter().any(|(name, _)| *name == creep.name)
+                {
+                    assignments.push((creep.name.clone(), buffer_entry(store.energy)));
+                }
+            }
+        }
+        assignments.extend(collected);
+        assignments
+    }
+}
+
+// greedy matching, `entries` are left with the amounts nobody took
+fn match_entries<F>(
+    creeps: &[CreepInfo],
+    entries: &mut [LogisticsEntry],
+    load: F,
+) -> Vec<(String, LogisticsEntry)>
+where
+    F: Fn(&CreepInfo) -> u32,
+{
+    let mut pairs: Vec<(usize, usize)> = (0..creeps.len())
+        .flat_map(|creep| (0..entries.len()).map(move |entry| (creep, entry)))
+        .collect();
+    pairs.sort_by_key(|(creep, entry)| {
+        (
+            std::cmp::Reverse(entries[*entry].priority),
+            creeps[*creep].pos.get_range_to(entries[*entry].pos),
+            *entry,
+            *creep,
+        )
+    });
+
+    let mut assignments: Vec<(String, LogisticsEntry)> = Vec::new();
+    for (creep, entry) in pairs {
+        let creep = &creeps[creep];
+        if entries[entry].amount == 0 || assignments.iter().any(|(name, _)| *name == creep.name) {
+            continue;
+        }
+        assignments.push((creep.name.clone(), entries[entry].clone()));
+        entries[entry].amount = entries[entry].amount.saturating_sub(load(creep));
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::{
+            mock::{position, MockWorld},
+            StoreInfo,
+        },
+        zerg::CreepMemory,
+    };
+    use screeps::Part;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 550);
+        world.add_controller(position(ROOM, 10, 10), 3);
+        world
+    }
+
+    fn hauler(world: &MockWorld, name: &str, x: u8, y: u8, energy: u32) -> CreepInfo {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Carry, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: "transport".to_string(),
+                role: "hauler".to_string(),
+                colony: "W1N1".to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        world.set_creep_energy(name, energy);
+        world.creep(name).unwrap()
+    }
+
+    fn store(energy: u32, capacity: u32) -> Option<StoreInfo> {
+        Some(StoreInfo {
+            energy,
+            used: energy,
+            capacity,
+        })
+    }
+
+    fn provide(id: RawObjectId, x: u8, y: u8, amount: u32) -> LogisticsEntry {
+        LogisticsEntry {
+            target: LogisticsTarget::Dropped(id),
+            pos: position(ROOM, x, y),
+            resource: ResourceType::Energy,
+            amount,
+            priority: DEFAULT_PRIORITY,
+        }
+    }
+
+    #[test]
+    fn posts_requests_of_empty_extensions() {
+        let world = setup();
+        world.add_structure(StructureType::Extension, position(ROOM, 20, 20), store(0, 50));
+        world.add_structure(StructureType::Extension, position(ROOM, 21, 20), store(50, 50));
+        world.add_structure(StructureType::Tower, position(ROOM, 22, 20), store(500, 1000));
+
+        let network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        let priorities: Vec<(u32, u32)> = network
+            .requests()
+            .iter()
+            .map(|entry| (entry.amount, entry.priority))
+            .collect();
+        assert_eq!(priorities, vec![(50, HIGH_PRIORITY), (500, DEFAULT_PRIORITY)]);
+    }
+
+    #[test]
+    fn closest_hauler_takes_the_provide() {
+        let world = setup();
+        let far = hauler(&world, "far", 5, 5, 0);
+        let near = hauler(&world, "near", 30, 30, 0);
+        let mut network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.provide(provide(RawObjectId::from(99), 32, 32, 100));
+
+        let assignments = network.assign(&[far, near], &[]);
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].0, "near");
+    }
+
+    #[test]
+    fn big_provide_takes_several_haulers() {
+        let world = setup();
+        let first = hauler(&world, "first", 30, 30, 0);
+        let second = hauler(&world, "second", 5, 5, 0);
+        let mut network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.provide(provide(RawObjectId::from(99), 32, 32, 500));
+
+        assert_eq!(network.assign(&[first, second], &[]).len(), 2);
+    }
+
+    #[test]
+    fn urgent_request_goes_first() {
+        let world = setup();
+        let extension =
+            world.add_structure(StructureType::Extension, position(ROOM, 5, 5), store(0, 50));
+        world.add_structure(StructureType::Tower, position(ROOM, 31, 31), store(0, 1000));
+        let full = hauler(&world, "full", 30, 30, 100);
+        let network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+
+        let assignments = network.assign(&[], &[full]);
+        assert_eq!(
+            assignments[0].1.target,
+            LogisticsTarget::Structure(extension)
+        );
+    }
+
+    #[test]
+    fn storage_buffers_surplus_and_covers_requests() {
+        let world = setup();
+        let storage =
+            world.add_structure(StructureType::Storage, position(ROOM, 25, 25), store(1000, 1_000_000));
+        let full = hauler(&world, "full", 30, 30, 100);
+        let empty = hauler(&world, "empty", 20, 20, 0);
+
+        // nothing requested: the full hauler stores, the empty one idles
+        let network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        let assignments = network.assign(std::slice::from_ref(&empty), &[full]);
+        assert_eq!(
+            assignments,
+            vec![(
+                "full".to_string(),
+                LogisticsEntry {
+                    target: LogisticsTarget::Structure(storage),
+                    pos: position(ROOM, 25, 25),
+                    resource: ResourceType::Energy,
+                    amount: 1_000_000 - 1000,
+                    priority: DEFAULT_PRIORITY,
+                }
+            )]
+        );
+
+        // an empty extension: the empty hauler fetches from the storage
+        world.add_structure(StructureType::Extension, position(ROOM, 5, 5), store(0, 50));
+        let network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        let assignments = network.assign(&[empty], &[]);
+        assert_eq!(assignments[0].1.target, LogisticsTarget::Structure(storage));
+    }
+
+    #[test]
+    fn feeds_creeps_only_without_a_storage() {
+        let world = setup();
+        let hungry = hauler(&world, "hungry", 12, 12, 0);
+        let fed = hauler(&world, "fed", 13, 12, 100);
+        let full = hauler(&world, "full", 30, 30, 100);
+
+        // the spawns are full: the loaded hauler feeds the hungry creep
+        let mut network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.request_for_creeps(&[hungry, fed]);
+        let assignments = network.assign(&[], &[full]);
+        assert_eq!(
+            assignments,
+            vec![(
+                "full".to_string(),
+                LogisticsEntry {
+                    target: LogisticsTarget::Creep("hungry".to_string()),
+                    pos: position(ROOM, 12, 12),
+                    resource: ResourceType::Energy,
+                    amount: 100,
+                    priority: LOW_PRIORITY,
+                }
+            )]
+        );
+
+        // with a storage the creeps fetch their own energy
+        world.add_structure(StructureType::Storage, position(ROOM, 25, 25), store(1000, 1_000_000));
+        let mut network = LogisticsNetwork::new(world.clone(), RoomName::new(ROOM).unwrap());
+        network.request_for_creeps(&[world.creep("hungry").unwrap()]);
+        assert!(network.requests().is_empty());
+    }
+}
@@ -1,27 +1,71 @@
 mod hatchery;
+mod link_network;
+mod logistics;
+mod repair_queue;
+mod safe_mode;
+mod spawn_queue;
+mod threat_assessor;
+mod tower_controller;
 
 pub use hatchery::Hatchery;
-use log::warn;
-use screeps::{
-    find::{Find, RoomObject},
-    ErrorCode, Room, StructureSpawn,
+pub use link_network::LinkNetwork;
+pub use logistics::{LogisticsEntry, LogisticsNetwork, LogisticsTarget};
+pub use repair_queue::{
+    fortification_target, RepairEntry, RepairQueue, REPAIR_CRITICAL, REPAIR_DECAY, REPAIR_FORTIFY,
 };
+pub use safe_mode::SafeModePolicy;
+pub use spawn_queue::{SpawnQueue, SpawnRequest, SPAWN_REQUEST_TIMEOUT};
+pub use threat_assessor::{HostileRole, HostileThreat, ThreatAssessor, ThreatLevel};
+pub use tower_controller::{effective_damage, healing_at, tower_power, TowerController};
+use screeps::RoomName;
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{error::SwarmError, util::cast_room_object_into};
+use crate::{error::SwarmError, profiler, world::World};
 
 pub struct Hive {
+    world: Rc<dyn World>,
+    room_name: RoomName,
     pub hatcherys: Hatchery,
+    pub links: LinkNetwork,
+    pub logistics: LogisticsNetwork,
+    pub repairs: RepairQueue,
+    pub towers: TowerController,
+    pub threats: ThreatAssessor,
+    pub safe_mode: SafeModePolicy,
 }
 
 impl Hive {
-    pub fn new(room: &Room) -> Result<Rc<RefCell<Hive>>, SwarmError> {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Result<Rc<RefCell<Hive>>, SwarmError> {
+        let towers = TowerController::new(world.clone(), room_name);
+        let mut threats = ThreatAssessor::new(room_name);
+        threats.refresh(&towers);
         Ok(Rc::new(RefCell::new(Hive {
-            hatcherys: Hatchery::new(room)?,
+            hatcherys: Hatchery::new(world.clone(), room_name)?,
+            links: LinkNetwork::new(world.clone(), room_name),
+            logistics: LogisticsNetwork::new(world.clone(), room_name),
+            repairs: RepairQueue::new(world.clone(), room_name),
+            safe_mode: SafeModePolicy::new(world.clone(), room_name),
+            world,
+            room_name,
+            towers,
+            threats,
         })))
     }
 
+    pub fn refresh(&mut self) -> Result<(), SwarmError> {
+        self.links.refresh();
+        self.logistics.refresh();
+        self.repairs.refresh();
+        self.towers.refresh();
+        self.threats.refresh(&self.towers);
+        self.hatcherys.refresh()
+    }
+
     pub fn run(&mut self) {
-      self.hatcherys.run();
+      self.safe_mode.run(&self.threats);
+      let hatchery = format!("{}/hatchery", self.room_name);
+      profiler::measure(self.world.as_ref(), &hatchery, || self.hatcherys.run());
+      self.links.run();
+      self.towers.run(&self.repairs);
     }
 }
@@ -0,0 +1,334 @@
+use std::{collections::HashSet, rc::Rc};
+
+use screeps::{Position, RawObjectId, RoomName, StructureType, REPAIR_POWER};
+
+use crate::world::{StructureInfo, World};
+
+// repair priorities, the higher the sooner
+// about to collapse, or damaged by an attack
+pub const REPAIR_CRITICAL: u32 = 3;
+// roads and containers worn down by decay
+pub const REPAIR_DECAY: u32 = 2;
+// walls and ramparts below the fortification target
+pub const REPAIR_FORTIFY: u32 = 1;
+
+// a structure is queued once it drops below this share of its target hits and
+// stays queued until it is back at the target, so repairs don't stop halfway
+const REPAIR_THRESHOLD_PERCENT: u32 = 75;
+const CRITICAL_PERCENT: u32 = 25;
+// a rampart loses 300 hits every 100 ticks, this leaves about 1500 ticks
+const RAMPART_CRITICAL_HITS: u32 = 5_000;
+
+// the hits walls and ramparts are kept at
+pub fn fortification_target(rcl: u8) -> u32 {
+    match rcl {
+        0 | 1 => 0,
+        2 => 10_000,
+        3 => 50_000,
+        4 => 100_000,
+        5 => 300_000,
+        6 => 1_000_000,
+        7 => 3_000_000,
+        _ => 10_000_000,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairEntry {
+    pub id: RawObjectId,
+    pub pos: Position,
+    pub structure_type: StructureType,
+    pub hits: u32,
+    pub target_hits: u32,
+    pub priority: u32,
+}
+
+impl RepairEntry {
+    pub fn missing_hits(&self) -> u32 {
+        self.target_hits.saturating_sub(self.hits)
+    }
+}
+
+// the structures of a colony that need repairs, the most urgent first. towers
+// take the cheap repairs, the work overlord and drones the rest.
+pub struct RepairQueue {
+    world: Rc<dyn World>,
+    room_name: RoomName,
+    entries: Vec<RepairEntry>,
+    // queued structures that are not back at their target yet
+    repairing: HashSet<RawObjectId>,
+    // structures that will be removed, they are never repaired
+    doomed: HashSet<RawObjectId>,
+    // the roads of the room plan, other roads are left to decay. every road is
+    // kept while the room has no plan.
+    wanted_roads: Option<HashSet<Position>>,
+}
+
+impl RepairQueue {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Self {
+        let mut queue = RepairQueue {
+            world,
+            room_name,
+            entries: Vec::new(),
+            repairing: HashSet::new(),
+            doomed: HashSet::new(),
+            wanted_roads: None,
+        };
+        queue.refresh();
+        queue
+    }
+
+    pub fn refresh(&mut self) {
+        let rcl = self
+            .world
+            .controller(self.room_name)
+            .map(|controller| controller.level)
+            .unwrap_or(0);
+        let structures = self.world.structures(self.room_name);
+        self.doomed
+            .retain(|id| structures.iter().any(|structure| structure.id == *id));
+
+        self.entries.clear();
+        for structure in structures {
+            if self.doomed.contains(&structure.id) {
+                self.repairing.remove(&structure.id);
+                continue;
+            }
+            match self.entry(&structure, rcl) {
+                Some(entry) => self.entries.push(entry),
+                None => {
+                    self.repairing.remove(&structure.id);
+                }
+            }
+        }
+        self.entries.sort_by_key(|entry| {
+            (
+                std::cmp::Reverse(entry.priority),
+                entry.hits as u64 * 1000 / entry.target_hits.max(1) as u64,
+                entry.id,
+            )
+        });
+    }
+
+    fn entry(&mut self, structure: &StructureInfo, rcl: u8) -> Option<RepairEntry> {
+        // leftovers of a former owner are not worth keeping
+        let unowned = matches!(
+            structure.structure_type,
+            StructureType::Road | StructureType::Container | StructureType::Wall
+        );
+        if !structure.my && !unowned {
+            return None;
+        }
+        if structure.structure_type == StructureType::Road
+            && self
+                .wanted_roads
+                .as_ref()
+                .is_some_and(|wanted| !wanted.contains(&structure.pos))
+        {
+            return None;
+        }
+        let fortification = matches!(
+            structure.structure_type,
+            StructureType::Wall | StructureType::Rampart
+        );
+        let target_hits = if fortification {
+            fortification_target(rcl).min(structure.hits_max)
+        } else {
+            structure.hits_max
+        };
+        if structure.hits >= target_hits {
+            return None;
+        }
+        if structure.hits * 100 >= target_hits * REPAIR_THRESHOLD_PERCENT
+            && !self.repairing.contains(&structure.id)
+        {
+            return None;
+        }
+        self.repairing.insert(structure.id);
+
+        let priority = match structure.structure_type {
+            StructureType::Rampart if structure.hits < RAMPART_CRITICAL_HITS => REPAIR_CRITICAL,
+            StructureType::Wall | StructureType::Rampart => REPAIR_FORTIFY,
+            StructureType::Road | StructureType::Container
+                if structure.hits * 100 < target_hits * CRITICAL_PERCENT =>
+            {
+                REPAIR_CRITICAL
+            }
+            StructureType::Road | StructureType::Container => REPAIR_DECAY,
+            // nothing else decays, so the damage comes from an attack
+            _ => REPAIR_CRITICAL,
+        };
+        Some(RepairEntry {
+            id: structure.id,
+            pos: structure.pos,
+            structure_type: structure.structure_type,
+            hits: structure.hits,
+            target_hits,
+            priority,
+        })
+    }
+
+    pub fn entries(&self) -> &[RepairEntry] {
+        &self.entries
+    }
+
+    pub fn contains(&self, id: RawObjectId) -> bool {
+        self.entries.iter().any(|entry| entry.id == id)
+    }
+
+    // the structure is going to be removed, e.g. to make room for another
+    pub fn dismantle(&mut self, id: RawObjectId) {
+        self.doomed.insert(id);
+        self.entries.retain(|entry| entry.id != id);
+    }
+
+    pub fn set_wanted_roads(&mut self, roads: HashSet<Position>) {
+        self.wanted_roads = Some(roads);
+    }
+
+    // energy a creep needs to finish all repairs
+    pub fn energy_needed(&self) -> u32 {
+        self.entries
+            .iter()
+            .map(|entry| entry.missing_hits().div_ceil(REPAIR_POWER))
+            .sum()
+    }
+
+    // hands out entries to workers: the most urgent first, the closest worker
+    // first. an entry takes workers until their `load` of hits covers it.
+    pub fn assign<'a, T, F, L>(
+        &self,
+        workers: &'a [T],
+        min_priority: u32,
+        pos: F,
+        load: L,
+    ) -> Vec<(&'a T, RepairEntry)>
+    where
+        F: Fn(&T) -> Position,
+        L: Fn(&T) -> u32,
+    {
+        let mut missing: Vec<u32> = self.entries.iter().map(|entry| entry.missing_hits()).collect();
+        let mut pairs: Vec<(usize, usize)> = (0..workers.len())
+            .flat_map(|worker| {
+                (0..self.entries.len())
+                    .filter(|entry| self.entries[*entry].priority >= min_priority)
+                    .map(move |entry| (worker, entry))
+            })
+            .collect();
+        pairs.sort_by_key(|(worker, entry)| {
+            (
+                std::cmp::Reverse(self.entries[*entry].priority),
+                pos(&workers[*worker]).get_range_to(self.entries[*entry].pos),
+                *entry,
+                *worker,
+            )
+        });
+
+        let mut assigned: Vec<bool> = vec![false; workers.len()];
+        let mut assignments = Vec::new();
+        for (worker, entry) in pairs {
+            if assigned[worker] || missing[entry] == 0 {
+                continue;
+            }
+            assigned[worker] = true;
+            missing[entry] = missing[entry].saturating_sub(load(&workers[worker]));
+            assignments.push((&workers[worker], self.entries[entry].clone()));
+        }
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::{position, MockWorld};
+    use screeps::constants::rampart_hits_max;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup(rcl: u8) -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), rcl);
+        world
+    }
+
+    fn queue(world: &Rc<MockWorld>) -> RepairQueue {
+        RepairQueue::new(world.clone(), RoomName::new(ROOM).unwrap())
+    }
+
+    #[test]
+    fn decayed_road_is_queued_until_repaired() {
+        let world = setup(3);
+        let road = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(road, 4000, 5000);
+        let mut repairs = queue(&world);
+        assert!(!repairs.contains(road));
+
+        world.set_structure_hits(road, 3000, 5000);
+        repairs.refresh();
+        assert_eq!(repairs.entries()[0].priority, REPAIR_DECAY);
+
+        // above the threshold again, but not done yet
+        world.set_structure_hits(road, 4500, 5000);
+        repairs.refresh();
+        assert!(repairs.contains(road));
+
+        world.set_structure_hits(road, 5000, 5000);
+        repairs.refresh();
+        assert!(repairs.entries().is_empty());
+    }
+
+    #[test]
+    fn ramparts_are_kept_at_the_rcl_target() {
+        let world = setup(3);
+        let rampart = world.add_structure(StructureType::Rampart, position(ROOM, 20, 20), None);
+        world.set_structure_hits(rampart, 20_000, rampart_hits_max(3));
+        let repairs = queue(&world);
+        assert_eq!(repairs.entries()[0].target_hits, fortification_target(3));
+        assert_eq!(repairs.entries()[0].priority, REPAIR_FORTIFY);
+
+        world.set_structure_hits(rampart, 1_000, rampart_hits_max(3));
+        let repairs = queue(&world);
+        assert_eq!(repairs.entries()[0].priority, REPAIR_CRITICAL);
+    }
+
+    #[test]
+    fn crumbling_container_goes_before_worn_road() {
+        let world = setup(3);
+        let road = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(road, 2000, 5000);
+        let container = world.add_structure(StructureType::Container, position(ROOM, 30, 30), None);
+        world.set_structure_hits(container, 10_000, 250_000);
+        let repairs = queue(&world);
+        let ids: Vec<RawObjectId> = repairs.entries().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![container, road]);
+    }
+
+    #[test]
+    fn doomed_structures_are_not_repaired() {
+        let world = setup(3);
+        let road = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(road, 1000, 5000);
+        let mut repairs = queue(&world);
+        repairs.dismantle(road);
+        repairs.refresh();
+        assert!(repairs.entries().is_empty());
+    }
+
+    #[test]
+    fn roads_off_the_plan_are_left_to_decay() {
+        let world = setup(3);
+        let wanted = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(wanted, 1000, 5000);
+        let unwanted = world.add_structure(StructureType::Road, position(ROOM, 30, 30), None);
+        world.set_structure_hits(unwanted, 1000, 5000);
+        let mut repairs = queue(&world);
+        assert_eq!(repairs.entries().len(), 2);
+
+        repairs.set_wanted_roads(HashSet::from([position(ROOM, 20, 20)]));
+        repairs.refresh();
+        let ids: Vec<RawObjectId> = repairs.entries().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![wanted]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::rc::Rc;
+
+use log::warn;
+use screeps::{Part, RoomName, StructureType};
+
+use crate::world::World;
+
+use super::ThreatAssessor;
+
+// a hostile this close to a spawn or the storage hurts it within a tick or two
+const DANGER_RANGE: u32 = 3;
+// claimers attack the controller from next to it
+const CONTROLLER_DANGER_RANGE: u32 = 2;
+// towers that need longer to kill a hostile don't stop it in time
+const MAX_TICKS_TO_KILL: u32 = 10;
+
+// activates safe mode when hostiles the towers can't kill close in on the
+// spawns, the storage or the controller. every activation, and every time it
+// is needed but not possible, is reported through a warning, which ends up as a
+// game notification.
+pub struct SafeModePolicy {
+    world: Rc<dyn World>,
+    room_name: RoomName,
+    // the current incident was reported already
+    reported: bool,
+}
+
+impl SafeModePolicy {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Self {
+        SafeModePolicy {
+            world,
+            room_name,
+            reported: false,
+        }
+    }
+
+    // why the colony needs safe mode now, none while it holds
+    pub fn reason(&self, threats: &ThreatAssessor) -> Option<String> {
+        let controller = self.world.controller(self.room_name)?;
+        let vital: Vec<_> = self
+            .world
+            .structures(self.room_name)
+            .into_iter()
+            .filter(|s| s.my && matches!(s.structure_type, StructureType::Spawn | StructureType::Storage))
+            .collect();
+        threats
+            .threats()
+            .iter()
+            .filter(|threat| {
+                threat
+                    .ticks_to_kill()
+                    .is_none_or(|ticks| ticks > MAX_TICKS_TO_KILL)
+            })
+            .find_map(|threat| {
+                let creep = &threat.creep;
+                if threat.damage > 0 || threat.dismantle > 0 {
+                    if let Some(structure) = vital
+                        .iter()
+                        .find(|s| s.pos.get_range_to(creep.pos) <= DANGER_RANGE)
+                    {
+                        return Some(format!(
+                            "{:?} of {} at {} next to the {:?}, towers can't kill it",
+                            threat.role, creep.owner, creep.pos, structure.structure_type
+                        ));
+                    }
+                }
+                if creep.count_parts(Part::Claim) > 0
+                    && creep.pos.get_range_to(controller.pos) <= CONTROLLER_DANGER_RANGE
+                {
+                    return Some(format!(
+                        "claimer of {} at {} attacks the controller",
+                        creep.owner, creep.pos
+                    ));
+                }
+                None
+            })
+    }
+
+    pub fn run(&mut self, threats: &ThreatAssessor) {
+        let reason = match self.reason(threats) {
+            Some(reason) => reason,
+            None => {
+                self.reported = false;
+                return;
+            }
+        };
+        let controller = match self.world.controller(self.room_name) {
+            Some(controller) if controller.my && controller.safe_mode == 0 => controller,
+            _ => return,
+        };
+        let blocked = if controller.safe_mode_available == 0 {
+            Some("no charges left".to_string())
+        } else if controller.safe_mode_cooldown > 0 {
+            Some(format!("cooldown for {} ticks", controller.safe_mode_cooldown))
+        } else {
+            None
+        };
+        if let Some(blocked) = blocked {
+            if !self.reported {
+                warn!(
+                    "{} needs safe mode, but {}: {}",
+                    self.room_name, blocked, reason
+                );
+                self.reported = true;
+            }
+            return;
+        }
+        match self.world.activate_safe_mode(controller.id) {
+            Ok(()) => warn!(
+                "activated safe mode of {}, {} charges left: {}",
+                self.room_name,
+                controller.safe_mode_available - 1,
+                reason
+            ),
+            Err(e) if !self.reported => {
+                warn!("activate safe mode of {} failed: {:?}: {}", self.room_name, e, reason);
+                self.reported = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hive::TowerController,
+        world::{
+            mock::{position, MockAction, MockWorld},
+            StoreInfo,
+        },
+    };
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 3);
+        world.add_structure(StructureType::Spawn, position(ROOM, 25, 25), None);
+        world
+    }
+
+    fn run(world: &Rc<MockWorld>) -> Vec<MockAction> {
+        let room_name = RoomName::new(ROOM).unwrap();
+        let towers = TowerController::new(world.clone(), room_name);
+        let mut threats = ThreatAssessor::new(room_name);
+        threats.refresh(&towers);
+        SafeModePolicy::new(world.clone(), room_name).run(&threats);
+        world.take_actions()
+    }
+
+    #[test]
+    fn fires_when_an_attacker_reaches_the_spawn() {
+        let world = setup();
+        world.add_hostile_creep("someone", position(ROOM, 27, 25), &[Part::Attack, Part::Move]);
+        assert_eq!(run(&world).len(), 1);
+    }
+
+    #[test]
+    fn holds_while_the_attacker_is_far_off() {
+        let world = setup();
+        world.add_hostile_creep("someone", position(ROOM, 40, 40), &[Part::Attack, Part::Move]);
+        assert!(run(&world).is_empty());
+    }
+
+    #[test]
+    fn holds_while_the_towers_kill_it_in_time() {
+        let world = setup();
+        world.add_structure(
+            StructureType::Tower,
+            position(ROOM, 24, 24),
+            Some(StoreInfo {
+                energy: 1000,
+                used: 1000,
+                capacity: 1000,
+            }),
+        );
+        world.add_hostile_creep("someone", position(ROOM, 27, 25), &[Part::Attack, Part::Move]);
+        assert!(run(&world).is_empty());
+    }
+
+    #[test]
+    fn fires_when_a_claimer_reaches_the_controller() {
+        let world = setup();
+        world.add_hostile_creep("someone", position(ROOM, 11, 11), &[Part::Claim, Part::Move]);
+        assert_eq!(run(&world).len(), 1);
+    }
+
+    #[test]
+    fn respects_charges_and_cooldown() {
+        let world = setup();
+        world.add_hostile_creep("someone", position(ROOM, 27, 25), &[Part::Attack, Part::Move]);
+        world.set_safe_mode(ROOM, 0, 0, 0);
+        assert!(run(&world).is_empty());
+        world.set_safe_mode(ROOM, 0, 2, 5000);
+        assert!(run(&world).is_empty());
+        // already on
+        world.set_safe_mode(ROOM, 1000, 2, 0);
+        assert!(run(&world).is_empty());
+    }
+}
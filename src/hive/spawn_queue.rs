@@ -0,0 +1,194 @@
+use std::{cmp::Ordering, fmt::Write};
+
+use log::debug;
+use screeps::Position;
+use serde::{Deserialize, Serialize};
+
+use crate::creep_setup::CreepSetup;
+
+// a request that is not issued again for this many ticks is dropped, so
+// requests of overlords that no longer need the creep don't pile up.
+pub const SPAWN_REQUEST_TIMEOUT: u32 = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SpawnRequest {
+    pub priority: u32,
+    pub setup: CreepSetup,
+    pub overlord: String,
+    pub colony: String,
+    // where the creep is going to work, spawns close to it are preferred
+    #[serde(default)]
+    pub site: Option<Position>,
+    // tick the request entered the queue, older requests go first among equals
+    pub enqueued_at: u32,
+    // tick the overlord issued the request the last time
+    pub last_requested: u32,
+}
+
+impl SpawnRequest {
+    fn is_same(&self, overlord: &str, role: &str) -> bool {
+        self.overlord == overlord && self.setup.role == role
+    }
+
+    // the order requests are served in, first one is the most urgent
+    fn serve_order(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .cmp(&self.priority)
+            .then(self.enqueued_at.cmp(&other.enqueued_at))
+            .then(self.overlord.cmp(&other.overlord))
+            .then(self.setup.role.cmp(&other.setup.role))
+    }
+}
+
+// spawn requests of one hatchery. requests live across ticks and are keyed by
+// overlord and role, issuing the same request again only refreshes it.
+#[derive(Debug, Default)]
+pub struct SpawnQueue {
+    // kept in serve order
+    requests: Vec<SpawnRequest>,
+}
+
+impl SpawnQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_requests(mut requests: Vec<SpawnRequest>) -> Self {
+        requests.sort_by(SpawnRequest::serve_order);
+        requests.dedup_by(|a, b| a.is_same(&b.overlord, &b.setup.role));
+        SpawnQueue { requests }
+    }
+
+    pub fn push(&mut self, request: SpawnRequest) {
+        match self
+            .requests
+            .iter_mut()
+            .find(|queued| queued.is_same(&request.overlord, &request.setup.role))
+        {
+            Some(queued) => {
+                queued.priority = request.priority;
+                queued.setup = request.setup;
+                queued.site = request.site;
+                queued.last_requested = request.last_requested;
+            }
+            None => self.requests.push(request),
+        }
+        self.requests.sort_by(SpawnRequest::serve_order);
+    }
+
+    pub fn remove(&mut self, overlord: &str, role: &str) -> Option<SpawnRequest> {
+        let index = self
+            .requests
+            .iter()
+            .position(|request| request.is_same(overlord, role))?;
+        Some(self.requests.remove(index))
+    }
+
+    pub fn drop_expired(&mut self, time: u32) {
+        self.requests.retain(|request| {
+            let alive = time.saturating_sub(request.last_requested) <= SPAWN_REQUEST_TIMEOUT;
+            if !alive {
+                debug!(
+                    "drop spawn request of {} for {}, not requested since {}",
+                    request.overlord, request.setup.role, request.last_requested
+                );
+            }
+            alive
+        });
+    }
+
+    // the request that gets the energy of the room first
+    pub fn peek(&self) -> Option<&SpawnRequest> {
+        self.requests.first()
+    }
+
+    pub fn requests(&self) -> &[SpawnRequest] {
+        &self.requests
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    // one line per request, in serve order
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        for (index, request) in self.requests.iter().enumerate() {
+            let _ = writeln!(
+                description,
+                "{}. {} for {} priority: {} cost: {} queued at: {} last requested: {}",
+                index + 1,
+                request.setup.role,
+                request.overlord,
+                request.priority,
+                request.setup.spawn_cost(),
+                request.enqueued_at,
+                request.last_requested
+            );
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{DEFAULT_PRIORITY, HIGH_PRIORITY};
+    use screeps::Part;
+
+    fn request(role: &str, overlord: &str, priority: u32, time: u32) -> SpawnRequest {
+        SpawnRequest {
+            priority,
+            setup: CreepSetup::new(role, vec![Part::Work, Part::Carry, Part::Move]),
+            overlord: overlord.to_string(),
+            colony: "W1N1".to_string(),
+            site: None,
+            enqueued_at: time,
+            last_requested: time,
+        }
+    }
+
+    #[test]
+    fn repeated_request_is_deduplicated() {
+        let mut queue = SpawnQueue::new();
+        queue.push(request("miner", "mine", DEFAULT_PRIORITY, 1));
+        queue.push(request("miner", "mine", HIGH_PRIORITY, 5));
+
+        assert_eq!(queue.len(), 1);
+        let request = queue.peek().unwrap();
+        assert_eq!(request.priority, HIGH_PRIORITY);
+        assert_eq!(request.enqueued_at, 1);
+        assert_eq!(request.last_requested, 5);
+    }
+
+    #[test]
+    fn serves_by_priority_then_age() {
+        let mut queue = SpawnQueue::new();
+        queue.push(request("a", "first", DEFAULT_PRIORITY, 1));
+        queue.push(request("b", "second", DEFAULT_PRIORITY, 2));
+        queue.push(request("c", "urgent", HIGH_PRIORITY, 3));
+
+        let order: Vec<&str> = queue
+            .requests()
+            .iter()
+            .map(|request| request.overlord.as_str())
+            .collect();
+        assert_eq!(order, vec!["urgent", "first", "second"]);
+    }
+
+    #[test]
+    fn expired_requests_are_dropped() {
+        let mut queue = SpawnQueue::new();
+        queue.push(request("a", "old", DEFAULT_PRIORITY, 0));
+        queue.push(request("a", "new", DEFAULT_PRIORITY, 40));
+        queue.drop_expired(SPAWN_REQUEST_TIMEOUT + 1);
+
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.peek().unwrap().overlord, "new");
+    }
+}
@@ -0,0 +1,318 @@
+use log::{info, warn};
+use screeps::{
+    Boost, Part, RoomName, ATTACK_POWER, DISMANTLE_POWER, HEAL_POWER, RANGED_ATTACK_POWER,
+};
+
+use crate::world::{BodyPartInfo, HostileCreepInfo};
+
+use super::{effective_damage, healing_at, TowerController};
+
+const MAX_DEFENDERS: usize = 3;
+
+// what a hostile creep is built for, by the parts of its body that matter most
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostileRole {
+    Attacker,
+    RangedAttacker,
+    Healer,
+    Dismantler,
+    Claimer,
+    // nothing that can hurt us
+    Scout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThreatLevel {
+    // no hostiles, or scouts only
+    None,
+    // the towers out-damage the healing of the hostiles
+    Contained,
+    // the colony needs defenders
+    Uncontained,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostileThreat {
+    pub creep: HostileCreepInfo,
+    pub role: HostileRole,
+    // damage to creeps per tick, melee and ranged
+    pub damage: u32,
+    // damage to structures per tick by dismantling
+    pub dismantle: u32,
+    // hits it heals per tick on a creep next to it
+    pub heal: u32,
+    // hits the towers take off it per tick after its healers are done
+    pub tower_net_damage: i64,
+}
+
+impl HostileThreat {
+    pub fn new(creep: HostileCreepInfo) -> Self {
+        let power = |part: Part, base: u32| -> u32 {
+            creep
+                .body
+                .iter()
+                .filter(|body_part| body_part.part == part)
+                .map(|body_part| base * boost_multiplier(body_part))
+                .sum()
+        };
+        let damage = power(Part::Attack, ATTACK_POWER) + power(Part::RangedAttack, RANGED_ATTACK_POWER);
+        let dismantle = power(Part::Work, DISMANTLE_POWER);
+        let heal = power(Part::Heal, HEAL_POWER);
+        let role = if creep.count_parts(Part::Attack) > 0 {
+            HostileRole::Attacker
+        } else if creep.count_parts(Part::RangedAttack) > 0 {
+            HostileRole::RangedAttacker
+        } else if heal > 0 {
+            HostileRole::Healer
+        } else if dismantle > 0 {
+            HostileRole::Dismantler
+        } else if creep.count_parts(Part::Claim) > 0 {
+            HostileRole::Claimer
+        } else {
+            HostileRole::Scout
+        };
+        HostileThreat {
+            creep,
+            role,
+            damage,
+            dismantle,
+            heal,
+            tower_net_damage: 0,
+        }
+    }
+
+    // none if the towers can't kill it at all
+    pub fn ticks_to_kill(&self) -> Option<u32> {
+        if self.tower_net_damage <= 0 {
+            return None;
+        }
+        Some(self.creep.hits.div_ceil(self.tower_net_damage as u32))
+    }
+}
+
+fn boost_multiplier(body_part: &BodyPartInfo) -> u32 {
+    match (body_part.part, body_part.boost.and_then(|boost| boost.boost())) {
+        (Part::Attack, Some(Boost::Attack(multiplier)))
+        | (Part::RangedAttack, Some(Boost::RangedAttack(multiplier)))
+        | (Part::Work, Some(Boost::Dismantle(multiplier)))
+        | (Part::Heal, Some(Boost::Heal(multiplier))) => multiplier,
+        _ => 1,
+    }
+}
+
+// sizes up the hostiles in a colony room against its towers, refreshed after
+// the towers every tick
+pub struct ThreatAssessor {
+    room_name: RoomName,
+    threats: Vec<HostileThreat>,
+    level: ThreatLevel,
+    // the fewest hits the towers take off a hostile that can harm us in a
+    // tick after healing, negative if they can't hurt the worst of them
+    tower_net_damage: i64,
+}
+
+impl ThreatAssessor {
+    pub fn new(room_name: RoomName) -> Self {
+        ThreatAssessor {
+            room_name,
+            threats: Vec::new(),
+            level: ThreatLevel::None,
+            tower_net_damage: 0,
+        }
+    }
+
+    pub fn refresh(&mut self, towers: &TowerController) {
+        let hostiles = towers.hostiles();
+        self.threats = hostiles
+            .iter()
+            .map(|hostile| {
+                let mut threat = HostileThreat::new(hostile.clone());
+                let damage = effective_damage(hostile, towers.damage_at(hostile.pos)) as i64;
+                threat.tower_net_damage = damage - healing_at(hostile, hostiles) as i64;
+                threat
+            })
+            .collect();
+        self.tower_net_damage = self
+            .threats
+            .iter()
+            .filter(|threat| threat.role != HostileRole::Scout)
+            .map(|threat| threat.tower_net_damage)
+            .min()
+            .unwrap_or(0);
+
+        let level = if !self.threats.iter().any(|threat| threat.role != HostileRole::Scout) {
+            ThreatLevel::None
+        } else if self.tower_net_damage > 0 {
+            ThreatLevel::Contained
+        } else {
+            ThreatLevel::Uncontained
+        };
+        if level != self.level {
+            match level {
+                ThreatLevel::None => info!("{} is clear of hostiles", self.room_name),
+                ThreatLevel::Contained => info!(
+                    "{} hostiles in {}, the towers hold them",
+                    self.threats.len(),
+                    self.room_name
+                ),
+                ThreatLevel::Uncontained => warn!(
+                    "{} hostiles in {} with {} damage and {} heal, the towers can't hold them",
+                    self.threats.len(),
+                    self.room_name,
+                    self.total_damage(),
+                    self.total_heal()
+                ),
+            }
+        }
+        self.level = level;
+    }
+
+    pub fn level(&self) -> ThreatLevel {
+        self.level
+    }
+
+    pub fn threats(&self) -> &[HostileThreat] {
+        &self.threats
+    }
+
+    pub fn total_damage(&self) -> u32 {
+        self.threats.iter().map(|threat| threat.damage).sum()
+    }
+
+    pub fn total_heal(&self) -> u32 {
+        self.threats.iter().map(|threat| threat.heal).sum()
+    }
+
+    // defenders dealing `defender_damage` each it takes to out-damage the
+    // healing the towers can't, on the hostile the towers hurt the least
+    pub fn defenders_needed(&self, defender_damage: u32) -> usize {
+        if self.level != ThreatLevel::Uncontained || defender_damage == 0 {
+            return 0;
+        }
+        let missing = (1 - self.tower_net_damage).max(1) as u32;
+        (missing.div_ceil(defender_damage) as usize).clamp(1, MAX_DEFENDERS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        mock::{position, MockWorld},
+        StoreInfo, World,
+    };
+    use screeps::{ResourceType, StructureType};
+    use std::rc::Rc;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup(towers: usize) -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 3);
+        for i in 0..towers {
+            world.add_structure(
+                StructureType::Tower,
+                position(ROOM, 25 + i as u8, 25),
+                Some(StoreInfo {
+                    energy: 1000,
+                    used: 1000,
+                    capacity: 1000,
+                }),
+            );
+        }
+        world
+    }
+
+    fn assess(world: &Rc<MockWorld>) -> ThreatAssessor {
+        let room_name = RoomName::new(ROOM).unwrap();
+        let towers = TowerController::new(world.clone(), room_name);
+        let mut threats = ThreatAssessor::new(room_name);
+        threats.refresh(&towers);
+        threats
+    }
+
+    #[test]
+    fn classifies_hostiles_by_body() {
+        let world = setup(0);
+        world.add_hostile_creep("Invader", position(ROOM, 20, 20), &[Part::Attack, Part::Heal]);
+        world.add_hostile_creep("Invader", position(ROOM, 20, 21), &[Part::Heal, Part::Move]);
+        world.add_hostile_creep("Invader", position(ROOM, 20, 22), &[Part::Work, Part::Move]);
+        world.add_hostile_creep("someone", position(ROOM, 20, 23), &[Part::Claim, Part::Move]);
+        world.add_hostile_creep("someone", position(ROOM, 20, 24), &[Part::Move]);
+        let roles: Vec<HostileRole> = assess(&world).threats().iter().map(|threat| threat.role).collect();
+        assert_eq!(
+            roles,
+            vec![
+                HostileRole::Attacker,
+                HostileRole::Healer,
+                HostileRole::Dismantler,
+                HostileRole::Claimer,
+                HostileRole::Scout
+            ]
+        );
+    }
+
+    #[test]
+    fn boosts_multiply_damage_and_heal() {
+        let world = setup(0);
+        world.add_hostile_creep("Invader", position(ROOM, 20, 20), &[Part::Attack, Part::Heal]);
+        let mut creep = world.hostile_creeps(RoomName::new(ROOM).unwrap())[0].clone();
+        creep.body[0].boost = Some(ResourceType::CatalyzedUtriumAcid);
+        creep.body[1].boost = Some(ResourceType::LemergiumOxide);
+        let threat = HostileThreat::new(creep);
+        assert_eq!(threat.damage, ATTACK_POWER * 4);
+        assert_eq!(threat.heal, HEAL_POWER * 2);
+    }
+
+    #[test]
+    fn scouts_are_no_threat() {
+        let world = setup(0);
+        world.add_hostile_creep("someone", position(ROOM, 20, 20), &[Part::Move]);
+        let threats = assess(&world);
+        assert_eq!(threats.level(), ThreatLevel::None);
+        assert_eq!(threats.defenders_needed(300), 0);
+    }
+
+    #[test]
+    fn towers_contain_a_small_attacker() {
+        let world = setup(1);
+        world.add_hostile_creep("Invader", position(ROOM, 30, 30), &[Part::Attack, Part::Move]);
+        let threats = assess(&world);
+        assert_eq!(threats.level(), ThreatLevel::Contained);
+        assert_eq!(threats.defenders_needed(300), 0);
+    }
+
+    #[test]
+    fn healers_out_of_tower_reach_need_defenders() {
+        let world = setup(1);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 45), &[Part::Attack; 5]);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 46), &[Part::Heal; 20]);
+        let threats = assess(&world);
+        assert_eq!(threats.level(), ThreatLevel::Uncontained);
+        // 240 heal on the attacker against 150 tower damage
+        assert_eq!(threats.defenders_needed(60), 2);
+    }
+
+    #[test]
+    fn a_room_without_towers_needs_defenders() {
+        let world = setup(0);
+        world.add_hostile_creep("Invader", position(ROOM, 30, 30), &[Part::Work, Part::Move]);
+        let threats = assess(&world);
+        assert_eq!(threats.level(), ThreatLevel::Uncontained);
+        assert_eq!(threats.defenders_needed(60), 1);
+    }
+
+    #[test]
+    fn an_unkillable_pair_is_not_hidden_by_a_killable_creep() {
+        let world = setup(1);
+        world.add_hostile_creep("Invader", position(ROOM, 26, 26), &[Part::Attack, Part::Move]);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 45), &[Part::Attack; 5]);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 46), &[Part::Heal; 20]);
+        let threats = assess(&world);
+        assert!(threats.threats()[0].ticks_to_kill().is_some());
+        assert_eq!(threats.level(), ThreatLevel::Uncontained);
+        // sized for the pair, like without the killable creep
+        assert_eq!(threats.defenders_needed(60), 2);
+    }
+}
@@ -0,0 +1,346 @@
+use std::rc::Rc;
+
+use log::{info, warn};
+use screeps::{
+    Boost, ErrorCode, Part, Position, RoomName, StructureType, HEAL_POWER, RANGED_HEAL_POWER,
+    TOWER_ENERGY_COST, TOWER_FALLOFF, TOWER_FALLOFF_RANGE, TOWER_OPTIMAL_RANGE,
+    TOWER_POWER_ATTACK, TOWER_POWER_HEAL, TOWER_POWER_REPAIR,
+};
+
+use crate::world::{CreepInfo, HostileCreepInfo, StructureInfo, World};
+
+use super::{RepairQueue, REPAIR_DECAY};
+
+// towers spend their energy on the cheap repairs only
+const TOWER_REPAIR_MIN_PRIORITY: u32 = REPAIR_DECAY;
+// and keep this share of it for defense
+const TOWER_RESERVE_PERCENT: u32 = 50;
+const BODY_PART_HITS: u32 = 100;
+
+// the power of a tower action at `range`: full up to 5 tiles, falling off to a
+// quarter at 20
+pub fn tower_power(power: u32, range: u32) -> u32 {
+    let range = range.clamp(TOWER_OPTIMAL_RANGE, TOWER_FALLOFF_RANGE);
+    let falloff = TOWER_FALLOFF * (range - TOWER_OPTIMAL_RANGE) as f32
+        / (TOWER_FALLOFF_RANGE - TOWER_OPTIMAL_RANGE) as f32;
+    (power as f32 * (1.0 - falloff)) as u32
+}
+
+// the hits `damage` takes from the creep. parts are hit front to back and
+// boosted tough parts only take a share of it.
+pub fn effective_damage(creep: &HostileCreepInfo, damage: u32) -> u32 {
+    let mut remaining = damage as f32;
+    let mut dealt = 0.0;
+    for body_part in creep.body.iter() {
+        if remaining <= 0.0 {
+            break;
+        }
+        let ratio = match body_part.boost.and_then(|boost| boost.boost()) {
+            Some(Boost::Tough(ratio)) if body_part.part == Part::Tough => ratio,
+            _ => 1.0,
+        };
+        let absorbed = remaining.min(BODY_PART_HITS as f32 / ratio);
+        dealt += absorbed * ratio;
+        remaining -= absorbed;
+    }
+    (dealt + remaining.max(0.0)) as u32
+}
+
+// the hits the hostiles around `target` heal on it in one tick
+pub fn healing_at(target: &HostileCreepInfo, hostiles: &[HostileCreepInfo]) -> u32 {
+    hostiles
+        .iter()
+        .map(|healer| {
+            let power = match healer.pos.get_range_to(target.pos) {
+                0 | 1 => HEAL_POWER,
+                2 | 3 => RANGED_HEAL_POWER,
+                _ => return 0,
+            };
+            healer
+                .body
+                .iter()
+                .filter(|body_part| body_part.part == Part::Heal)
+                .map(|body_part| match body_part.boost.and_then(|boost| boost.boost()) {
+                    Some(Boost::Heal(multiplier)) => power * multiplier,
+                    _ => power,
+                })
+                .sum::<u32>()
+        })
+        .sum()
+}
+
+// the towers of a colony: they focus fire on the hostile they can hurt the
+// most, heal our creeps, and keep roads and containers up while idle.
+pub struct TowerController {
+    world: Rc<dyn World>,
+    room_name: RoomName,
+    // our towers with energy for a shot
+    towers: Vec<StructureInfo>,
+    hostiles: Vec<HostileCreepInfo>,
+}
+
+impl TowerController {
+    pub fn new(world: Rc<dyn World>, room_name: RoomName) -> Self {
+        let mut controller = TowerController {
+            world,
+            room_name,
+            towers: Vec::new(),
+            hostiles: Vec::new(),
+        };
+        controller.refresh();
+        controller
+    }
+
+    pub fn refresh(&mut self) {
+        self.towers = self
+            .world
+            .structures(self.room_name)
+            .into_iter()
+            .filter(|s| s.structure_type == StructureType::Tower && s.my)
+            .filter(|s| s.store.map_or(0, |store| store.energy) >= TOWER_ENERGY_COST)
+            .collect();
+        self.hostiles = self.world.hostile_creeps(self.room_name);
+    }
+
+    pub fn hostiles(&self) -> &[HostileCreepInfo] {
+        &self.hostiles
+    }
+
+    // damage of all towers together on a creep at `pos`
+    pub fn damage_at(&self, pos: Position) -> u32 {
+        self.towers
+            .iter()
+            .map(|tower| tower_power(TOWER_POWER_ATTACK, tower.pos.get_range_to(pos)))
+            .sum()
+    }
+
+    // the hostile losing the most hits to all towers after its healers are
+    // done, none if no hostile loses any
+    pub fn focus_target(&self) -> Option<&HostileCreepInfo> {
+        self.hostiles
+            .iter()
+            .map(|hostile| {
+                let damage = effective_damage(hostile, self.damage_at(hostile.pos));
+                let net = damage as i64 - healing_at(hostile, &self.hostiles) as i64;
+                (hostile, net)
+            })
+            .filter(|(_, net)| *net > 0)
+            .max_by_key(|(hostile, net)| (*net, hostile.count_parts(Part::Heal), hostile.id))
+            .map(|(hostile, _)| hostile)
+    }
+
+    pub fn run(&self, repairs: &RepairQueue) {
+        if self.towers.is_empty() {
+            return;
+        }
+        if let Some(target) = self.focus_target() {
+            self.attack(target);
+            return;
+        }
+        let healing = self.heal();
+        // with hostiles around the energy is kept for when they can be hurt
+        if !healing && self.hostiles.is_empty() {
+            self.repair(repairs);
+        }
+    }
+
+    fn attack(&self, target: &HostileCreepInfo) {
+        info!(
+            "towers of {} fire at {} of {}",
+            self.room_name, target.id, target.owner
+        );
+        for tower in self.towers.iter() {
+            match self.world.tower_attack(tower.id, target.id) {
+                Ok(()) => {}
+                Err(ErrorCode::NotEnough) => {}
+                Err(e) => warn!("tower attack of {} failed: {:?}", self.room_name, e),
+            }
+        }
+    }
+
+    // every tower heals the creep missing the most hits, until it is covered.
+    // returns whether a tower healed.
+    fn heal(&self) -> bool {
+        let mut damaged: Vec<(CreepInfo, u32)> = self
+            .world
+            .creeps()
+            .into_iter()
+            .filter(|creep| creep.pos.room_name() == self.room_name && !creep.spawning)
+            .filter(|creep| creep.hits < creep.hits_max)
+            .map(|creep| {
+                let missing = creep.hits_max - creep.hits;
+                (creep, missing)
+            })
+            .collect();
+        let mut healed = false;
+        for tower in self.towers.iter() {
+            let patient = match damaged
+                .iter_mut()
+                .filter(|(_, missing)| *missing > 0)
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.name.cmp(&a.0.name)))
+            {
+                Some(patient) => patient,
+                None => break,
+            };
+            match self.world.tower_heal(tower.id, &patient.0.name) {
+                Ok(()) => healed = true,
+                Err(ErrorCode::NotEnough) => {}
+                Err(e) => warn!("tower heal of {} failed: {:?}", self.room_name, e),
+            }
+            let power = tower_power(TOWER_POWER_HEAL, tower.pos.get_range_to(patient.0.pos));
+            patient.1 = patient.1.saturating_sub(power);
+        }
+        healed
+    }
+
+    fn repair(&self, repairs: &RepairQueue) {
+        let towers: Vec<&StructureInfo> = self
+            .towers
+            .iter()
+            .filter(|s| {
+                s.store
+                    .map(|store| store.energy * 100 > store.capacity * TOWER_RESERVE_PERCENT)
+                    .unwrap_or(false)
+            })
+            .collect();
+        for (tower, entry) in repairs.assign(
+            &towers,
+            TOWER_REPAIR_MIN_PRIORITY,
+            |tower| tower.pos,
+            |_| TOWER_POWER_REPAIR,
+        ) {
+            match self.world.tower_repair(tower.id, entry.id) {
+                Ok(()) => {}
+                Err(ErrorCode::NotEnough) => {}
+                Err(e) => warn!("tower repair of {} failed: {:?}", self.room_name, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        mock::{position, MockAction, MockWorld},
+        BodyPartInfo, StoreInfo,
+    };
+    use crate::zerg::CreepMemory;
+    use screeps::{RawObjectId, ResourceType};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> Rc<MockWorld> {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 3);
+        world
+    }
+
+    fn add_tower(world: &MockWorld, x: u8, y: u8, energy: u32) -> RawObjectId {
+        world.add_structure(
+            StructureType::Tower,
+            position(ROOM, x, y),
+            Some(StoreInfo {
+                energy,
+                used: energy,
+                capacity: 1000,
+            }),
+        )
+    }
+
+    fn add_worker(world: &MockWorld, name: &str, x: u8, y: u8, hits: u32) {
+        let memory = CreepMemory {
+            overlord: "work-W1N1".to_string(),
+            role: "worker".to_string(),
+            colony: "W1N1".to_string(),
+            state: None,
+            task: None,
+        };
+        world.add_creep(name, position(ROOM, x, y), &[Part::Work; 5], memory);
+        world.set_creep_hits(name, hits);
+    }
+
+    fn run(world: &Rc<MockWorld>) -> Vec<MockAction> {
+        let room_name = RoomName::new(ROOM).unwrap();
+        let repairs = RepairQueue::new(world.clone(), room_name);
+        TowerController::new(world.clone(), room_name).run(&repairs);
+        world.take_actions()
+    }
+
+    #[test]
+    fn tower_power_falls_off_with_range() {
+        assert_eq!(tower_power(TOWER_POWER_ATTACK, 3), 600);
+        assert_eq!(tower_power(TOWER_POWER_ATTACK, 20), 150);
+        assert_eq!(tower_power(TOWER_POWER_ATTACK, 40), 150);
+        assert!(tower_power(TOWER_POWER_ATTACK, 10) < 600);
+    }
+
+    #[test]
+    fn boosted_tough_parts_soak_damage() {
+        let world = setup();
+        world.add_hostile_creep("Invader", position(ROOM, 20, 20), &[Part::Tough, Part::Move]);
+        let mut hostile = world.hostile_creeps(RoomName::new(ROOM).unwrap())[0].clone();
+        assert_eq!(effective_damage(&hostile, 150), 150);
+        hostile.body[0] = BodyPartInfo {
+            part: Part::Tough,
+            boost: Some(ResourceType::CatalyzedGhodiumAlkalide),
+        };
+        // 333 damage break the tough part, the rest hits the move part
+        assert_eq!(effective_damage(&hostile, 433), 199);
+    }
+
+    #[test]
+    fn towers_focus_fire_on_what_they_can_hurt() {
+        let world = setup();
+        let tower = add_tower(&world, 25, 25, 500);
+        let other = add_tower(&world, 30, 30, 500);
+        // two healers keep each other up far away, the attacker close by can't
+        // be healed through
+        world.add_hostile_creep("Invader", position(ROOM, 45, 45), &[Part::Heal; 10]);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 46), &[Part::Heal; 10]);
+        let attacker = world.add_hostile_creep("Invader", position(ROOM, 26, 26), &[Part::Attack]);
+        assert_eq!(
+            run(&world),
+            vec![
+                MockAction::TowerAttack { tower, target: attacker },
+                MockAction::TowerAttack { tower: other, target: attacker },
+            ]
+        );
+    }
+
+    #[test]
+    fn towers_hold_fire_against_out_healing_hostiles() {
+        let world = setup();
+        add_tower(&world, 5, 5, 500);
+        let road = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(road, 1000, 5000);
+        world.add_hostile_creep("Invader", position(ROOM, 45, 45), &[Part::Heal; 20]);
+        assert!(run(&world).is_empty());
+    }
+
+    #[test]
+    fn towers_heal_the_most_damaged_creep() {
+        let world = setup();
+        let tower = add_tower(&world, 25, 25, 500);
+        add_worker(&world, "scratched", 20, 20, 450);
+        add_worker(&world, "wounded", 30, 30, 100);
+        assert_eq!(
+            run(&world),
+            vec![MockAction::TowerHeal {
+                tower,
+                creep: "wounded".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn tower_repairs_with_spare_energy_only() {
+        let world = setup();
+        let road = world.add_structure(StructureType::Road, position(ROOM, 20, 20), None);
+        world.set_structure_hits(road, 1000, 5000);
+        add_tower(&world, 30, 30, 400);
+        let tower = add_tower(&world, 25, 25, 800);
+        assert_eq!(run(&world), vec![MockAction::TowerRepair { tower, target: road }]);
+    }
+}
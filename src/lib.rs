@@ -1,32 +1,36 @@
 use std::cell::RefCell;
-use std::collections::HashSet;
-use std::collections::{hash_map::Entry, HashMap};
 use std::rc::Rc;
 
 use log::*;
-use screeps::Room;
-use screeps::{
-    constants::{ErrorCode, Part, ResourceType},
-    enums::StructureObject,
-    find, game,
-    local::ObjectId,
-    objects::{Creep, Source, StructureController},
-    prelude::*,
-};
+use screeps::game;
 use wasm_bindgen::prelude::*;
 
 use crate::colony::Colony;
+use crate::memory::{SwarmMemory, COLONIES};
+use crate::world::{ScreepsWorld, World};
 
-mod colony;
-mod constants;
-mod creep_setup;
-mod error;
-mod hive;
+pub mod colony;
+pub mod constants;
+pub mod creep_setup;
+pub mod error;
+pub mod hive;
 mod logging;
-mod memory;
-mod overlord;
-mod util;
-mod zerg;
+pub mod memory;
+pub mod movement;
+pub mod overlord;
+pub mod pathfinder;
+pub mod planner;
+pub mod profiler;
+// native only, kept out of the wasm we upload
+#[cfg(not(target_arch = "wasm32"))]
+pub mod simulator;
+pub mod task;
+pub mod world;
+pub mod zerg;
+
+thread_local! {
+  static WORLD: Rc<dyn World> = Rc::new(ScreepsWorld::new());
+}
 
 // add wasm_bindgen to any function you would like to expose for call from js
 #[wasm_bindgen]
@@ -34,38 +38,110 @@ pub fn setup() {
     logging::setup_logging(logging::Debug);
 }
 
-fn get_room_with_spawn() -> Vec<Room> {
-  let mut hash_map: HashMap<String, Room> = HashMap::new();
-  game::spawns().values().for_each(|spawn| {
-    hash_map.insert(spawn.room().unwrap().name().to_string(), spawn.room().unwrap());
-  });
-  hash_map.into_values().collect()
-}
+// colonys live on the heap across ticks. lost rooms are dropped, new rooms with
+// spawns are added, restored from the snapshot in `Memory` when there is one.
+fn refresh_colonys(world: &Rc<dyn World>) -> Vec<Rc<RefCell<Colony>>> {
+  let room_names = world.spawn_rooms();
+  COLONIES.with(|colonies| {
+    let mut colonies = colonies.borrow_mut();
 
-// to use a reserved name as a function name, use `js_name`:
-#[wasm_bindgen(js_name = loop)]
-pub fn game_loop() {
-    debug!("loop starting! CPU: {}", game::cpu::get_used());
+    colonies.retain(|name, colony| {
+      let room_name = screeps::RoomName::new(name).ok();
+      if !room_name.is_some_and(|room_name| room_names.contains(&room_name)) {
+        info!("colony {} lost its spawns, removed", name);
+        return false;
+      }
+      let result = colony.as_ref().borrow_mut().refresh();
+      if result.is_err() {
+        warn!("refresh colony {} failed, removed. {:?}", name, result.err().unwrap());
+        return false;
+      }
+      true
+    });
 
-    let rooms = get_room_with_spawn();
-    let mut colonys:  Vec<Rc<RefCell<Colony>>> = Vec::new();
-    for room in rooms {
-      let colony = colony::Colony::new_from_room(room);
+    // only read the snapshot when a colony has to be built, i.e. after a global reset
+    let mut swarm_memory: Option<SwarmMemory> = None;
+    for room_name in room_names {
+      let name = room_name.to_string();
+      if colonies.contains_key(&name) {
+        continue;
+      }
+      let cache = swarm_memory
+        .get_or_insert_with(|| SwarmMemory::load(world.as_ref()))
+        .colonies
+        .get(&name);
+      let colony = colony::Colony::new_from_room(world.clone(), room_name, cache);
       if colony.is_err() {
         warn!("init colony failed. {:?}", colony.err().unwrap());
         continue;
       }
-      colonys.push(colony.unwrap())
+      colonies.insert(name, colony.unwrap());
     }
 
-    debug!("initialize colony done! cpu: {}", game::cpu::get_used());
+    colonies.values().cloned().collect()
+  })
+}
+
+// one tick of the whole swarm. the game calls it through `game_loop`, the
+// simulator calls it directly with its own world.
+pub fn run_tick(world: &Rc<dyn World>) {
+    let colonys = profiler::measure(world.as_ref(), "refresh", || refresh_colonys(world));
 
     // run colonys
     for colony in colonys.iter() {
-      colony.as_ref().borrow().run();
+      let colony = colony.as_ref().borrow();
+      profiler::measure(world.as_ref(), &colony.central_room_name, || colony.run());
     }
+    profiler::measure(world.as_ref(), "traffic", || movement::run_traffic(world.as_ref()));
+
+    // persist colonys
+    profiler::measure(world.as_ref(), "persist", || {
+      let mut swarm_memory = SwarmMemory::default();
+      for colony in colonys.iter() {
+        let colony = colony.as_ref().borrow();
+        swarm_memory
+          .colonies
+          .insert(colony.central_room_name.clone(), colony.get_cache());
+      }
+      swarm_memory.store(world.as_ref());
+    });
+
+    profiler::finish_tick(world.as_ref());
+}
+
+// debugging aid, call `spawn_queue("W1N1")` from the game console to see what
+// the hatchery of a colony is waiting for.
+#[wasm_bindgen]
+pub fn spawn_queue(room_name: String) -> String {
+    COLONIES.with(|colonies| match colonies.borrow().get(&room_name) {
+        Some(colony) => {
+            let colony = colony.as_ref().borrow();
+            let hive = colony.hive.as_ref().borrow();
+            let queue = hive.hatcherys.spawn_queue();
+            if queue.is_empty() {
+                format!("spawn queue of {} is empty", room_name)
+            } else {
+                format!("spawn queue of {}:\n{}", room_name, queue.describe())
+            }
+        }
+        None => format!("no colony in {}", room_name),
+    })
+}
+
+// call `cpu_report()` from the game console for the cpu each colony, overlord
+// and hive took on average.
+#[wasm_bindgen]
+pub fn cpu_report() -> String {
+    WORLD.with(|world| profiler::report(world.as_ref()))
+}
+
+// to use a reserved name as a function name, use `js_name`:
+#[wasm_bindgen(js_name = loop)]
+pub fn game_loop() {
+    debug!("loop starting! CPU: {}", game::cpu::get_used());
 
-    debug!("run colony done! cpu: {}", game::cpu::get_used());
+    let world = WORLD.with(|world| world.clone());
+    run_tick(&world);
 }
 
 // fn run_spawn() {
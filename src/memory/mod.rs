@@ -1,9 +1,115 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
-use wasm_bindgen::JsValue;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::colony::{Colony, ColonyCache};
+use crate::world::World;
+
+// bump this whenever the layout of any cache changes. snapshots written by an
+// older version are dropped and the colonies are rebuilt from the game state.
+pub const SWARM_MEMORY_VERSION: u32 = 2;
 
 // this is one way to persist data between ticks within Rust's memory, as opposed to
 // keeping state in memory on game objects - but will be lost on global resets!
+// colonies are keyed by the name of their central room.
 thread_local! {
-  pub static COLONY_LIST: RefCell<Vec<String>> = RefCell::new(Vec::new());
+  pub static COLONIES: RefCell<BTreeMap<String, Rc<RefCell<Colony>>>> =
+    const { RefCell::new(BTreeMap::new()) };
+}
+
+// snapshot of the whole swarm, stored as json in `Memory.swarm`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SwarmMemory {
+    pub version: u32,
+    pub colonies: BTreeMap<String, ColonyCache>,
+}
+
+// only the version is read first, so a snapshot of another layout is recognized
+// before we try to parse it.
+#[derive(Deserialize)]
+struct SwarmMemoryVersion {
+    version: u32,
+}
+
+impl Default for SwarmMemory {
+    fn default() -> Self {
+        SwarmMemory {
+            version: SWARM_MEMORY_VERSION,
+            colonies: BTreeMap::new(),
+        }
+    }
+}
+
+impl SwarmMemory {
+    pub fn load(world: &dyn World) -> Self {
+        match world.load_snapshot() {
+            Some(snapshot) => Self::from_snapshot(&snapshot),
+            None => Self::default(),
+        }
+    }
+
+    // never fails, a snapshot we can't understand is treated as no snapshot at all.
+    pub fn from_snapshot(snapshot: &str) -> Self {
+        let version = serde_json::from_str::<SwarmMemoryVersion>(snapshot)
+            .ok()
+            .map(|probe| probe.version);
+        if version != Some(SWARM_MEMORY_VERSION) {
+            warn!(
+                "swarm memory version mismatch, expect {}, found {:?}. drop snapshot.",
+                SWARM_MEMORY_VERSION, version
+            );
+            return Self::default();
+        }
+
+        match serde_json::from_str::<SwarmMemory>(snapshot) {
+            Ok(memory) => memory,
+            Err(e) => {
+                warn!("parse swarm memory failed. drop snapshot. {:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn store(&self, world: &dyn World) {
+        match serde_json::to_string(self) {
+            Ok(snapshot) => world.store_snapshot(&snapshot),
+            Err(e) => warn!("serialize swarm memory failed. {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::MockWorld;
+
+    #[test]
+    fn snapshot_round_trip() {
+        let world = MockWorld::new();
+        let mut memory = SwarmMemory::default();
+        memory
+            .colonies
+            .insert("W1N1".to_string(), ColonyCache::default());
+        memory.store(&world);
+
+        let loaded = SwarmMemory::load(&world);
+        assert_eq!(loaded.version, SWARM_MEMORY_VERSION);
+        assert!(loaded.colonies.contains_key("W1N1"));
+    }
+
+    #[test]
+    fn snapshot_of_other_version_is_dropped() {
+        let loaded =
+            SwarmMemory::from_snapshot(r#"{"version":1,"colonies":{"W1N1":{"overlords":{}}}}"#);
+        assert_eq!(loaded.version, SWARM_MEMORY_VERSION);
+        assert!(loaded.colonies.is_empty());
+    }
+
+    #[test]
+    fn broken_snapshot_is_dropped() {
+        let loaded = SwarmMemory::from_snapshot(r#"{"version":2,"colonies":42}"#);
+        assert!(loaded.colonies.is_empty());
+        assert!(SwarmMemory::from_snapshot("not json").colonies.is_empty());
+    }
 }
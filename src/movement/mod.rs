@@ -0,0 +1,359 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+};
+
+use log::debug;
+use screeps::{ErrorCode, Position, RoomName};
+
+use crate::{
+    pathfinder::{self, CostMatrixCache, SearchOptions},
+    world::{CreepInfo, World},
+};
+
+mod traffic;
+
+pub use traffic::MovePriority;
+use traffic::{MoveIntent, Traffic};
+
+// ticks a creep may fail to move before it looks for a way around other creeps
+const STUCK_TICKS: u32 = 2;
+// paths not followed for this long are dropped, their creeps died or arrived
+const PATH_TTL: u32 = 50;
+
+// paths and cost matrices live on the heap, after a global reset they are
+// searched again
+thread_local! {
+  static MOVEMENT: RefCell<Movement> = RefCell::new(Movement::default());
+}
+
+// the path a creep follows to its target
+struct CachedPath {
+    target: Position,
+    range: u32,
+    // the tiles ahead, the next step first
+    steps: VecDeque<Position>,
+    // where the creep stood when it last asked to move, and when
+    last_pos: Position,
+    last_tick: u32,
+    // ticks in a row the creep asked to move and didn't
+    stuck: u32,
+}
+
+#[derive(Default)]
+struct Movement {
+    paths: HashMap<String, CachedPath>,
+    matrices: CostMatrixCache,
+    traffic: Traffic,
+}
+
+// walks the creep towards `target` until it is within `range`. the step is
+// only taken in `run_traffic`, once every creep asked for its own.
+pub fn travel(
+    world: &dyn World,
+    creep: &CreepInfo,
+    target: Position,
+    range: u32,
+    priority: MovePriority,
+) -> Result<(), ErrorCode> {
+    if creep.spawning {
+        return Err(ErrorCode::Busy);
+    }
+    if creep.pos.get_range_to(target) <= range {
+        return Ok(());
+    }
+    if creep.fatigue > 0 {
+        return Err(ErrorCode::Tired);
+    }
+    MOVEMENT.with(|movement| {
+        movement
+            .borrow_mut()
+            .travel(world, creep, target, range, priority)
+    })
+}
+
+// takes the steps the creeps asked for this tick, called once after every
+// colony ran
+pub fn run_traffic(world: &dyn World) {
+    MOVEMENT.with(|movement| movement.borrow_mut().run_traffic(world))
+}
+
+impl Movement {
+    fn travel(
+        &mut self,
+        world: &dyn World,
+        creep: &CreepInfo,
+        target: Position,
+        range: u32,
+        priority: MovePriority,
+    ) -> Result<(), ErrorCode> {
+        let time = world.time();
+        let mut stuck = 0;
+        let mut path = self
+            .paths
+            .remove(&creep.name)
+            .filter(|path| path.target == target && path.range == range)
+            .and_then(|mut path| {
+                if path.last_pos != creep.pos {
+                    path.stuck = 0;
+                } else if path.last_tick + 1 == time {
+                    path.stuck += 1;
+                }
+                stuck = path.stuck;
+                // the creep moved along, or was shoved off the path
+                if let Some(index) = path.steps.iter().position(|step| *step == creep.pos) {
+                    path.steps.drain(..=index);
+                }
+                let on_track = path
+                    .steps
+                    .front()
+                    .is_some_and(|step| step.get_range_to(creep.pos) == 1);
+                (on_track && path.stuck < STUCK_TICKS).then_some(path)
+            });
+
+        if path.is_none() {
+            let steps = self.search(world, creep, target, range, stuck >= STUCK_TICKS);
+            if steps.is_none() {
+                debug!("{} found no path to {}", creep.name, target);
+            }
+            path = steps.map(|steps| CachedPath {
+                target,
+                range,
+                steps,
+                last_pos: creep.pos,
+                last_tick: time,
+                stuck: 0,
+            });
+        }
+        let mut path = path.ok_or(ErrorCode::NoPath)?;
+        let next = *path.steps.front().ok_or(ErrorCode::NoPath)?;
+        path.last_pos = creep.pos;
+        path.last_tick = time;
+        self.paths.insert(creep.name.clone(), path);
+        self.traffic.request(
+            time,
+            &creep.name,
+            MoveIntent {
+                from: creep.pos,
+                to: next,
+                priority,
+            },
+        );
+        Ok(())
+    }
+
+    fn run_traffic(&mut self, world: &dyn World) {
+        let time = world.time();
+        let creeps = world.creeps();
+        let matrices = &self.matrices;
+        let moves = self
+            .traffic
+            .resolve(time, &creeps, |pos| matrices.walkable(pos));
+        for (name, direction) in moves {
+            if let Err(e) = world.move_direction(&name, direction) {
+                debug!("{} can't move {:?}: {:?}", name, direction, e);
+            }
+        }
+        self.paths
+            .retain(|_, path| path.last_tick + PATH_TTL > time);
+    }
+
+    // the tiles from the creep to the target, other creeps are in the way when
+    // `around_creeps`. targets in other rooms are reached over the rooms of
+    // the route there.
+    fn search(
+        &mut self,
+        world: &dyn World,
+        creep: &CreepInfo,
+        target: Position,
+        range: u32,
+        around_creeps: bool,
+    ) -> Option<VecDeque<Position>> {
+        let from = creep.pos.room_name();
+        let route = pathfinder::find_route(from, target.room_name(), |room_name| {
+            room_cost(world, room_name, target.room_name())
+        })?;
+        let matrices = &mut self.matrices;
+        let result = pathfinder::search(
+            creep.pos,
+            target,
+            range,
+            &SearchOptions::default(),
+            |room_name| {
+                if !route.contains(&room_name) {
+                    return None;
+                }
+                let mut costs = matrices.room_costs(world, room_name, around_creeps)?;
+                if route.len() == 1 {
+                    pathfinder::block_exits(&mut costs.matrix);
+                }
+                Some(costs)
+            },
+        );
+        if result.incomplete {
+            return None;
+        }
+        Some(result.path.into_iter().collect())
+    }
+}
+
+// what crossing a room costs on a route, rooms others own are only entered
+// when they are the target
+fn room_cost(world: &dyn World, room_name: RoomName, target: RoomName) -> Option<u32> {
+    let owned = world
+        .controller(room_name)
+        .is_some_and(|controller| !controller.my && controller.level > 0);
+    (!owned || room_name == target).then_some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        task::Task,
+        world::mock::{position, MockAction, MockWorld},
+        zerg::CreepMemory,
+    };
+    use screeps::{Direction, Part};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> MockWorld {
+        let world = MockWorld::new();
+        world.add_room(ROOM, 300, 300);
+        world
+    }
+
+    fn add_creep(world: &MockWorld, name: &str, x: u8, y: u8, task: Option<Task>) -> CreepInfo {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: String::new(),
+                role: "worker".to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task,
+            },
+        );
+        world.creep(name).unwrap()
+    }
+
+    fn step(world: &MockWorld, creep: &str, x: u8, y: u8, priority: MovePriority) {
+        let creep = world.creep(creep).unwrap();
+        travel(world, &creep, position(ROOM, x, y), 0, priority).unwrap();
+    }
+
+    fn moves(world: &MockWorld) -> Vec<MockAction> {
+        run_traffic(world);
+        world.take_actions()
+    }
+
+    fn corridor(world: &MockWorld) {
+        for x in 5..15 {
+            world.set_terrain(position(ROOM, x, 9), 1);
+            world.set_terrain(position(ROOM, x, 11), 1);
+        }
+    }
+
+    fn move_action(creep: &str, direction: Direction) -> MockAction {
+        MockAction::Move {
+            creep: creep.to_string(),
+            direction,
+        }
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let world = setup();
+        for y in 8..=12 {
+            world.set_terrain(position(ROOM, 11, y), 1);
+        }
+        add_creep(&world, "creep", 10, 10, None);
+        step(&world, "creep", 20, 10, MovePriority::Normal);
+        let actions = moves(&world);
+        assert!(
+            matches!(actions.as_slice(), [MockAction::Move { direction, .. }]
+            if !matches!(direction, Direction::TopRight | Direction::Right | Direction::BottomRight)),
+            "{:?}",
+            actions
+        );
+    }
+
+    #[test]
+    fn creeps_heading_for_each_other_swap() {
+        let world = setup();
+        corridor(&world);
+        add_creep(&world, "east", 10, 10, None);
+        add_creep(&world, "west", 11, 10, None);
+        step(&world, "east", 13, 10, MovePriority::Normal);
+        step(&world, "west", 8, 10, MovePriority::Normal);
+        assert_eq!(
+            moves(&world),
+            vec![
+                move_action("east", Direction::Right),
+                move_action("west", Direction::Left)
+            ]
+        );
+    }
+
+    #[test]
+    fn idle_creeps_are_shoved_aside() {
+        let world = setup();
+        corridor(&world);
+        add_creep(&world, "creep", 10, 10, None);
+        add_creep(&world, "idle", 11, 10, None);
+        step(&world, "creep", 13, 10, MovePriority::Low);
+        // the only way out of the corridor is back where the creep came from
+        assert_eq!(
+            moves(&world),
+            vec![
+                move_action("creep", Direction::Right),
+                move_action("idle", Direction::Left)
+            ]
+        );
+    }
+
+    #[test]
+    fn higher_priority_gets_the_tile() {
+        let world = setup();
+        corridor(&world);
+        add_creep(&world, "hauler", 10, 10, None);
+        add_creep(&world, "defender", 12, 10, None);
+        step(&world, "hauler", 13, 10, MovePriority::Normal);
+        step(&world, "defender", 8, 10, MovePriority::High);
+        assert_eq!(
+            moves(&world),
+            vec![move_action("defender", Direction::Left)]
+        );
+    }
+
+    #[test]
+    fn stuck_creeps_walk_around_creeps_at_work() {
+        let world = setup();
+        // a gap in the wall, the way around is longer
+        for y in 6..=14 {
+            if y != 10 {
+                world.set_terrain(position(ROOM, 11, y), 1);
+            }
+        }
+        add_creep(&world, "creep", 10, 10, None);
+        // a miner on its container doesn't make way
+        let spot = position(ROOM, 11, 10);
+        add_creep(&world, "miner", 11, 10, Some(Task::go_to(spot, 0)));
+        for _ in 0..STUCK_TICKS {
+            step(&world, "creep", 13, 10, MovePriority::Normal);
+            assert!(moves(&world).is_empty());
+            world.state.borrow_mut().time += 1;
+        }
+        step(&world, "creep", 13, 10, MovePriority::Normal);
+        let actions = moves(&world);
+        assert!(
+            matches!(actions.as_slice(), [MockAction::Move { direction, .. }]
+            if *direction != Direction::Right),
+            "{:?}",
+            actions
+        );
+    }
+}
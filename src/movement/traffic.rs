@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+
+use screeps::{Direction, Part, Position};
+
+use crate::world::CreepInfo;
+
+const DIRECTIONS: [Direction; 8] = [
+    Direction::Top,
+    Direction::TopRight,
+    Direction::Right,
+    Direction::BottomRight,
+    Direction::Bottom,
+    Direction::BottomLeft,
+    Direction::Left,
+    Direction::TopLeft,
+];
+
+// which creep gets a contested tile first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MovePriority {
+    // on the way to work that can wait a tick
+    Low,
+    Normal,
+    // fights and heals
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveIntent {
+    pub from: Position,
+    pub to: Position,
+    pub priority: MovePriority,
+}
+
+// the steps creeps asked for this tick. they are only taken once every
+// overlord ran, so a creep in the way can be swapped with or shoved aside.
+#[derive(Default)]
+pub struct Traffic {
+    tick: u32,
+    intents: HashMap<String, MoveIntent>,
+}
+
+impl Traffic {
+    // a later request of the same creep replaces the earlier one
+    pub fn request(&mut self, tick: u32, creep: &str, intent: MoveIntent) {
+        if tick != self.tick {
+            self.intents.clear();
+            self.tick = tick;
+        }
+        self.intents.insert(creep.to_string(), intent);
+    }
+
+    // settles who steps where, by priority and then by name. `creeps` are all
+    // of our creeps, `walkable` tells the tiles a creep may be shoved to.
+    // creeps heading for each other's tile swap, creeps that don't move on
+    // their own make way if they stay in range of their task, everyone else
+    // waits for the tile to clear.
+    pub fn resolve(
+        &mut self,
+        tick: u32,
+        creeps: &[CreepInfo],
+        walkable: impl Fn(Position) -> bool,
+    ) -> Vec<(String, Direction)> {
+        let intents = std::mem::take(&mut self.intents);
+        if tick != self.tick {
+            return Vec::new();
+        }
+        let occupants: HashMap<Position, &CreepInfo> = creeps
+            .iter()
+            .filter(|creep| !creep.spawning)
+            .map(|creep| (creep.pos, creep))
+            .collect();
+        let mut order: Vec<(&String, &MoveIntent)> = intents.iter().collect();
+        order.sort_by(|a, b| b.1.priority.cmp(&a.1.priority).then_with(|| a.0.cmp(b.0)));
+
+        let mut claimed: HashSet<Position> = HashSet::new();
+        let mut moves: HashMap<String, (Position, Position)> = HashMap::new();
+        for (name, intent) in order {
+            if moves.contains_key(name) || claimed.contains(&intent.to) {
+                continue;
+            }
+            let occupant = occupants
+                .get(&intent.to)
+                .filter(|occupant| occupant.name != *name && !moves.contains_key(&occupant.name));
+            if let Some(occupant) = occupant {
+                match intents.get(&occupant.name) {
+                    Some(other) if other.to == intent.from && !claimed.contains(&intent.from) => {
+                        claimed.insert(intent.from);
+                        moves.insert(occupant.name.clone(), (intent.to, intent.from));
+                    }
+                    // it leaves the tile, unless it is held up itself
+                    Some(_) => {}
+                    None => match shove_tile(occupant, intent, &claimed, &occupants, &walkable) {
+                        Some(tile) => {
+                            claimed.insert(tile);
+                            moves.insert(occupant.name.clone(), (intent.to, tile));
+                        }
+                        None => continue,
+                    },
+                }
+            }
+            claimed.insert(intent.to);
+            moves.insert(name.clone(), (intent.from, intent.to));
+        }
+
+        let mut moves: Vec<(String, Direction)> = moves
+            .into_iter()
+            .filter_map(|(name, (from, to))| Some((name, from.get_direction_to(to)?)))
+            .collect();
+        moves.sort_by(|a, b| a.0.cmp(&b.0));
+        moves
+    }
+}
+
+// a tile next to `creep` it can step aside to without leaving the range of its
+// task. the tile the pusher leaves comes first, so a creep in a corridor ends
+// up behind it.
+fn shove_tile(
+    creep: &CreepInfo,
+    pusher: &MoveIntent,
+    claimed: &HashSet<Position>,
+    occupants: &HashMap<Position, &CreepInfo>,
+    walkable: &impl Fn(Position) -> bool,
+) -> Option<Position> {
+    if creep.fatigue > 0 || creep.count_parts(Part::Move) == 0 {
+        return None;
+    }
+    let task = creep
+        .memory
+        .as_ref()
+        .and_then(|memory| memory.task.as_ref());
+    let in_range = |tile: Position| {
+        task.is_none_or(|task| {
+            task.pos.room_name() != tile.room_name() || tile.get_range_to(task.pos) <= task.range()
+        })
+    };
+    let neighbours = DIRECTIONS
+        .iter()
+        .filter_map(|direction| creep.pos.checked_add_direction(*direction).ok());
+    std::iter::once(pusher.from)
+        .chain(neighbours.filter(|tile| !occupants.contains_key(tile) && walkable(*tile)))
+        .find(|tile| !claimed.contains(tile) && in_range(*tile))
+}
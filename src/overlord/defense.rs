@@ -0,0 +1,286 @@
+use log::{debug, warn};
+use screeps::{Part, Position, RoomName, ATTACK_POWER};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    constants::TOP_MOST_PRIORITY,
+    creep_setup::creep_setup_templates::CreepSetupTemplate,
+    error::SwarmError,
+    hive::{Hive, HostileRole, ThreatLevel},
+    task::{do_task, Task},
+    world::{CreepInfo, HostileCreepInfo, World},
+};
+
+use super::{release_creep, retain_own_creeps, Overlord, OverlordType};
+
+// one DefenseOverlord per colony. it spawns defenders ahead of everything else
+// while the threat assessor finds hostiles the towers can't hold, and sends
+// them at the hostile the towers focus on. once the room is clear the defenders
+// go back to the idle pool, where the next attack finds them.
+pub struct DefenseOverlord {
+    overlord_type: OverlordType,
+    world: Rc<dyn World>,
+    hive: Rc<RefCell<Hive>>,
+    creeps: Vec<String>,
+    room_name: RoomName,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DefenseOverlordCache {
+    creeps: Vec<String>,
+}
+
+impl DefenseOverlord {
+    pub fn new(world: Rc<dyn World>, hive: Rc<RefCell<Hive>>) -> Result<Box<Self>, SwarmError> {
+        Self::new_internal(world, hive, &[])
+    }
+
+    pub fn new_from_cache(
+        world: Rc<dyn World>,
+        cache: &str,
+        hive: Rc<RefCell<Hive>>,
+    ) -> Result<Box<Self>, SwarmError> {
+        let overlord_cache = serde_json::from_str::<DefenseOverlordCache>(cache).map_err(|e| {
+            warn!("Parse overlord cache failed. {:?}", e);
+            SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
+        })?;
+        Self::new_internal(world, hive, &overlord_cache.creeps)
+    }
+
+    fn new_internal(
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cached_creeps: &[String],
+    ) -> Result<Box<Self>, SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let name = Self::get_name_by_room(room_name);
+        let mut creeps: Vec<String> = cached_creeps
+            .iter()
+            .filter(|creep_name| world.creep(creep_name).is_some())
+            .cloned()
+            .collect();
+        for creep in world.creeps() {
+            if !creeps.contains(&creep.name)
+                && creep.memory.as_ref().map(|memory| memory.overlord == name) == Some(true)
+            {
+                creeps.push(creep.name);
+            }
+        }
+
+        let mut overlord = Box::new(DefenseOverlord {
+            overlord_type: OverlordType::Defense,
+            world,
+            hive,
+            creeps,
+            room_name,
+        });
+        overlord.refresh()?;
+        debug!("initialize defense overlord done. {}", room_name);
+        Ok(overlord)
+    }
+
+    pub fn get_name_by_room(room_name: RoomName) -> String {
+        format!("defense-{}", room_name)
+    }
+
+    fn defenders(&self) -> Vec<CreepInfo> {
+        let mut defenders: Vec<CreepInfo> = self
+            .creeps
+            .iter()
+            .filter_map(|creep_name| self.world.creep(creep_name))
+            .collect();
+        defenders.sort_by(|a, b| a.name.cmp(&b.name));
+        defenders
+    }
+
+    fn maintain_creep(&self, alive: usize) {
+        let room = match self.world.room(self.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        let template = CreepSetupTemplate::defender();
+        let damage = template
+            .generate(&room)
+            .map(|setup| setup.body.iter().filter(|part| **part == Part::Attack).count() as u32)
+            .unwrap_or(1)
+            * ATTACK_POWER;
+        let mut hive = self.hive.as_ref().borrow_mut();
+        let count = hive.threats.defenders_needed(damage);
+        let site = hive.threats.threats().first().map(|threat| threat.creep.pos);
+        hive.hatcherys.request_creeps(
+            template,
+            count,
+            alive,
+            self.get_name(),
+            TOP_MOST_PRIORITY,
+            site,
+        );
+    }
+
+    // the hostile the towers focus on, else the closest one that can do harm
+    fn target(&self, pos: Position) -> Option<HostileCreepInfo> {
+        let hive = self.hive.as_ref().borrow();
+        if let Some(target) = hive.towers.focus_target() {
+            return Some(target.clone());
+        }
+        hive.threats
+            .threats()
+            .iter()
+            .filter(|threat| threat.role != HostileRole::Scout)
+            .min_by_key(|threat| (threat.creep.pos.get_range_to(pos), threat.creep.id))
+            .map(|threat| threat.creep.clone())
+    }
+
+    fn run_defender(&self, creep: &CreepInfo) {
+        let target = match self.target(creep.pos) {
+            Some(target) => target,
+            None => return,
+        };
+        if let Err(e) = do_task(self.world.as_ref(), creep, Task::attack(target.id, target.pos)) {
+            warn!("overlord:run_defender: unexpected error: {:?}", e);
+        }
+    }
+}
+
+impl Overlord for DefenseOverlord {
+    fn run(&self) -> Result<(), SwarmError> {
+        let defenders = self.defenders();
+        if self.hive.as_ref().borrow().threats.level() == ThreatLevel::None {
+            for creep in defenders.iter() {
+                release_creep(self.world.as_ref(), &creep.name);
+            }
+            return Ok(());
+        }
+        self.maintain_creep(defenders.len());
+        for creep in defenders.iter().filter(|creep| !creep.spawning) {
+            self.run_defender(creep);
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), SwarmError> {
+        let name = self.get_name();
+        retain_own_creeps(self.world.as_ref(), &name, &mut self.creeps);
+        Ok(())
+    }
+
+    fn assign_creep(&mut self, creep_name: String) {
+        if self.creeps.contains(&creep_name) {
+            return;
+        }
+        self.creeps.push(creep_name);
+    }
+
+    fn get_name(&self) -> String {
+        Self::get_name_by_room(self.room_name)
+    }
+
+    fn get_type(&self) -> OverlordType {
+        self.overlord_type
+    }
+
+    fn get_cache(&self) -> String {
+        let cache = DefenseOverlordCache {
+            creeps: self.creeps.clone(),
+        };
+        serde_json::to_string(&cache).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::creep_setup_templates::ROLE_DEFENDER,
+        movement,
+        world::mock::{position, MockAction, MockWorld},
+        zerg::CreepMemory,
+    };
+    use screeps::Direction;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (Rc<MockWorld>, Rc<RefCell<Hive>>) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 550, 550);
+        world.add_controller(position(ROOM, 10, 10), 2);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        let hive = Hive::new(world.clone(), RoomName::new(ROOM).unwrap()).unwrap();
+        (world, hive)
+    }
+
+    fn add_defender(world: &MockWorld, overlord: &mut DefenseOverlord, name: &str, x: u8, y: u8) {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Tough, Part::Attack, Part::Move, Part::Move],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: ROLE_DEFENDER.to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        overlord.assign_creep(name.to_string());
+    }
+
+    #[test]
+    fn no_defenders_in_peace() {
+        let (world, hive) = setup();
+        let overlord = DefenseOverlord::new(world.clone(), hive.clone()).unwrap();
+        overlord.run().unwrap();
+        assert!(hive.as_ref().borrow().hatcherys.spawn_queue().is_empty());
+    }
+
+    #[test]
+    fn hostiles_without_towers_bring_defenders_first() {
+        let (world, hive) = setup();
+        world.add_hostile_creep("Invader", position(ROOM, 40, 40), &[Part::Attack, Part::Move]);
+        hive.as_ref().borrow_mut().refresh().unwrap();
+        let overlord = DefenseOverlord::new(world.clone(), hive.clone()).unwrap();
+        overlord.run().unwrap();
+
+        let hive = hive.as_ref().borrow();
+        let request = hive.hatcherys.spawn_queue().peek().unwrap();
+        assert_eq!(request.setup.role, ROLE_DEFENDER);
+        assert_eq!(request.priority, TOP_MOST_PRIORITY);
+    }
+
+    #[test]
+    fn defenders_close_in_and_attack() {
+        let (world, hive) = setup();
+        let invader = world.add_hostile_creep("Invader", position(ROOM, 40, 40), &[Part::Work, Part::Move]);
+        hive.as_ref().borrow_mut().refresh().unwrap();
+        let mut overlord = DefenseOverlord::new(world.clone(), hive.clone()).unwrap();
+        add_defender(&world, &mut overlord, "far", 20, 20);
+        add_defender(&world, &mut overlord, "near", 39, 40);
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert_eq!(
+            world.take_actions(),
+            vec![
+                MockAction::Attack {
+                    creep: "near".to_string(),
+                    target: invader
+                },
+                MockAction::Move {
+                    creep: "far".to_string(),
+                    direction: Direction::BottomRight
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn defenders_are_released_once_the_room_is_clear() {
+        let (world, hive) = setup();
+        let mut overlord = DefenseOverlord::new(world.clone(), hive.clone()).unwrap();
+        add_defender(&world, &mut overlord, "defender", 20, 20);
+        overlord.run().unwrap();
+        overlord.refresh().unwrap();
+        assert!(overlord.defenders().is_empty());
+        assert_eq!(world.creep("defender").unwrap().memory.unwrap().overlord, "");
+    }
+}
@@ -1,291 +1,1063 @@
-use log::{error, warn, debug};
+use log::{debug, warn};
 use screeps::{
-    find, game, ConstructionSite, Creep, ErrorCode, HasPosition, HasTypedId, MoveToOptions,
-    ObjectId, PolyStyle, Position, ResourceType, Room, SharedCreepProperties, Source,
-    StructureController, StructureExtension, StructureObject, Transferable, TransferableObject,
+    ErrorCode, Part, Position, RawObjectId, ResourceType, RoomName, StructureType, Terrain,
+    ENERGY_REGEN_TIME, HARVEST_POWER, SOURCE_ENERGY_CAPACITY,
 };
 use serde::{Deserialize, Serialize};
-use std::{borrow::BorrowMut, cell::RefCell, rc::Rc, sync::Arc};
-use wasm_bindgen::{JsCast, JsValue};
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    colony::Colony, creep_setup::creep_setup_templates::CreepSetupTemplate, error::SwarmError,
-    hive::Hive, zerg::CreepMemory,
+    colony::ColonyStage,
+    constants::{DEFAULT_PRIORITY, HIGH_PRIORITY},
+    creep_setup::{
+        creep_setup_templates::{CreepSetupTemplate, ROLE_HAULER, ROLE_MINER},
+        CreepTemplate,
+    },
+    error::SwarmError,
+    hive::{Hive, LogisticsEntry, LogisticsTarget, REPAIR_DECAY},
+    task::{do_task, Task},
+    world::{CreepInfo, DroppedResourceInfo, StructureInfo, World},
+    zerg::{run_state_machine, EnergyCycle, MinerState},
 };
 
-use super::{Overlord, OverlordType};
+use super::{
+    retain_own_creeps, upgrade::UpgradeOverlord, work::WorkOverlord, Overlord, OverlordType,
+};
+
+// containers are planned from this level on. at RCL 1 every bit of energy
+// goes into the controller instead.
+const CONTAINER_MINING_RCL: u8 = 2;
+// a link this close to the source is filled by the miner
+const SOURCE_LINK_RANGE: u32 = 2;
+// dropped energy this close to the source belongs to the mine
+const DROPPED_ENERGY_RANGE: u32 = 2;
 
-const MINER_MINING: &'static str = "mining";
-const MINER_TRANSFERING: &'static str = "transfering";
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MiningMode {
+    // drones harvest and carry the energy themselves
+    Drone,
+    // a static miner drops the energy next to the source until the container is built
+    Drop,
+    // a static miner sits on the container, the haulers of the colony carry the energy away
+    Container,
+    // a static miner fills a link, the hive sends the energy on
+    Link,
+}
 
 // one MineOverlord instance controls one source
 pub struct MineOverlord {
     overlord_type: OverlordType,
-    source: Source,
+    world: Rc<dyn World>,
+    source_id: RawObjectId,
+    source_pos: Position,
     hive: Rc<RefCell<Hive>>,
-    creeps: Vec<Creep>,
-    room: Room,
+    creeps: Vec<String>,
+    room_name: RoomName,
+    rcl: u8,
+    mode: MiningMode,
+    container: Option<StructureInfo>,
+    link: Option<StructureInfo>,
+    // the tile the static miner works from, next to the source
+    mining_pos: Option<Position>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct MineOverlordCache {
-    source_id: ObjectId<Source>,
-    // todo(sheep): cache creeps
+    source_id: RawObjectId,
+    creeps: Vec<String>,
 }
 
 impl MineOverlord {
     pub fn new(
-        source_id: ObjectId<Source>,
+        world: Rc<dyn World>,
+        source_id: RawObjectId,
         hive: Rc<RefCell<Hive>>,
     ) -> Result<Box<Self>, SwarmError> {
-        Self::new_internal(source_id, hive)
+        Self::new_internal(world, source_id, hive, &[])
     }
 
-    // pub fn new_from_cache(
-    //     cache: &String,
-    //     hive: Rc<RefCell<Hive>>,
-    // ) -> Result<Box<Self>, SwarmError> {
-    //     let overlord_cache = serde_json::from_str::<MineOverlordCache>(cache).map_err(|e| {
-    //         warn!("Parse overlord cache failed");
-    //         SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
-    //     })?;
-    //     Self::new_internal(overlord_cache.source_id, hive)
-    // }
+    pub fn new_from_cache(
+        world: Rc<dyn World>,
+        cache: &str,
+        hive: Rc<RefCell<Hive>>,
+    ) -> Result<Box<Self>, SwarmError> {
+        let overlord_cache = serde_json::from_str::<MineOverlordCache>(cache).map_err(|e| {
+            warn!("Parse overlord cache failed. {:?}", e);
+            SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
+        })?;
+        Self::new_internal(world, overlord_cache.source_id, hive, &overlord_cache.creeps)
+    }
 
     fn new_internal(
-        source_id: ObjectId<Source>,
+        world: Rc<dyn World>,
+        source_id: RawObjectId,
         hive: Rc<RefCell<Hive>>,
+        cached_creeps: &[String],
     ) -> Result<Box<Self>, SwarmError> {
-        let room = hive.as_ref().borrow().hatcherys.room.clone();
-        let creeps = Self::initialize_creeps(Self::get_name_internal(&room, &source_id));
-        let source = game::get_object_by_id_typed(&source_id).ok_or(
-            SwarmError::InternalAssertionFailed("get source failed".to_string()),
-        )?;
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let creeps = Self::initialize_creeps(
+            world.as_ref(),
+            Self::get_name_internal(room_name, source_id),
+            cached_creeps,
+        );
+        let source = world
+            .sources(room_name)
+            .into_iter()
+            .find(|source| source.id == source_id)
+            .ok_or(SwarmError::InternalAssertionFailed(
+                "get source failed".to_string(),
+            ))?;
 
-        debug!("initialize mine overlord done. source id: {}", source_id.to_u128());
+        debug!("initialize mine overlord done. source id: {}", source_id);
 
-        Ok(Box::new(MineOverlord {
+        let mut overlord = Box::new(MineOverlord {
             overlord_type: OverlordType::Mine,
-            source: source,
-            hive: hive,
-            creeps: creeps,
-            room: room,
-        }))
+            world,
+            source_id,
+            source_pos: source.pos,
+            hive,
+            creeps,
+            room_name,
+            rcl: 0,
+            mode: MiningMode::Drone,
+            container: None,
+            link: None,
+            mining_pos: None,
+        });
+        overlord.refresh_structures();
+        Ok(overlord)
+    }
+
+    pub fn get_name_by_source(room_name: RoomName, source_id: RawObjectId) -> String {
+        Self::get_name_internal(room_name, source_id)
+    }
+
+    fn get_name_internal(room_name: RoomName, source_id: RawObjectId) -> String {
+        format!("mine-{}-{}", room_name, u128::from(source_id))
     }
 
-    fn get_name_internal(room: &Room, source_id: &ObjectId<Source>) -> String {
-        format!("mine-{}-{}", room.name().to_string(), source_id.to_u128())
+    pub fn mode(&self) -> MiningMode {
+        self.mode
+    }
+
+    // creeps remembered by the cache are resolved by name, the rest are creeps
+    // spawned for us since the cache was written. haulers belong to the
+    // transport overlord now.
+    fn initialize_creeps(world: &dyn World, name: String, cached_creeps: &[String]) -> Vec<String> {
+        let mut creeps: Vec<String> = cached_creeps
+            .iter()
+            .filter(|creep_name| {
+                world.creep(creep_name).is_some_and(|creep| {
+                    creep.memory.map(|memory| memory.role != ROLE_HAULER) == Some(true)
+                })
+            })
+            .cloned()
+            .collect();
+
+        for creep in world.creeps() {
+            if cached_creeps.contains(&creep.name) {
+                continue;
+            }
+            if creep
+                .memory
+                .map(|memory| memory.overlord == name && memory.role != ROLE_HAULER)
+                == Some(true)
+            {
+                creeps.push(creep.name);
+            }
+        }
+        creeps
+    }
+
+    // the mining mode follows the structures around the source and the stage of
+    // the colony: a link once the colony has a receiving link, else the container,
+    // else dropping next to the source until the container is built.
+    fn refresh_structures(&mut self) {
+        self.rcl = self
+            .world
+            .controller(self.room_name)
+            .map(|controller| controller.level)
+            .unwrap_or(0);
+        let source_pos = self.source_pos;
+        self.container = self
+            .world
+            .structures(self.room_name)
+            .into_iter()
+            .find(|structure| {
+                structure.structure_type == StructureType::Container
+                    && structure.pos.get_range_to(source_pos) <= 1
+            });
+        self.link = {
+            let hive = self.hive.as_ref().borrow();
+            hive.links
+                .source_links()
+                .iter()
+                .find(|link| link.pos.get_range_to(source_pos) <= SOURCE_LINK_RANGE)
+                .filter(|_| hive.links.has_receiver())
+                .cloned()
+        };
+
+        let stage = ColonyStage::from_rcl(self.rcl);
+        self.mode = if self.link.is_some() && stage >= ColonyStage::WithStorage {
+            MiningMode::Link
+        } else if self.container.is_some() {
+            MiningMode::Container
+        } else if self.rcl >= CONTAINER_MINING_RCL {
+            MiningMode::Drop
+        } else {
+            MiningMode::Drone
+        };
+        self.mining_pos = self.find_mining_pos();
+        self.post_logistics();
     }
 
-    fn initialize_creeps(name: String) -> Vec<Creep> {
-        game::creeps()
-            .values()
-            .filter(|creep| CreepMemory::from_value(creep.memory()).overlord == name)
+    fn find_mining_pos(&self) -> Option<Position> {
+        match self.mode {
+            MiningMode::Drone => None,
+            MiningMode::Container => self.container.as_ref().map(|container| container.pos),
+            // where the container is going to be
+            MiningMode::Drop => self
+                .world
+                .construction_sites(self.room_name)
+                .into_iter()
+                .find(|site| {
+                    site.structure_type == StructureType::Container
+                        && site.pos.get_range_to(self.source_pos) <= 1
+                })
+                .map(|site| site.pos),
+            // next to both the source and the link, on the container if it is
+            MiningMode::Link => {
+                let link_pos = self.link.as_ref()?.pos;
+                if let Some(container) = self
+                    .container
+                    .as_ref()
+                    .filter(|container| container.pos.get_range_to(link_pos) <= 1)
+                {
+                    return Some(container.pos);
+                }
+                let structures = self.world.structures(self.room_name);
+                self.tiles_next_to_source()
+                    .into_iter()
+                    .filter(|pos| pos.get_range_to(link_pos) <= 1)
+                    .find(|pos| {
+                        !structures.iter().any(|structure| {
+                            structure.pos == *pos
+                                && !matches!(
+                                    structure.structure_type,
+                                    StructureType::Road
+                                        | StructureType::Container
+                                        | StructureType::Rampart
+                                )
+                        })
+                    })
+            }
+        }
+    }
+
+    // tiles around the source that are not walls, sorted by y then x
+    fn tiles_next_to_source(&self) -> Vec<Position> {
+        let terrain = match self.world.terrain(self.room_name) {
+            Some(terrain) => terrain,
+            None => return Vec::new(),
+        };
+        let mut tiles = Vec::new();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                if let Ok(pos) = self.source_pos.checked_add((dx, dy)) {
+                    if terrain.get(pos.xy()) != Terrain::Wall {
+                        tiles.push(pos);
+                    }
+                }
+            }
+        }
+        tiles
+    }
+
+    fn creeps_of_role(&self, role: &str) -> Vec<CreepInfo> {
+        self.creeps
+            .iter()
+            .filter_map(|creep_name| self.world.creep(creep_name))
+            .filter(|creep| creep.memory.as_ref().map(|memory| memory.role.as_str()) == Some(role))
             .collect()
     }
 
     fn maintain_creep(&self) {
-        const miner_count: usize = 1;
-        if self.creeps.len() >= miner_count {
+        match self.mode {
+            MiningMode::Drone => {
+                self.request_creeps(CreepSetupTemplate::drone(), 1, DEFAULT_PRIORITY);
+            }
+            // haulers come from the transport overlord, see `post_logistics`
+            MiningMode::Drop | MiningMode::Container | MiningMode::Link => {
+                self.request_creeps(CreepSetupTemplate::static_miner(), 1, HIGH_PRIORITY);
+                if !self.drones_retired() {
+                    self.request_creeps(CreepSetupTemplate::drone(), 1, DEFAULT_PRIORITY);
+                }
+            }
+        }
+    }
+
+    // a drone carries the energy of a static miner until the upgraders take
+    // over. the drones left keep working until they expire.
+    fn drones_retired(&self) -> bool {
+        let hive = self.hive.as_ref().borrow();
+        UpgradeOverlord::has_supplied_upgrader(self.world.as_ref(), &hive, self.room_name)
+    }
+
+    fn request_creeps(&self, template: CreepTemplate, count: usize, priority: u32) {
+        let alive = self.creeps_of_role(&template.role).len();
+        self.hive.as_ref().borrow_mut().hatcherys.request_creeps(
+            template,
+            count,
+            alive,
+            self.get_name(),
+            priority,
+            Some(self.source_pos),
+        )
+    }
+
+    // enough carry capacity to move a full source worth of energy to the spawn and back
+    fn carry_demand(&self) -> u32 {
+        let pickup_pos = self.mining_pos.unwrap_or(self.source_pos);
+        let distance = self
+            .world
+            .spawns(self.room_name)
+            .iter()
+            .map(|spawn| spawn.pos.get_range_to(pickup_pos))
+            .min()
+            .unwrap_or(0);
+        let energy_per_tick = SOURCE_ENERGY_CAPACITY / ENERGY_REGEN_TIME;
+        energy_per_tick * 2 * (distance + 2)
+    }
+
+    // offers the mined energy to the haulers of the colony
+    fn post_logistics(&self) {
+        if self.mode == MiningMode::Drone {
             return;
         }
+        let mut provides = Vec::new();
+        if let Some(container) = &self.container {
+            let energy = container.store.map(|store| store.energy).unwrap_or(0);
+            if energy > 0 {
+                provides.push(LogisticsEntry {
+                    target: LogisticsTarget::Structure(container.id),
+                    pos: container.pos,
+                    resource: ResourceType::Energy,
+                    amount: energy,
+                    priority: DEFAULT_PRIORITY,
+                });
+            }
+        }
+        if let Some(resource) = self.dropped_energy() {
+            provides.push(LogisticsEntry {
+                target: LogisticsTarget::Dropped(resource.id),
+                pos: resource.pos,
+                resource: resource.resource_type,
+                amount: resource.amount,
+                priority: DEFAULT_PRIORITY,
+            });
+        }
 
-        let miner_needs_cnt = miner_count - self.creeps.len();
-        for i in 0..miner_needs_cnt {
-            self.hive.as_ref().borrow_mut().hatcherys.request_for_spawn(
-                CreepSetupTemplate::drone(),
-                self.get_name(),
-                crate::constants::DEFAULT_PRIORITY,
-            )
+        let mut hive = self.hive.as_ref().borrow_mut();
+        for entry in provides {
+            hive.logistics.provide(entry);
+        }
+        if matches!(self.mode, MiningMode::Drop | MiningMode::Container) {
+            hive.logistics.add_carry_demand(self.carry_demand());
         }
     }
 
-    fn run_miner(&self, creep: &Creep) -> Result<(), SwarmError> {
-      if creep.spawning() {
-        return Ok(())
-      }
+    // places a container next to the source, on the tile closest to the spawn
+    fn plan_container(&self) {
+        if self.mode != MiningMode::Drop {
+            return;
+        }
+        let source_pos = self.source_pos;
+        let sites = self.world.construction_sites(self.room_name);
+        if sites.iter().any(|site| {
+            site.structure_type == StructureType::Container && site.pos.get_range_to(source_pos) <= 1
+        }) {
+            return;
+        }
+        let spawn_pos = match self.world.spawns(self.room_name).first() {
+            Some(spawn) => spawn.pos,
+            None => return,
+        };
+        let structures = self.world.structures(self.room_name);
+
+        let mut candidates: Vec<Position> = self
+            .tiles_next_to_source()
+            .into_iter()
+            .filter(|pos| {
+                !structures.iter().any(|structure| {
+                    structure.pos == *pos && structure.structure_type != StructureType::Road
+                }) && !sites.iter().any(|site| site.pos == *pos)
+            })
+            .collect();
+        candidates.sort_by_key(|pos| (pos.get_range_to(spawn_pos), pos.y().u8(), pos.x().u8()));
 
-        let mut memory = CreepMemory::from_value(creep.memory());
-        if memory.state.is_none() {
-            memory.state = Some(MINER_TRANSFERING.to_string());
+        if let Some(pos) = candidates.first() {
+            match self
+                .world
+                .create_construction_site(*pos, StructureType::Container)
+            {
+                Ok(()) => debug!("plan container of {} at {}", self.get_name(), pos),
+                Err(e) => warn!("place container site failed. {} {:?}", self.get_name(), e),
+            }
         }
-        if creep.store().get_used_capacity(None) == 0
-            && memory.state.as_ref().unwrap() == MINER_TRANSFERING
-        {
-            memory.state = Some(MINER_MINING.to_string())
+    }
+
+    fn run_creep(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        if creep.spawning {
+            return Ok(());
         }
-        if creep.store().get_free_capacity(None) == 0
-            && memory.state.as_ref().unwrap() == MINER_MINING
-        {
-            memory.state = Some(MINER_TRANSFERING.to_string())
+        match creep.memory.as_ref().map(|memory| memory.role.as_str()) {
+            Some(ROLE_MINER) => self.run_static_miner(creep),
+            _ => self.run_miner(creep),
         }
+    }
 
-        if memory.state.as_ref().unwrap() == MINER_TRANSFERING {
-            self.run_miner_transfering(creep)?
-        } else if memory.state.as_ref().unwrap() == MINER_MINING {
-            self.run_miner_mining(creep)?
-        } else {
-            error!("invalid miner state {}", memory.state.as_ref().unwrap());
+    // sits on its tile and harvests, what doesn't fit into the creep drops into
+    // the container or onto the ground. in link mode the creep empties itself
+    // into the link before it overflows.
+    fn run_static_miner(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        if let Some(pos) = self.mining_pos {
+            if creep.pos != pos {
+                let task = Task::go_to(pos, 0).then(self.harvest_task());
+                if let Err(e) = do_task(self.world.as_ref(), creep, task) {
+                    warn!("overlord:run_static_miner: unexpected error: {:?}", e);
+                }
+                return Ok(());
+            }
+        }
+        // the container under the miner is repaired with the energy it carries.
+        // these go along with the harvest, they don't replace the task.
+        if let (MiningMode::Container, Some(container)) = (self.mode, &self.container) {
+            if creep.store.energy > 0 && self.hive.as_ref().borrow().repairs.contains(container.id) {
+                match Task::repair(container.id, container.pos).run(self.world.as_ref(), creep) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => warn!("overlord:run_static_miner: unexpected error: {:?}", e),
+                }
+            }
         }
+        if let (MiningMode::Link, Some(link)) = (self.mode, &self.link) {
+            let harvest = creep.count_parts(Part::Work) * HARVEST_POWER;
+            if creep.store.energy > 0 && creep.store.free() < harvest {
+                let task = Task::transfer(link.id, link.pos, ResourceType::Energy);
+                match task.run(self.world.as_ref(), creep) {
+                    // the hive empties the link, the overflow drops meanwhile
+                    Ok(()) | Err(ErrorCode::Full) => {}
+                    Err(e) => warn!("overlord:run_static_miner: unexpected error: {:?}", e),
+                }
+            }
+        }
+        self.run_miner_mining(creep)
+    }
 
-        creep.set_memory(&memory.into_value());
+    // the biggest pile of energy around the source
+    fn dropped_energy(&self) -> Option<DroppedResourceInfo> {
+        self.world
+            .dropped_resources(self.room_name)
+            .into_iter()
+            .filter(|resource| {
+                resource.resource_type == ResourceType::Energy
+                    && resource.pos.get_range_to(self.source_pos) <= DROPPED_ENERGY_RANGE
+            })
+            .max_by_key(|resource| resource.amount)
+    }
 
-        Ok(())
+    fn harvest_task(&self) -> Task {
+        Task::harvest(self.source_id, self.source_pos)
     }
 
-    fn run_miner_transfering(&self, creep: &Creep) -> Result<(), SwarmError> {
-        // try upgrade controller if it's going to downgrade
-        let controller = self.room.controller();
-        if controller.is_some() && controller.as_ref().unwrap().ticks_to_downgrade() < 200 {
-            debug!("ticks to downgrade {} is small, upgrade first", controller.as_ref().unwrap().ticks_to_downgrade());
-            Self::do_upgrade(creep, controller.as_ref().unwrap());
-            return Ok(());
+    fn run_miner(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        let mut creep = creep.clone();
+        let state = run_state_machine(self.world.as_ref(), &mut creep, MinerState::cycle).ok_or(
+            SwarmError::InternalAssertionFailed("parse creep memory failed".to_string()),
+        )?;
+        match state {
+            MinerState::Transfering => self.run_miner_transfering(&creep),
+            MinerState::Mining => self.run_drone_collecting(&creep),
         }
+    }
+
+    // drones take from the container or the dropped energy once there is a
+    // static miner, the source is left to the miner
+    fn run_drone_collecting(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        let container = self
+            .container
+            .as_ref()
+            .filter(|container| container.store.map(|store| store.energy > 0) == Some(true));
+        if container.is_none() {
+            if let Some(resource) = self.dropped_energy() {
+                let task = Task::pickup(resource.id, resource.pos);
+                if let Err(e) = do_task(self.world.as_ref(), creep, task) {
+                    warn!("overlord:run_drone_collecting: unexpected error: {:?}", e);
+                }
+                return Ok(());
+            }
+        }
+        match container {
+            Some(container) => {
+                let task = Task::withdraw(container.id, container.pos, ResourceType::Energy);
+                match do_task(self.world.as_ref(), creep, task) {
+                    // a hauler emptied it earlier this tick
+                    Ok(()) | Err(ErrorCode::NotEnough) => {}
+                    Err(e) => warn!("overlord:run_drone_collecting: unexpected error: {:?}", e),
+                }
+                Ok(())
+            }
+            None => self.run_miner_mining(creep),
+        }
+    }
+
+    fn run_miner_transfering(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        // the upgrade overlord protects the controller from downgrading
+        let controller = self.world.controller(self.room_name);
 
         // first try fill energy
-        if Self::try_fill_energy(creep, &self.room) {
+        if self.try_fill_energy(creep) {
             debug!("fill energy");
             return Ok(());
         }
 
         // then try build
-        if Self::try_build(creep, &self.room) {
+        if self.try_build(creep) {
             debug!("build");
             return Ok(());
         }
 
-        // todo(sheep): repair container
+        // then keep roads and containers from decaying
+        if self.try_repair(creep) {
+            debug!("repair");
+            return Ok(());
+        }
 
         // last, try upgrade
-        if controller.is_some() {
+        if let Some(controller) = controller {
             debug!("do upgrade");
-            Self::do_upgrade(creep, controller.as_ref().unwrap());
+            self.do_task(creep, Task::upgrade(controller.id, controller.pos));
             return Ok(());
         }
 
         Ok(())
     }
 
-    fn run_miner_mining(&self, creep: &Creep) -> Result<(), SwarmError> {
-        let res = creep.harvest(&self.source);
-        if res.is_err() {
-            if res.unwrap_err() == ErrorCode::NotInRange {
-                Self::move_creep(self.source.pos(), creep);
-            } else {
-                warn!(
-                    "overlord:run_miner_mining: unexpected error: {:?}",
-                    res.unwrap_err()
-                );
-            }
+    fn run_miner_mining(&self, creep: &CreepInfo) -> Result<(), SwarmError> {
+        match do_task(self.world.as_ref(), creep, self.harvest_task()) {
+            // the source is drained until it regenerates
+            Ok(()) | Err(ErrorCode::NotEnough) => {}
+            Err(e) => warn!("overlord:run_miner_mining: unexpected error: {:?}", e),
         }
         Ok(())
     }
 
-    fn try_fill_energy(creep: &Creep, room: &Room) -> bool {
-        let targets = room
-            .find(screeps::find::MY_STRUCTURES, None)
-            .into_iter()
-            .filter(|structure| {
-                match structure {
-                    StructureObject::StructureExtension(s) => {
-                        return s.store().get_free_capacity(Some(ResourceType::Energy)) > 0
-                    }
-                    StructureObject::StructureSpawn(s) => {
-                        return s.store().get_free_capacity(Some(ResourceType::Energy)) > 0
-                    }
-                    StructureObject::StructureTower(s) => {
-                        return s.store().get_free_capacity(Some(ResourceType::Energy)) > 0
-                    }
-                    _ => return false,
-                }
+    // the spawns, extensions and towers the logistics network has requests
+    // for, the most urgent first, among equals the closest
+    fn try_fill_energy(&self, creep: &CreepInfo) -> bool {
+        let target = self
+            .hive
+            .as_ref()
+            .borrow()
+            .logistics
+            .requests()
+            .iter()
+            .filter(|entry| entry.resource == ResourceType::Energy)
+            .filter_map(|entry| match entry.target {
+                LogisticsTarget::Structure(id) => Some((entry.priority, id, entry.pos)),
+                // the haulers feed the creeps
+                LogisticsTarget::Dropped(_) | LogisticsTarget::Creep(_) => None,
             })
-            .next();
+            .min_by_key(|(priority, _, pos)| (std::cmp::Reverse(*priority), pos.get_range_to(creep.pos)));
 
-        if targets.is_some() {
-            Self::do_transfer(targets.unwrap().as_transferable().unwrap(), creep);
+        if let Some((_, id, pos)) = target {
+            self.do_task(creep, Task::transfer(id, pos, ResourceType::Energy));
             return true;
         }
-        return false;
+        false
     }
 
-    fn try_build(creep: &Creep, room: &Room) -> bool {
-        let targets = room
-            .find(screeps::find::MY_CONSTRUCTION_SITES, None)
-            .into_iter()
-            .next();
-        if targets.is_some() {
-            Self::do_build(targets.unwrap(), creep);
+    fn try_build(&self, creep: &CreepInfo) -> bool {
+        // the same order the work overlord builds in
+        if let Some((id, pos)) = WorkOverlord::best_site(self.world.as_ref(), self.room_name, creep.pos) {
+            self.do_task(creep, Task::build(id, pos));
             return true;
         }
-        return false;
+        false
     }
 
-    fn move_creep(pos: Position, creep: &Creep) {
-        let path_style = PolyStyle::default().stroke("#ffaa00'");
-        let move_opts = MoveToOptions::default()
-            .visualize_path_style(path_style)
-            .reuse_path(10);
-        let _ = creep.move_to_with_options(pos, Some(move_opts));
+    // walls and ramparts are left to the workers
+    fn try_repair(&self, creep: &CreepInfo) -> bool {
+        let target = self
+            .hive
+            .as_ref()
+            .borrow()
+            .repairs
+            .entries()
+            .iter()
+            .filter(|entry| entry.priority >= REPAIR_DECAY)
+            .min_by_key(|entry| (std::cmp::Reverse(entry.priority), entry.pos.get_range_to(creep.pos)))
+            .cloned();
+        if let Some(target) = target {
+            self.do_task(creep, Task::repair(target.id, target.pos));
+            return true;
+        }
+        false
     }
 
-    fn do_transfer(transferable_structure: &dyn Transferable, creep: &Creep) {
-        let res = creep.transfer(transferable_structure, ResourceType::Energy, None);
-        if res.is_err() {
-            if res.unwrap_err() == ErrorCode::NotInRange {
-                Self::move_creep(transferable_structure.pos(), creep);
-            } else {
-                warn!(
-                    "overlord:do_transfer: unexpected error: {:?}",
-                    res.unwrap_err()
-                );
-            }
+    fn do_task(&self, creep: &CreepInfo, task: Task) {
+        if let Err(e) = do_task(self.world.as_ref(), creep, task) {
+            warn!("overlord:run_miner_transfering: unexpected error: {:?}", e);
         }
     }
+}
 
-    fn do_build(construction_site: ConstructionSite, creep: &Creep) {
-        let res = creep.build(&construction_site);
-        if res.is_err() {
-            if res.unwrap_err() == ErrorCode::NotInRange {
-                Self::move_creep(construction_site.pos(), creep);
-            } else {
-                warn!(
-                    "overlord:do_build: unexpected error: {:?}",
-                    res.unwrap_err()
-                );
+impl Overlord for MineOverlord {
+    fn run(&self) -> Result<(), SwarmError> {
+        self.maintain_creep();
+        self.plan_container();
+        for creep_name in self.creeps.iter() {
+            match self.world.creep(creep_name) {
+                Some(creep) => self.run_creep(&creep)?,
+                None => warn!("creep {} not found", creep_name),
             }
         }
+        Ok(())
     }
 
-    fn do_upgrade(creep: &Creep, controller: &StructureController) {
-        let res = creep.upgrade_controller(controller);
-        if res.is_err() {
-            if res.unwrap_err() == ErrorCode::NotInRange {
-                Self::move_creep(controller.pos(), creep);
-            } else {
-                warn!(
-                    "overlord:do_upgrade: unexpected error: {:?}",
-                    res.unwrap_err()
-                );
-            }
+    fn refresh(&mut self) -> Result<(), SwarmError> {
+        let name = self.get_name();
+        retain_own_creeps(self.world.as_ref(), &name, &mut self.creeps);
+        self.refresh_structures();
+        Ok(())
+    }
+
+    fn assign_creep(&mut self, creep_name: String) {
+        if self.creeps.contains(&creep_name) {
+            return;
         }
+        self.creeps.push(creep_name);
+    }
+
+    fn get_name(&self) -> String {
+        Self::get_name_internal(self.room_name, self.source_id)
+    }
+
+    fn get_type(&self) -> OverlordType {
+        self.overlord_type
+    }
+
+    fn get_cache(&self) -> String {
+        let cache = MineOverlordCache {
+            source_id: self.source_id,
+            creeps: self.creeps.clone(),
+        };
+        serde_json::to_string(&cache).unwrap()
     }
 }
 
-impl Overlord for MineOverlord {
-    fn run(&self) -> Result<(), SwarmError> {
-        self.maintain_creep();
-        for creep in self.creeps.iter() {
-            self.run_miner(creep)?
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::creep_setup_templates::{ROLE_DRONE, ROLE_UPGRADER},
+        movement,
+        world::mock::{position, MockAction, MockWorld},
+        zerg::{CreepMemory, CreepState},
+    };
+    use screeps::Direction;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (Rc<MockWorld>, Box<MineOverlord>, RawObjectId) {
+        setup_at(1)
+    }
+
+    fn setup_at(rcl: u8) -> (Rc<MockWorld>, Box<MineOverlord>, RawObjectId) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), rcl);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        let source_id = world.add_source(position(ROOM, 40, 40));
+        let room_name = RoomName::new(ROOM).unwrap();
+        let hive = Hive::new(world.clone(), room_name).unwrap();
+        let overlord = MineOverlord::new(world.clone(), source_id, hive).unwrap();
+        (world, overlord, source_id)
+    }
+
+    fn add_miner(world: &MockWorld, overlord: &mut MineOverlord, x: u8, y: u8) {
+        world.add_creep(
+            "miner",
+            position(ROOM, x, y),
+            &[Part::Work, Part::Work, Part::Move, Part::Carry],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: "drone".to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        overlord.assign_creep("miner".to_string());
+    }
+
+    #[test]
+    fn requests_drone_when_short_of_creeps() {
+        let (world, overlord, _) = setup();
+        overlord.run().unwrap();
+        overlord.hive.as_ref().borrow_mut().run();
+
+        let actions = world.take_actions();
+        assert!(matches!(
+            actions.as_slice(),
+            [MockAction::SpawnCreep { body, .. }] if body.len() == 4
+        ));
+    }
+
+    #[test]
+    fn empty_miner_walks_to_source_then_harvests() {
+        let (world, mut overlord, source_id) = setup();
+        add_miner(&world, &mut overlord, 20, 20);
+
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::Move {
+                creep: "miner".to_string(),
+                direction: Direction::BottomRight,
+            }]
+        );
+
+        world.state.borrow_mut().creeps.get_mut("miner").unwrap().pos = position(ROOM, 39, 39);
+        overlord.run().unwrap();
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::Harvest {
+                creep: "miner".to_string(),
+                target: source_id,
+            }]
+        );
+        let memory = world.creep("miner").unwrap().memory.unwrap();
+        assert_eq!(memory.state, Some(CreepState::Miner(MinerState::Mining)));
+    }
+
+    #[test]
+    fn full_miner_fills_spawn_first() {
+        let (world, mut overlord, _) = setup();
+        add_miner(&world, &mut overlord, 25, 26);
+        world.set_creep_energy("miner", 50);
+        let spawn_id = world.spawns(RoomName::new(ROOM).unwrap())[0].id;
+        world.state.borrow_mut().rooms.values_mut().for_each(|room| {
+            room.structures[0].store.as_mut().unwrap().used = 200;
+        });
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+
+        overlord.run().unwrap();
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::Transfer {
+                creep: "miner".to_string(),
+                target: spawn_id,
+                resource: ResourceType::Energy,
+            }]
+        );
+    }
+
+    #[test]
+    fn full_miner_fills_the_most_urgent_request() {
+        let (world, mut overlord, _) = setup();
+        add_miner(&world, &mut overlord, 25, 26);
+        world.set_creep_energy("miner", 50);
+        let empty = |capacity| {
+            Some(crate::world::StoreInfo {
+                energy: 0,
+                used: 0,
+                capacity,
+            })
+        };
+        world.add_structure(StructureType::Tower, position(ROOM, 25, 27), empty(1000));
+        let extension =
+            world.add_structure(StructureType::Extension, position(ROOM, 30, 30), empty(50));
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        let actions = world.take_actions();
+        assert!(!actions
+            .iter()
+            .any(|action| matches!(action, MockAction::Transfer { .. })));
+        assert!(matches!(actions.as_slice(), [MockAction::Move { .. }]));
+        let memory = world.creep("miner").unwrap().memory.unwrap();
+        assert_eq!(
+            memory.task,
+            Some(Task::transfer(extension, position(ROOM, 30, 30), ResourceType::Energy))
+        );
+    }
+
+    fn add_creep_with_role(world: &MockWorld, overlord: &mut MineOverlord, role: &str, x: u8, y: u8) {
+        world.add_creep(
+            role,
+            position(ROOM, x, y),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: role.to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        overlord.assign_creep(role.to_string());
+    }
+
+    fn with_container(rcl: u8) -> (Rc<MockWorld>, Box<MineOverlord>, RawObjectId) {
+        let (world, mut overlord, _) = setup_at(rcl);
+        let container = world.add_structure(
+            StructureType::Container,
+            position(ROOM, 39, 39),
+            Some(crate::world::StoreInfo {
+                energy: 500,
+                used: 500,
+                capacity: 2000,
+            }),
+        );
+        overlord.refresh().unwrap();
+        (world, overlord, container)
+    }
+
+    #[test]
+    fn plans_container_on_free_tile_closest_to_spawn() {
+        let (world, overlord, _) = setup_at(2);
+        world.set_terrain(position(ROOM, 39, 39), 1);
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::CreateConstructionSite {
+            pos: position(ROOM, 40, 39),
+            structure_type: StructureType::Container,
+        }));
+    }
+
+    #[test]
+    fn no_container_planned_at_rcl_1() {
+        let (world, overlord, _) = setup();
+        overlord.run().unwrap();
+
+        assert!(!world
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, MockAction::CreateConstructionSite { .. })));
+    }
+
+    #[test]
+    fn container_mode_requests_static_miner_first() {
+        let (world, overlord, _) = with_container(2);
+        assert_eq!(overlord.mode(), MiningMode::Container);
+        overlord.run().unwrap();
+        overlord.hive.as_ref().borrow_mut().run();
+
+        let actions = world.take_actions();
+        assert!(matches!(
+            actions.as_slice(),
+            [MockAction::SpawnCreep { name, .. }] if name.contains(ROLE_MINER)
+        ));
+    }
+
+    #[test]
+    fn static_miner_sits_on_container() {
+        let (world, mut overlord, _) = with_container(2);
+        add_creep_with_role(&world, &mut overlord, ROLE_MINER, 30, 30);
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert!(world.take_actions().contains(&MockAction::Move {
+            creep: ROLE_MINER.to_string(),
+            direction: Direction::BottomRight,
+        }));
+
+        world.state.borrow_mut().creeps.get_mut(ROLE_MINER).unwrap().pos = position(ROOM, 39, 39);
+        overlord.run().unwrap();
+        assert!(world
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, MockAction::Harvest { creep, .. } if creep == ROLE_MINER)));
+    }
+
+    #[test]
+    fn static_miner_repairs_decayed_container() {
+        let (world, mut overlord, container) = with_container(2);
+        add_creep_with_role(&world, &mut overlord, ROLE_MINER, 39, 39);
+        world.set_creep_energy(ROLE_MINER, 50);
+        world.set_structure_hits(container, 100_000, 250_000);
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::Repair {
+            creep: ROLE_MINER.to_string(),
+            target: container,
+        }));
+    }
+
+    #[test]
+    fn container_energy_is_offered_to_haulers() {
+        let (_, overlord, container) = with_container(2);
+        let hive = overlord.hive.as_ref().borrow();
+        assert_eq!(
+            hive.logistics.provides(),
+            &[LogisticsEntry {
+                target: LogisticsTarget::Structure(container),
+                pos: position(ROOM, 39, 39),
+                resource: ResourceType::Energy,
+                amount: 500,
+                priority: DEFAULT_PRIORITY,
+            }]
+        );
+        assert!(hive.logistics.carry_demand() > 0);
+    }
+
+    #[test]
+    fn drop_mode_before_container_is_built() {
+        let (world, mut overlord, _) = setup_at(2);
+        assert_eq!(overlord.mode(), MiningMode::Drop);
+        let pile = world.add_dropped_resource(position(ROOM, 39, 40), 200);
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+        overlord.refresh().unwrap();
+
+        let hive = overlord.hive.as_ref().borrow();
+        assert_eq!(hive.logistics.provides().len(), 1);
+        assert_eq!(
+            hive.logistics.provides()[0].target,
+            LogisticsTarget::Dropped(pile)
+        );
+    }
+
+    fn with_link(rcl: u8) -> (Rc<MockWorld>, Box<MineOverlord>, RawObjectId) {
+        let (world, mut overlord, _) = setup_at(rcl);
+        let store = Some(crate::world::StoreInfo {
+            energy: 0,
+            used: 0,
+            capacity: 800,
+        });
+        let link = world.add_structure(StructureType::Link, position(ROOM, 38, 40), store);
+        world.add_structure(StructureType::Link, position(ROOM, 12, 12), store);
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+        overlord.refresh().unwrap();
+        (world, overlord, link)
+    }
+
+    #[test]
+    fn link_mode_needs_a_storage_stage_colony() {
+        let (_, overlord, _) = with_link(3);
+        assert_eq!(overlord.mode(), MiningMode::Drop);
+        let (_, overlord, _) = with_link(5);
+        assert_eq!(overlord.mode(), MiningMode::Link);
+    }
+
+    #[test]
+    fn link_miner_stands_next_to_source_and_link() {
+        let (world, mut overlord, link) = with_link(5);
+        add_creep_with_role(&world, &mut overlord, ROLE_MINER, 30, 30);
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert!(world.take_actions().contains(&MockAction::Move {
+            creep: ROLE_MINER.to_string(),
+            direction: Direction::BottomRight,
+        }));
+
+        world.state.borrow_mut().creeps.get_mut(ROLE_MINER).unwrap().pos = position(ROOM, 39, 39);
+        world.set_creep_energy(ROLE_MINER, 50);
+        overlord.run().unwrap();
+        let actions = world.take_actions();
+        assert!(actions.contains(&MockAction::Transfer {
+            creep: ROLE_MINER.to_string(),
+            target: link,
+            resource: ResourceType::Energy,
+        }));
+        assert!(actions
+            .iter()
+            .any(|action| matches!(action, MockAction::Harvest { creep, .. } if creep == ROLE_MINER)));
+    }
+
+    #[test]
+    fn link_mode_spawns_no_haulers() {
+        let (world, mut overlord, _) = with_link(5);
+        add_creep_with_role(&world, &mut overlord, ROLE_MINER, 39, 39);
+        add_creep_with_role(&world, &mut overlord, "drone", 20, 20);
+        overlord.run().unwrap();
+        overlord.hive.as_ref().borrow_mut().run();
+
+        assert!(!world
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, MockAction::SpawnCreep { .. })));
+    }
+
+    // a hauler, an upgrader at work and a drone that just died
+    fn drone_requested(world: &MockWorld, overlord: &mut MineOverlord) -> bool {
+        add_creep_with_role(world, overlord, ROLE_MINER, 39, 39);
+        add_creep_with_role(world, overlord, ROLE_DRONE, 20, 20);
+        for (role, other, x) in [(ROLE_HAULER, "transport", 25), (ROLE_UPGRADER, "upgrade", 11)] {
+            world.add_creep(
+                role,
+                position(ROOM, x, 27),
+                &[Part::Work, Part::Carry, Part::Move],
+                CreepMemory {
+                    overlord: format!("{}-{}", other, ROOM),
+                    role: role.to_string(),
+                    colony: ROOM.to_string(),
+                    state: None,
+                    task: None,
+                },
+            );
         }
-        Ok(())
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+        overlord.refresh().unwrap();
+        // retired or not, the drone works on until it expires
+        assert!(overlord.creeps.contains(&ROLE_DRONE.to_string()));
+
+        world.state.borrow_mut().creeps.remove(ROLE_DRONE);
+        overlord.run().unwrap();
+        let hive = overlord.hive.as_ref().borrow();
+        hive.hatcherys
+            .spawn_queue()
+            .requests()
+            .iter()
+            .any(|request| request.setup.role == ROLE_DRONE)
     }
 
-    fn get_name(&self) -> String {
-        Self::get_name_internal(&self.room, &self.source.id())
+    #[test]
+    fn drones_retire_once_upgraders_have_a_supply() {
+        // haulers alone don't take over, nothing upgrades yet
+        let (world, mut overlord, _) = with_container(2);
+        assert!(drone_requested(&world, &mut overlord));
+
+        let (world, mut overlord, _) = with_container(2);
+        world.add_structure(
+            StructureType::Container,
+            position(ROOM, 12, 12),
+            Some(crate::world::StoreInfo {
+                energy: 0,
+                used: 0,
+                capacity: 2000,
+            }),
+        );
+        assert!(!drone_requested(&world, &mut overlord));
+
+        // the controller link supplies them
+        let (world, mut overlord, _) = with_link(5);
+        assert!(!drone_requested(&world, &mut overlord));
     }
 
-    // fn get_cache(&self) -> String {
-    //   let cache = MineOverlordCache{source_id: self.source.id()};
-    //   serde_json::to_string(&cache).unwrap()
-    // }
+    #[test]
+    fn cache_round_trip_keeps_creeps() {
+        let (world, mut overlord, source_id) = setup();
+        add_miner(&world, &mut overlord, 20, 20);
+
+        let cache = overlord.get_cache();
+        let restored =
+            MineOverlord::new_from_cache(world.clone(), &cache, overlord.hive.clone()).unwrap();
+        assert_eq!(restored.source_id, source_id);
+        assert_eq!(restored.creeps, vec!["miner".to_string()]);
+    }
 }
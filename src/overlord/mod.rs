@@ -1,20 +1,98 @@
-use screeps::{objects::Creep, ErrorCode};
+use serde::{Deserialize, Serialize};
 
-use crate::{colony::Colony, error::SwarmError};
-use std::sync::Arc;
+use crate::{error::SwarmError, world::World};
 
+pub mod defense;
 pub mod mine;
+pub mod transport;
+pub mod upgrade;
+pub mod work;
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OverlordType {
     Mine,
+    Transport,
+    Upgrade,
+    Work,
+    Defense,
+}
+
+// what an overlord is given up for when the cpu bucket runs low, see
+// `profiler::budget`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverlordPriority {
+    // work that only pays off in the long run
+    Low,
+    // the energy income
+    Normal,
+    // the colony is lost without it
+    Critical,
+}
+
+impl OverlordType {
+    pub fn priority(self) -> OverlordPriority {
+        match self {
+            OverlordType::Defense => OverlordPriority::Critical,
+            OverlordType::Mine | OverlordType::Transport => OverlordPriority::Normal,
+            OverlordType::Upgrade | OverlordType::Work => OverlordPriority::Low,
+        }
+    }
+}
+
+// persisted form of an overlord. `cache` is produced by `Overlord::get_cache`
+// and is only understood by the overlord of the same type.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OverlordCache {
+    pub overlord_type: OverlordType,
+    pub cache: String,
 }
 
 pub trait Overlord {
     fn run(&self) -> Result<(), SwarmError>;
 
+    // re-resolve game objects held by the overlord, called once per tick before `run`.
+    fn refresh(&mut self) -> Result<(), SwarmError>;
+
+    // hand a freshly spawned creep, or an idle one it claimed, to the overlord
+    // that requested it.
+    fn assign_creep(&mut self, creep_name: String);
+
     // name of overlord must be globally unique.
     // [room_name]:[pos]:[overlord_name]
     fn get_name(&self) -> String;
 
-    // fn get_cache(&self) -> String;
+    fn get_type(&self) -> OverlordType;
+
+    // what the overlord is given up for when the cpu bucket runs low, the
+    // priority of its type unless it is needed more urgently right now
+    fn priority(&self) -> OverlordPriority {
+        self.get_type().priority()
+    }
+
+    fn get_cache(&self) -> String;
+}
+
+// keeps the creeps that are alive and still work for `overlord`. a creep that
+// was released to the idle pool or claimed by another overlord names someone
+// else in its memory.
+pub fn retain_own_creeps(world: &dyn World, overlord: &str, creeps: &mut Vec<String>) {
+    creeps.retain(|creep_name| {
+        world
+            .creep(creep_name)
+            .and_then(|creep| creep.memory)
+            .is_some_and(|memory| memory.overlord == overlord)
+    });
+}
+
+// hands a creep the overlord is done with back to the idle pool of its colony,
+// where any overlord may claim it by role.
+pub fn release_creep(world: &dyn World, creep_name: &str) {
+    let mut memory = match world.creep(creep_name).and_then(|creep| creep.memory) {
+        Some(memory) => memory,
+        None => return,
+    };
+    memory.overlord = String::new();
+    memory.state = None;
+    memory.task = None;
+    world.set_creep_memory(creep_name, &memory);
 }
@@ -0,0 +1,443 @@
+use log::{debug, warn};
+use screeps::{ErrorCode, Part, RoomName, CARRY_CAPACITY};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    constants::DEFAULT_PRIORITY,
+    creep_setup::creep_setup_templates::{CreepSetupTemplate, ROLE_HAULER},
+    error::SwarmError,
+    hive::{Hive, LogisticsEntry, LogisticsTarget},
+    task::{do_task, Task},
+    world::{CreepInfo, World},
+    zerg::{run_state_machine, EnergyCycle, HaulerState},
+};
+
+use super::{retain_own_creeps, Overlord, OverlordType};
+
+// the demand of far sources asks for a crowd of the small bodies early rooms
+// can afford. two sources are carried away by six of them, more only clog the
+// spawn and the roads, the bodies grow with the extensions instead.
+const MAX_HAULERS: usize = 6;
+
+// one TransportOverlord per colony. its haulers serve the logistics network of
+// the hive: they collect from provide entries and deliver to request entries.
+pub struct TransportOverlord {
+    overlord_type: OverlordType,
+    world: Rc<dyn World>,
+    hive: Rc<RefCell<Hive>>,
+    creeps: Vec<String>,
+    room_name: RoomName,
+    rcl: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransportOverlordCache {
+    creeps: Vec<String>,
+}
+
+impl TransportOverlord {
+    pub fn new(world: Rc<dyn World>, hive: Rc<RefCell<Hive>>) -> Result<Box<Self>, SwarmError> {
+        Self::new_internal(world, hive, &[])
+    }
+
+    pub fn new_from_cache(
+        world: Rc<dyn World>,
+        cache: &str,
+        hive: Rc<RefCell<Hive>>,
+    ) -> Result<Box<Self>, SwarmError> {
+        let overlord_cache =
+            serde_json::from_str::<TransportOverlordCache>(cache).map_err(|e| {
+                warn!("Parse overlord cache failed. {:?}", e);
+                SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
+            })?;
+        Self::new_internal(world, hive, &overlord_cache.creeps)
+    }
+
+    fn new_internal(
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cached_creeps: &[String],
+    ) -> Result<Box<Self>, SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let creeps = Self::initialize_creeps(world.as_ref(), room_name, cached_creeps);
+        let mut overlord = Box::new(TransportOverlord {
+            overlord_type: OverlordType::Transport,
+            world,
+            hive,
+            creeps,
+            room_name,
+            rcl: 0,
+        });
+        overlord.refresh()?;
+        debug!("initialize transport overlord done. {}", room_name);
+        Ok(overlord)
+    }
+
+    pub fn get_name_by_room(room_name: RoomName) -> String {
+        format!("transport-{}", room_name)
+    }
+
+    // haulers spawned by the mine overlords of this room before there was a
+    // transport overlord are taken over as well.
+    fn initialize_creeps(
+        world: &dyn World,
+        room_name: RoomName,
+        cached_creeps: &[String],
+    ) -> Vec<String> {
+        let name = Self::get_name_by_room(room_name);
+        let mine_prefix = format!("mine-{}-", room_name);
+        let mut creeps: Vec<String> = cached_creeps
+            .iter()
+            .filter(|creep_name| world.creep(creep_name).is_some())
+            .cloned()
+            .collect();
+
+        for creep in world.creeps() {
+            if cached_creeps.contains(&creep.name) {
+                continue;
+            }
+            let mut memory = match creep.memory {
+                Some(memory) => memory,
+                None => continue,
+            };
+            if memory.overlord == name {
+                creeps.push(creep.name);
+            } else if memory.role == ROLE_HAULER && memory.overlord.starts_with(&mine_prefix) {
+                memory.overlord = name.clone();
+                world.set_creep_memory(&creep.name, &memory);
+                creeps.push(creep.name);
+            }
+        }
+        creeps
+    }
+
+    fn haulers(&self) -> Vec<CreepInfo> {
+        self.creeps
+            .iter()
+            .filter_map(|creep_name| self.world.creep(creep_name))
+            .collect()
+    }
+
+    // enough carry parts for the demand the network collected, at least one
+    // hauler to fill the spawns from the storage once there is one
+    fn haulers_needed(&self) -> usize {
+        let hive = self.hive.as_ref().borrow();
+        let demand = hive.logistics.carry_demand();
+        let minimum = usize::from(hive.logistics.buffer().is_some());
+        let capacity = self
+            .world
+            .room(self.room_name)
+            .and_then(|room| CreepSetupTemplate::hauler(self.rcl).generate(&room))
+            .map(|setup| setup.body.iter().filter(|part| **part == Part::Carry).count() as u32)
+            .map(|carry| carry * CARRY_CAPACITY)
+            .unwrap_or(0);
+        if capacity == 0 {
+            return minimum;
+        }
+        (demand.div_ceil(capacity) as usize).clamp(minimum, MAX_HAULERS)
+    }
+
+    fn maintain_creep(&self, alive: usize) {
+        let count = self.haulers_needed();
+        let site = self.world.spawns(self.room_name).first().map(|spawn| spawn.pos);
+        self.hive.as_ref().borrow_mut().hatcherys.request_creeps(
+            CreepSetupTemplate::hauler(self.rcl),
+            count,
+            alive,
+            self.get_name(),
+            DEFAULT_PRIORITY,
+            site,
+        );
+    }
+
+    // full haulers deliver until they are empty, the others collect. a hauler
+    // that finds nothing to collect delivers what it already carries.
+    fn update_state(&self, creep: &mut CreepInfo, has_provide: bool) -> HaulerState {
+        run_state_machine(self.world.as_ref(), creep, |state: HaulerState, creep| {
+            match state.cycle(creep) {
+                HaulerState::Collecting if creep.store.used > 0 && !has_provide => {
+                    HaulerState::Delivering
+                }
+                state => state,
+            }
+        })
+        .unwrap_or(HaulerState::Collecting)
+    }
+
+    fn run_hauler(&self, creep: &CreepInfo, entry: &LogisticsEntry, delivering: bool) {
+        let task = match (&entry.target, delivering) {
+            (LogisticsTarget::Structure(id), true) => Task::transfer(*id, entry.pos, entry.resource),
+            (LogisticsTarget::Structure(id), false) => Task::withdraw(*id, entry.pos, entry.resource),
+            (LogisticsTarget::Dropped(id), false) => Task::pickup(*id, entry.pos),
+            (LogisticsTarget::Creep(name), true) => {
+                Task::transfer_to_creep(name, entry.pos, entry.resource)
+            }
+            (LogisticsTarget::Dropped(_), true) => {
+                warn!("hauler {} can't deliver to the ground", creep.name);
+                return;
+            }
+            (LogisticsTarget::Creep(_), false) => {
+                warn!("hauler {} can't collect from a creep", creep.name);
+                return;
+            }
+        };
+        match do_task(self.world.as_ref(), creep, task) {
+            Ok(()) => {}
+            // the target changed or is gone since the network was built, next
+            // tick has a new one
+            Err(ErrorCode::Full) | Err(ErrorCode::NotEnough) | Err(ErrorCode::InvalidTarget) => {}
+            Err(e) => warn!("overlord:run_hauler: unexpected error: {:?}", e),
+        }
+    }
+}
+
+impl Overlord for TransportOverlord {
+    fn run(&self) -> Result<(), SwarmError> {
+        let haulers = self.haulers();
+        self.maintain_creep(haulers.len());
+
+        let has_provide = {
+            let hive = self.hive.as_ref().borrow();
+            !hive.logistics.provides().is_empty()
+        };
+        let mut collecting = Vec::new();
+        let mut delivering = Vec::new();
+        for mut creep in haulers.into_iter().filter(|creep| !creep.spawning) {
+            if self.update_state(&mut creep, has_provide) == HaulerState::Delivering {
+                delivering.push(creep);
+            } else {
+                collecting.push(creep);
+            }
+        }
+
+        let assignments = self
+            .hive
+            .as_ref()
+            .borrow()
+            .logistics
+            .assign(&collecting, &delivering);
+        for creep in collecting.iter().chain(delivering.iter()) {
+            let delivering = delivering.iter().any(|other| other.name == creep.name);
+            match assignments.iter().find(|(name, _)| *name == creep.name) {
+                Some((_, entry)) => self.run_hauler(creep, entry, delivering),
+                None => debug!("hauler {} has nothing to do", creep.name),
+            }
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), SwarmError> {
+        let name = self.get_name();
+        retain_own_creeps(self.world.as_ref(), &name, &mut self.creeps);
+        self.rcl = self
+            .world
+            .controller(self.room_name)
+            .map(|controller| controller.level)
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    fn assign_creep(&mut self, creep_name: String) {
+        if self.creeps.contains(&creep_name) {
+            return;
+        }
+        self.creeps.push(creep_name);
+    }
+
+    fn get_name(&self) -> String {
+        Self::get_name_by_room(self.room_name)
+    }
+
+    fn get_type(&self) -> OverlordType {
+        self.overlord_type
+    }
+
+    fn get_cache(&self) -> String {
+        let cache = TransportOverlordCache {
+            creeps: self.creeps.clone(),
+        };
+        serde_json::to_string(&cache).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        movement,
+        world::{
+            mock::{position, MockAction, MockWorld},
+            StoreInfo,
+        },
+        zerg::{CreepMemory, CreepState},
+    };
+    use screeps::{Direction, RawObjectId, ResourceType, StructureType};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (Rc<MockWorld>, Box<TransportOverlord>) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 2);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        let hive = Hive::new(world.clone(), RoomName::new(ROOM).unwrap()).unwrap();
+        let overlord = TransportOverlord::new(world.clone(), hive).unwrap();
+        (world, overlord)
+    }
+
+    fn add_hauler(world: &MockWorld, overlord: &mut TransportOverlord, name: &str, x: u8, y: u8) {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Carry, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: ROLE_HAULER.to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        overlord.assign_creep(name.to_string());
+    }
+
+    fn provide_container(world: &MockWorld, overlord: &TransportOverlord) -> RawObjectId {
+        let pos = position(ROOM, 40, 40);
+        let container = world.add_structure(
+            StructureType::Container,
+            pos,
+            Some(StoreInfo {
+                energy: 500,
+                used: 500,
+                capacity: 2000,
+            }),
+        );
+        overlord.hive.as_ref().borrow_mut().logistics.provide(LogisticsEntry {
+            target: LogisticsTarget::Structure(container),
+            pos,
+            resource: ResourceType::Energy,
+            amount: 500,
+            priority: DEFAULT_PRIORITY,
+        });
+        container
+    }
+
+    #[test]
+    fn requests_haulers_for_carry_demand() {
+        let (world, overlord) = setup();
+        overlord.run().unwrap();
+        overlord.hive.as_ref().borrow_mut().run();
+        assert!(world.take_actions().is_empty());
+
+        overlord
+            .hive
+            .as_ref()
+            .borrow_mut()
+            .logistics
+            .add_carry_demand(300);
+        overlord.run().unwrap();
+        overlord.hive.as_ref().borrow_mut().run();
+        assert!(matches!(
+            world.take_actions().as_slice(),
+            [MockAction::SpawnCreep { name, .. }] if name.contains(ROLE_HAULER)
+        ));
+    }
+
+    #[test]
+    fn empty_hauler_collects_from_provide() {
+        let (world, mut overlord) = setup();
+        add_hauler(&world, &mut overlord, "hauler", 39, 39);
+        let container = provide_container(&world, &overlord);
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::Withdraw {
+            creep: "hauler".to_string(),
+            target: container,
+            resource: ResourceType::Energy,
+        }));
+    }
+
+    #[test]
+    fn full_hauler_fills_the_spawn() {
+        let (world, mut overlord) = setup();
+        add_hauler(&world, &mut overlord, "hauler", 30, 30);
+        world.set_creep_energy("hauler", 100);
+        world.state.borrow_mut().rooms.values_mut().for_each(|room| {
+            let store = room.structures[0].store.as_mut().unwrap();
+            store.energy = 100;
+            store.used = 100;
+        });
+        overlord.hive.as_ref().borrow_mut().refresh().unwrap();
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+
+        assert!(world.take_actions().contains(&MockAction::Move {
+            creep: "hauler".to_string(),
+            direction: Direction::TopLeft,
+        }));
+        let memory = world.creep("hauler").unwrap().memory.unwrap();
+        assert_eq!(memory.state, Some(CreepState::Hauler(HaulerState::Delivering)));
+    }
+
+    #[test]
+    fn full_hauler_feeds_a_worker_once_the_spawn_is_full() {
+        let (world, mut overlord) = setup();
+        add_hauler(&world, &mut overlord, "hauler", 30, 30);
+        world.set_creep_energy("hauler", 100);
+        world.add_creep(
+            "worker",
+            position(ROOM, 31, 30),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: format!("work-{}", ROOM),
+                role: "worker".to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        world.state.borrow_mut().rooms.values_mut().for_each(|room| {
+            let store = room.structures[0].store.as_mut().unwrap();
+            store.energy = 300;
+            store.used = 300;
+        });
+        let mut hive = overlord.hive.as_ref().borrow_mut();
+        hive.refresh().unwrap();
+        hive.logistics.request_for_creeps(&[world.creep("worker").unwrap()]);
+        drop(hive);
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::TransferToCreep {
+            creep: "hauler".to_string(),
+            target: "worker".to_string(),
+            resource: ResourceType::Energy,
+        }));
+    }
+
+    #[test]
+    fn takes_over_haulers_of_mine_overlords() {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 2);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        world.add_creep(
+            "old-hauler",
+            position(ROOM, 20, 20),
+            &[Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: format!("mine-{}-7", ROOM),
+                role: ROLE_HAULER.to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        let hive = Hive::new(world.clone(), RoomName::new(ROOM).unwrap()).unwrap();
+        let overlord = TransportOverlord::new(world.clone(), hive).unwrap();
+
+        assert_eq!(overlord.creeps, vec!["old-hauler".to_string()]);
+        let memory = world.creep("old-hauler").unwrap().memory.unwrap();
+        assert_eq!(memory.overlord, overlord.get_name());
+    }
+}
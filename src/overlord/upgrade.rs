@@ -0,0 +1,681 @@
+use log::{debug, warn};
+use screeps::{
+    constants::controller_downgrade, ErrorCode, Part, Position, ResourceType, RoomName,
+    StructureType, Terrain, UPGRADE_CONTROLLER_POWER,
+};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    constants::{DEFAULT_PRIORITY, HIGH_PRIORITY},
+    creep_setup::creep_setup_templates::CreepSetupTemplate,
+    error::SwarmError,
+    hive::{Hive, LogisticsEntry, LogisticsTarget},
+    task::{do_task, Task},
+    world::{ControllerInfo, CreepInfo, StructureInfo, World},
+};
+
+use super::{retain_own_creeps, Overlord, OverlordPriority, OverlordType};
+
+// a container this close to the controller supplies the upgraders, upgraders
+// standing next to it are still in range of the controller.
+const CONTROLLER_CONTAINER_RANGE: u32 = 2;
+// the controller takes at most 15 energy a tick at RCL 8
+const MAX_UPGRADE_WORK_AT_RCL_8: u32 = 15;
+// energy the storage keeps for everything else, above it goes into upgrading
+const STORAGE_RESERVE: u32 = 50_000;
+const STORAGE_SURPLUS_PER_UPGRADER: u32 = 100_000;
+const UPGRADERS_WITHOUT_STORAGE: usize = 2;
+const MAX_UPGRADERS: usize = 4;
+
+// one UpgradeOverlord per colony. upgraders stay next to the controller and
+// take energy from the controller link, the controller container or the
+// storage. it also keeps the controller from downgrading.
+pub struct UpgradeOverlord {
+    overlord_type: OverlordType,
+    world: Rc<dyn World>,
+    hive: Rc<RefCell<Hive>>,
+    creeps: Vec<String>,
+    room_name: RoomName,
+    controller: Option<ControllerInfo>,
+    link: Option<StructureInfo>,
+    container: Option<StructureInfo>,
+    storage: Option<StructureInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UpgradeOverlordCache {
+    creeps: Vec<String>,
+}
+
+impl UpgradeOverlord {
+    pub fn new(world: Rc<dyn World>, hive: Rc<RefCell<Hive>>) -> Result<Box<Self>, SwarmError> {
+        Self::new_internal(world, hive, &[])
+    }
+
+    pub fn new_from_cache(
+        world: Rc<dyn World>,
+        cache: &str,
+        hive: Rc<RefCell<Hive>>,
+    ) -> Result<Box<Self>, SwarmError> {
+        let overlord_cache = serde_json::from_str::<UpgradeOverlordCache>(cache).map_err(|e| {
+            warn!("Parse overlord cache failed. {:?}", e);
+            SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
+        })?;
+        Self::new_internal(world, hive, &overlord_cache.creeps)
+    }
+
+    fn new_internal(
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cached_creeps: &[String],
+    ) -> Result<Box<Self>, SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let name = Self::get_name_by_room(room_name);
+        let mut creeps: Vec<String> = cached_creeps
+            .iter()
+            .filter(|creep_name| world.creep(creep_name).is_some())
+            .cloned()
+            .collect();
+        for creep in world.creeps() {
+            if !creeps.contains(&creep.name)
+                && creep.memory.as_ref().map(|memory| memory.overlord == name) == Some(true)
+            {
+                creeps.push(creep.name);
+            }
+        }
+
+        let mut overlord = Box::new(UpgradeOverlord {
+            overlord_type: OverlordType::Upgrade,
+            world,
+            hive,
+            creeps,
+            room_name,
+            controller: None,
+            link: None,
+            container: None,
+            storage: None,
+        });
+        overlord.refresh()?;
+        debug!("initialize upgrade overlord done. {}", room_name);
+        Ok(overlord)
+    }
+
+    pub fn get_name_by_room(room_name: RoomName) -> String {
+        format!("upgrade-{}", room_name)
+    }
+
+    fn refresh_structures(&mut self) {
+        self.controller = self.world.controller(self.room_name);
+        let controller_pos = match &self.controller {
+            Some(controller) => controller.pos,
+            None => return,
+        };
+        let structures = self.world.structures(self.room_name);
+        self.container = Self::find_container(self.world.as_ref(), self.room_name, controller_pos);
+        self.storage = structures
+            .iter()
+            .find(|s| s.structure_type == StructureType::Storage && s.my)
+            .cloned();
+        self.link = self.hive.as_ref().borrow().links.controller_link().cloned();
+    }
+
+    fn find_container(
+        world: &dyn World,
+        room_name: RoomName,
+        controller_pos: Position,
+    ) -> Option<StructureInfo> {
+        let sources = world.sources(room_name);
+        world.structures(room_name).into_iter().find(|s| {
+            s.structure_type == StructureType::Container
+                && s.pos.get_range_to(controller_pos) <= CONTROLLER_CONTAINER_RANGE
+                // mining containers stay with the mine
+                && !sources.iter().any(|source| source.pos.get_range_to(s.pos) <= 1)
+        })
+    }
+
+    // an upgrader at work with a supply to take from. the mines keep their
+    // drones until the colony upgrades without them.
+    pub fn has_supplied_upgrader(world: &dyn World, hive: &Hive, room_name: RoomName) -> bool {
+        let name = Self::get_name_by_room(room_name);
+        let upgrader = world.creeps().into_iter().any(|creep| {
+            !creep.spawning && creep.memory.is_some_and(|memory| memory.overlord == name)
+        });
+        let controller_pos = match world.controller(room_name) {
+            Some(controller) => controller.pos,
+            None => return false,
+        };
+        let supply = hive.links.controller_link().is_some()
+            || hive.logistics.buffer().is_some()
+            || Self::find_container(world, room_name, controller_pos).is_some();
+        upgrader && supply
+    }
+
+    // haulers keep the controller container filled, after the spawns. what is
+    // left they hand to the upgraders.
+    fn post_logistics(&self) {
+        let upgraders = self.upgraders();
+        self.hive
+            .as_ref()
+            .borrow_mut()
+            .logistics
+            .request_for_creeps(&upgraders);
+        let container = match &self.container {
+            Some(container) => container,
+            None => return,
+        };
+        let free = container.store.map(|store| store.free()).unwrap_or(0);
+        let mut hive = self.hive.as_ref().borrow_mut();
+        if free > 0 {
+            hive.logistics.request(LogisticsEntry {
+                target: LogisticsTarget::Structure(container.id),
+                pos: container.pos,
+                resource: ResourceType::Energy,
+                amount: free,
+                priority: DEFAULT_PRIORITY,
+            });
+        }
+        let distance = self
+            .world
+            .spawns(self.room_name)
+            .iter()
+            .map(|spawn| spawn.pos.get_range_to(container.pos))
+            .min()
+            .unwrap_or(0);
+        let upgrade_per_tick = self.upgrade_work() * UPGRADE_CONTROLLER_POWER;
+        hive.logistics
+            .add_carry_demand(upgrade_per_tick * 2 * (distance + 2));
+    }
+
+    fn upgraders(&self) -> Vec<CreepInfo> {
+        let mut upgraders: Vec<CreepInfo> = self
+            .creeps
+            .iter()
+            .filter_map(|creep_name| self.world.creep(creep_name))
+            .collect();
+        upgraders.sort_by(|a, b| a.name.cmp(&b.name));
+        upgraders
+    }
+
+    fn upgrade_work(&self) -> u32 {
+        self.upgraders()
+            .iter()
+            .map(|creep| creep.count_parts(Part::Work))
+            .sum()
+    }
+
+    // where the upgraders take their energy from, the closest kind first
+    fn supply(&self) -> Option<&StructureInfo> {
+        self.link
+            .as_ref()
+            .or(self.container.as_ref())
+            .or(self.storage.as_ref())
+    }
+
+    fn upgraders_needed(&self) -> usize {
+        let controller = match &self.controller {
+            Some(controller) => controller,
+            None => return 0,
+        };
+        if controller.level >= 8 {
+            return 1;
+        }
+        match self.storage.as_ref().and_then(|storage| storage.store) {
+            Some(store) => {
+                let surplus = store.energy.saturating_sub(STORAGE_RESERVE);
+                (1 + (surplus / STORAGE_SURPLUS_PER_UPGRADER) as usize).min(MAX_UPGRADERS)
+            }
+            None => UPGRADERS_WITHOUT_STORAGE,
+        }
+    }
+
+    // a quarter of the full timer left: one upgrader goes first, even without
+    // a supply it harvests its own energy
+    fn downgrade_imminent(&self) -> bool {
+        self.controller
+            .as_ref()
+            .map(|controller| {
+                controller.ticks_to_downgrade
+                    < controller_downgrade(controller.level).unwrap_or(0) / 4
+            })
+            == Some(true)
+    }
+
+    fn maintain_creep(&self, alive: usize) {
+        let controller = match &self.controller {
+            Some(controller) => controller,
+            None => return,
+        };
+        let (count, priority) = if self.downgrade_imminent() && alive == 0 {
+            (1, HIGH_PRIORITY)
+        } else {
+            (self.upgraders_needed(), DEFAULT_PRIORITY)
+        };
+        if controller.level >= 8 && self.upgrade_work() >= MAX_UPGRADE_WORK_AT_RCL_8 {
+            return;
+        }
+        self.hive.as_ref().borrow_mut().hatcherys.request_creeps(
+            CreepSetupTemplate::upgrader(controller.level),
+            count,
+            alive,
+            self.get_name(),
+            priority,
+            Some(controller.pos),
+        );
+    }
+
+    // a container by the controller, on the tile closest to the spawn. a
+    // controller link makes it unnecessary.
+    fn plan_container(&self) {
+        let controller = match &self.controller {
+            Some(controller) => controller,
+            None => return,
+        };
+        if controller.level < 2 || self.container.is_some() || self.link.is_some() {
+            return;
+        }
+        let sites = self.world.construction_sites(self.room_name);
+        if sites.iter().any(|site| {
+            site.structure_type == StructureType::Container
+                && site.pos.get_range_to(controller.pos) <= CONTROLLER_CONTAINER_RANGE
+        }) {
+            return;
+        }
+        let spawn_pos = match self.world.spawns(self.room_name).first() {
+            Some(spawn) => spawn.pos,
+            None => return,
+        };
+        let structures = self.world.structures(self.room_name);
+        let sources = self.world.sources(self.room_name);
+        let mut candidates: Vec<Position> = self
+            .tiles_around(controller.pos, CONTROLLER_CONTAINER_RANGE)
+            .into_iter()
+            .filter(|pos| {
+                !structures.iter().any(|s| {
+                    s.pos == *pos && s.structure_type != StructureType::Road
+                }) && !sites.iter().any(|site| site.pos == *pos)
+                    && !sources.iter().any(|source| source.pos.get_range_to(*pos) <= 1)
+            })
+            .collect();
+        candidates.sort_by_key(|pos| (pos.get_range_to(spawn_pos), pos.y().u8(), pos.x().u8()));
+
+        if let Some(pos) = candidates.first() {
+            match self
+                .world
+                .create_construction_site(*pos, StructureType::Container)
+            {
+                Ok(()) => debug!("plan controller container of {} at {}", self.get_name(), pos),
+                Err(e) => warn!("place container site failed. {} {:?}", self.get_name(), e),
+            }
+        }
+    }
+
+    // tiles within `range` of `center` that are not walls, sorted by y then x
+    fn tiles_around(&self, center: Position, range: u32) -> Vec<Position> {
+        let terrain = match self.world.terrain(self.room_name) {
+            Some(terrain) => terrain,
+            None => return Vec::new(),
+        };
+        let range = range as i32;
+        let mut tiles = Vec::new();
+        for dy in -range..=range {
+            for dx in -range..=range {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                if let Ok(pos) = center.checked_add((dx, dy)) {
+                    if terrain.get(pos.xy()) != Terrain::Wall {
+                        tiles.push(pos);
+                    }
+                }
+            }
+        }
+        tiles
+    }
+
+    // tiles next to the supply in range of the controller, one per upgrader so
+    // they don't queue up behind each other
+    fn upgrade_spots(&self, supply: &StructureInfo, controller: &ControllerInfo) -> Vec<Position> {
+        let structures = self.world.structures(self.room_name);
+        let mut spots: Vec<Position> = self
+            .tiles_around(supply.pos, 1)
+            .into_iter()
+            .chain(std::iter::once(supply.pos))
+            .filter(|pos| pos.get_range_to(controller.pos) <= 3)
+            .filter(|pos| {
+                !structures.iter().any(|s| {
+                    s.pos == *pos
+                        && !matches!(
+                            s.structure_type,
+                            StructureType::Road | StructureType::Container | StructureType::Rampart
+                        )
+                })
+            })
+            .collect();
+        spots.sort_by_key(|pos| (pos.get_range_to(controller.pos), pos.y().u8(), pos.x().u8()));
+        spots
+    }
+
+    fn run_upgrader(&self, creep: &CreepInfo, spot: Option<Position>) {
+        let controller = match &self.controller {
+            Some(controller) => controller,
+            None => return,
+        };
+        let upgrade = Task::upgrade(controller.id, controller.pos);
+        if let Some(spot) = spot {
+            if creep.pos != spot {
+                let task = Task::go_to(spot, 0).then(upgrade);
+                if let Err(e) = do_task(self.world.as_ref(), creep, task) {
+                    warn!("overlord:run_upgrader: unexpected error: {:?}", e);
+                }
+                return;
+            }
+        }
+
+        // refill before running dry, both happen in the same tick. upgraders
+        // with energy left keep upgrading instead of walking off.
+        let power = creep.count_parts(Part::Work) * UPGRADE_CONTROLLER_POWER;
+        if creep.store.energy <= power {
+            if let Some(task) = self.collect_task(creep) {
+                if creep.store.energy == 0 {
+                    self.collect_with(creep, task);
+                } else if creep.pos.get_range_to(task.pos) <= task.range() {
+                    // goes along with the upgrade, it doesn't replace the task
+                    self.collect_with_result(task.run(self.world.as_ref(), creep));
+                }
+            }
+        }
+        if creep.store.energy > 0 {
+            if let Err(e) = do_task(self.world.as_ref(), creep, upgrade) {
+                warn!("overlord:run_upgrader: unexpected error: {:?}", e);
+            }
+        }
+    }
+
+    // the supply, else the closest source. without a supply the upgraders
+    // harvest their own energy, or the haulers bring it.
+    fn collect_task(&self, creep: &CreepInfo) -> Option<Task> {
+        if let Some(supply) = self
+            .supply()
+            .filter(|supply| supply.store.map(|store| store.energy > 0) == Some(true))
+        {
+            return Some(Task::withdraw(supply.id, supply.pos, ResourceType::Energy));
+        }
+        self.world
+            .sources(self.room_name)
+            .into_iter()
+            .filter(|source| source.energy > 0)
+            .min_by_key(|source| source.pos.get_range_to(creep.pos))
+            .map(|source| Task::harvest(source.id, source.pos))
+    }
+
+    fn collect_with(&self, creep: &CreepInfo, task: Task) {
+        self.collect_with_result(do_task(self.world.as_ref(), creep, task));
+    }
+
+    fn collect_with_result(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => {}
+            // emptied by someone else earlier this tick
+            Err(ErrorCode::NotEnough) | Err(ErrorCode::Full) => {}
+            Err(e) => warn!("overlord:collect: unexpected error: {:?}", e),
+        }
+    }
+}
+
+impl Overlord for UpgradeOverlord {
+    fn run(&self) -> Result<(), SwarmError> {
+        let upgraders = self.upgraders();
+        self.maintain_creep(upgraders.len());
+        self.plan_container();
+
+        let spots = match (self.supply(), &self.controller) {
+            (Some(supply), Some(controller)) => self.upgrade_spots(supply, controller),
+            _ => Vec::new(),
+        };
+        for (index, creep) in upgraders.iter().filter(|creep| !creep.spawning).enumerate() {
+            self.run_upgrader(creep, spots.get(index).copied());
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), SwarmError> {
+        let name = self.get_name();
+        retain_own_creeps(self.world.as_ref(), &name, &mut self.creeps);
+        self.refresh_structures();
+        self.post_logistics();
+        Ok(())
+    }
+
+    fn assign_creep(&mut self, creep_name: String) {
+        if self.creeps.contains(&creep_name) {
+            return;
+        }
+        self.creeps.push(creep_name);
+    }
+
+    fn get_name(&self) -> String {
+        Self::get_name_by_room(self.room_name)
+    }
+
+    fn get_type(&self) -> OverlordType {
+        self.overlord_type
+    }
+
+    // the controller is lost to a downgrade however low the bucket is
+    fn priority(&self) -> OverlordPriority {
+        if self.downgrade_imminent() {
+            OverlordPriority::Critical
+        } else {
+            self.get_type().priority()
+        }
+    }
+
+    fn get_cache(&self) -> String {
+        let cache = UpgradeOverlordCache {
+            creeps: self.creeps.clone(),
+        };
+        serde_json::to_string(&cache).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::creep_setup_templates::ROLE_UPGRADER,
+        movement,
+        world::{
+            mock::{position, MockAction, MockWorld},
+            StoreInfo,
+        },
+        zerg::CreepMemory,
+    };
+    use screeps::Direction;
+
+    const ROOM: &str = "W1N1";
+
+    fn setup_at(rcl: u8, energy_capacity: u32) -> (Rc<MockWorld>, Rc<RefCell<Hive>>) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, energy_capacity, energy_capacity);
+        world.add_controller(position(ROOM, 10, 10), rcl);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        let hive = Hive::new(world.clone(), RoomName::new(ROOM).unwrap()).unwrap();
+        (world, hive)
+    }
+
+    fn add_container(world: &MockWorld, energy: u32) -> screeps::RawObjectId {
+        world.add_structure(
+            StructureType::Container,
+            position(ROOM, 12, 12),
+            Some(StoreInfo {
+                energy,
+                used: energy,
+                capacity: 2000,
+            }),
+        )
+    }
+
+    fn add_upgrader(world: &MockWorld, overlord: &mut UpgradeOverlord, name: &str, x: u8, y: u8) {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Work, Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: ROLE_UPGRADER.to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        overlord.assign_creep(name.to_string());
+    }
+
+    fn spawned(world: &MockWorld) -> Vec<Vec<Part>> {
+        world
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                MockAction::SpawnCreep { body, .. } => Some(body),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plans_controller_container_towards_spawn() {
+        let (world, hive) = setup_at(2, 300);
+        let overlord = UpgradeOverlord::new(world.clone(), hive).unwrap();
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::CreateConstructionSite {
+            pos: position(ROOM, 12, 12),
+            structure_type: StructureType::Container,
+        }));
+    }
+
+    #[test]
+    fn upgraders_without_controller_container() {
+        let (world, hive) = setup_at(2, 300);
+        let overlord = UpgradeOverlord::new(world.clone(), hive.clone()).unwrap();
+        assert_eq!(overlord.upgraders_needed(), UPGRADERS_WITHOUT_STORAGE);
+        overlord.run().unwrap();
+        hive.as_ref().borrow_mut().run();
+        assert_eq!(spawned(&world).len(), 1);
+    }
+
+    #[test]
+    fn container_is_requested_from_haulers() {
+        let (world, hive) = setup_at(3, 800);
+        let container = add_container(&world, 500);
+        hive.as_ref().borrow_mut().refresh().unwrap();
+        let _overlord = UpgradeOverlord::new(world.clone(), hive.clone()).unwrap();
+
+        let hive = hive.as_ref().borrow();
+        assert!(hive
+            .logistics
+            .requests()
+            .iter()
+            .any(|entry| entry.target == LogisticsTarget::Structure(container)
+                && entry.amount == 1500));
+    }
+
+    #[test]
+    fn upgrader_refills_and_upgrades_from_its_spot() {
+        let (world, hive) = setup_at(3, 800);
+        let container = add_container(&world, 500);
+        let mut overlord = UpgradeOverlord::new(world.clone(), hive).unwrap();
+        add_upgrader(&world, &mut overlord, "upgrader", 20, 20);
+
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert!(world.take_actions().contains(&MockAction::Move {
+            creep: "upgrader".to_string(),
+            direction: Direction::TopLeft,
+        }));
+
+        world.state.borrow_mut().creeps.get_mut("upgrader").unwrap().pos = position(ROOM, 11, 11);
+        world.set_creep_energy("upgrader", 2);
+        overlord.run().unwrap();
+        let actions = world.take_actions();
+        assert!(actions.contains(&MockAction::Withdraw {
+            creep: "upgrader".to_string(),
+            target: container,
+            resource: ResourceType::Energy,
+        }));
+        assert!(actions
+            .iter()
+            .any(|action| matches!(action, MockAction::UpgradeController { .. })));
+    }
+
+    #[test]
+    fn rcl_8_spawns_a_single_capped_upgrader() {
+        let (world, hive) = setup_at(8, 12_900);
+        world.add_structure(
+            StructureType::Storage,
+            position(ROOM, 14, 14),
+            Some(StoreInfo {
+                energy: 900_000,
+                used: 900_000,
+                capacity: 1_000_000,
+            }),
+        );
+        let overlord = UpgradeOverlord::new(world.clone(), hive.clone()).unwrap();
+        overlord.run().unwrap();
+        hive.as_ref().borrow_mut().run();
+
+        let bodies = spawned(&world);
+        assert_eq!(bodies.len(), 1);
+        let work = bodies[0].iter().filter(|part| **part == Part::Work).count() as u32;
+        assert_eq!(work, MAX_UPGRADE_WORK_AT_RCL_8);
+    }
+
+    #[test]
+    fn storage_surplus_adds_upgraders() {
+        let (world, hive) = setup_at(6, 2300);
+        world.add_structure(
+            StructureType::Storage,
+            position(ROOM, 14, 14),
+            Some(StoreInfo {
+                energy: 300_000,
+                used: 300_000,
+                capacity: 1_000_000,
+            }),
+        );
+        let overlord = UpgradeOverlord::new(world.clone(), hive).unwrap();
+        assert_eq!(overlord.upgraders_needed(), 3);
+    }
+
+    #[test]
+    fn downgrade_brings_an_upgrader_without_supply() {
+        let (world, hive) = setup_at(3, 800);
+        world.state.borrow_mut().rooms.values_mut().for_each(|room| {
+            room.controller.as_mut().unwrap().ticks_to_downgrade = 1000;
+        });
+        let overlord = UpgradeOverlord::new(world.clone(), hive.clone()).unwrap();
+        overlord.run().unwrap();
+
+        let hive = hive.as_ref().borrow();
+        let request = hive.hatcherys.spawn_queue().peek().unwrap();
+        assert_eq!(request.setup.role, ROLE_UPGRADER);
+        assert_eq!(request.priority, HIGH_PRIORITY);
+    }
+
+    #[test]
+    fn dry_upgrader_without_supply_harvests_through_a_task() {
+        let (world, hive) = setup_at(3, 800);
+        let source = world.add_source(position(ROOM, 30, 30));
+        let mut overlord = UpgradeOverlord::new(world.clone(), hive).unwrap();
+        add_upgrader(&world, &mut overlord, "upgrader", 20, 20);
+
+        overlord.run().unwrap();
+        movement::run_traffic(world.as_ref());
+        assert!(world.take_actions().contains(&MockAction::Move {
+            creep: "upgrader".to_string(),
+            direction: Direction::BottomRight,
+        }));
+        let memory = world.creep("upgrader").unwrap().memory.unwrap();
+        assert_eq!(memory.task, Some(Task::harvest(source, position(ROOM, 30, 30))));
+    }
+}
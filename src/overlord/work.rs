@@ -0,0 +1,574 @@
+use log::{debug, warn};
+use screeps::{
+    ErrorCode, Part, Position, RawObjectId, ResourceType, RoomName, StructureType, REPAIR_POWER,
+};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    constants::DEFAULT_PRIORITY,
+    creep_setup::creep_setup_templates::CreepSetupTemplate,
+    error::SwarmError,
+    hive::{Hive, LogisticsTarget, RepairEntry, REPAIR_FORTIFY},
+    task::{do_task, Task},
+    world::{ConstructionSiteInfo, CreepInfo, World},
+    zerg::{run_state_machine, EnergyCycle, WorkerState},
+};
+
+use super::{retain_own_creeps, Overlord, OverlordType};
+
+// roughly the progress a small worker builds in its life, including the trips
+// to fetch energy
+const BUILD_PER_WORKER: u32 = 5_000;
+const MAX_WORKERS: usize = 4;
+
+// the order sites are built in. the containers go with the extensions, the
+// upgraders and the haulers wait on them. structures not named by the plan,
+// like the storage or links, go after the towers.
+pub fn build_priority(structure_type: StructureType) -> u32 {
+    match structure_type {
+        StructureType::Spawn => 7,
+        StructureType::Extension | StructureType::Container => 6,
+        StructureType::Tower => 5,
+        StructureType::Road => 2,
+        StructureType::Wall | StructureType::Rampart => 1,
+        _ => 4,
+    }
+}
+
+enum Job {
+    Build(ConstructionSiteInfo),
+    Repair(RepairEntry),
+}
+
+fn remaining(site: &ConstructionSiteInfo) -> u32 {
+    site.progress_total.saturating_sub(site.progress)
+}
+
+// one WorkOverlord per colony. its workers build the construction sites of the
+// room, the most important ones first and spread over the sites of equal rank.
+// with nothing left to build they work through the repair queue of the hive.
+pub struct WorkOverlord {
+    overlord_type: OverlordType,
+    world: Rc<dyn World>,
+    hive: Rc<RefCell<Hive>>,
+    creeps: Vec<String>,
+    room_name: RoomName,
+    rcl: u8,
+    sites: Vec<ConstructionSiteInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkOverlordCache {
+    creeps: Vec<String>,
+}
+
+impl WorkOverlord {
+    pub fn new(world: Rc<dyn World>, hive: Rc<RefCell<Hive>>) -> Result<Box<Self>, SwarmError> {
+        Self::new_internal(world, hive, &[])
+    }
+
+    pub fn new_from_cache(
+        world: Rc<dyn World>,
+        cache: &str,
+        hive: Rc<RefCell<Hive>>,
+    ) -> Result<Box<Self>, SwarmError> {
+        let overlord_cache = serde_json::from_str::<WorkOverlordCache>(cache).map_err(|e| {
+            warn!("Parse overlord cache failed. {:?}", e);
+            SwarmError::InternalAssertionFailed("Parse overlord cache failed".to_string())
+        })?;
+        Self::new_internal(world, hive, &overlord_cache.creeps)
+    }
+
+    fn new_internal(
+        world: Rc<dyn World>,
+        hive: Rc<RefCell<Hive>>,
+        cached_creeps: &[String],
+    ) -> Result<Box<Self>, SwarmError> {
+        let room_name = hive.as_ref().borrow().hatcherys.room_name;
+        let name = Self::get_name_by_room(room_name);
+        let mut creeps: Vec<String> = cached_creeps
+            .iter()
+            .filter(|creep_name| world.creep(creep_name).is_some())
+            .cloned()
+            .collect();
+        for creep in world.creeps() {
+            if !creeps.contains(&creep.name)
+                && creep.memory.as_ref().map(|memory| memory.overlord == name) == Some(true)
+            {
+                creeps.push(creep.name);
+            }
+        }
+
+        let mut overlord = Box::new(WorkOverlord {
+            overlord_type: OverlordType::Work,
+            world,
+            hive,
+            creeps,
+            room_name,
+            rcl: 0,
+            sites: Vec::new(),
+        });
+        overlord.refresh()?;
+        debug!("initialize work overlord done. {}", room_name);
+        Ok(overlord)
+    }
+
+    pub fn get_name_by_room(room_name: RoomName) -> String {
+        format!("work-{}", room_name)
+    }
+
+    fn workers(&self) -> Vec<CreepInfo> {
+        let mut workers: Vec<CreepInfo> = self
+            .creeps
+            .iter()
+            .filter_map(|creep_name| self.world.creep(creep_name))
+            .collect();
+        workers.sort_by(|a, b| a.name.cmp(&b.name));
+        workers
+    }
+
+    // a point of progress and a hundred hits of repair both cost one energy
+    fn workers_needed(&self) -> usize {
+        let progress: u32 = self.sites.iter().map(remaining).sum::<u32>()
+            + self.hive.as_ref().borrow().repairs.energy_needed();
+        (progress.div_ceil(BUILD_PER_WORKER) as usize).min(MAX_WORKERS)
+    }
+
+    fn maintain_creep(&self, alive: usize) {
+        let site = self.sites.first().map(|site| site.pos);
+        let count = self.workers_needed();
+        self.hive.as_ref().borrow_mut().hatcherys.request_creeps(
+            CreepSetupTemplate::worker(self.rcl),
+            count,
+            alive,
+            self.get_name(),
+            DEFAULT_PRIORITY,
+            site,
+        );
+    }
+
+    // every worker goes to a site of the best rank left. a site takes as many
+    // workers as it needs loads to finish, the closest worker first.
+    fn assign_sites(&self, workers: &[CreepInfo]) -> Vec<(String, ConstructionSiteInfo)> {
+        let mut needed: Vec<u32> = self.sites.iter().map(remaining).collect();
+        let mut assignments: Vec<(String, ConstructionSiteInfo)> = Vec::new();
+        let mut unassigned: Vec<&CreepInfo> = workers.iter().collect();
+
+        while !unassigned.is_empty() {
+            let rank = self
+                .sites
+                .iter()
+                .zip(needed.iter())
+                .filter(|(_, needed)| **needed > 0)
+                .map(|(site, _)| build_priority(site.structure_type))
+                .max();
+            let rank = match rank {
+                Some(rank) => rank,
+                // more workers than loads left, the rest help at the top site
+                None => {
+                    if let Some(site) = self.sites.first() {
+                        for creep in unassigned {
+                            assignments.push((creep.name.clone(), site.clone()));
+                        }
+                    }
+                    break;
+                }
+            };
+            let best = unassigned
+                .iter()
+                .enumerate()
+                .flat_map(|(worker, creep)| {
+                    self.sites
+                        .iter()
+                        .enumerate()
+                        .filter(|(site, _)| needed[*site] > 0)
+                        .filter(|(_, info)| build_priority(info.structure_type) == rank)
+                        .map(move |(site, info)| (creep.pos.get_range_to(info.pos), worker, site))
+                })
+                .min();
+            let (_, worker, site) = match best {
+                Some(best) => best,
+                None => break,
+            };
+            let creep = unassigned.remove(worker);
+            needed[site] = needed[site].saturating_sub(creep.store.capacity.max(1));
+            assignments.push((creep.name.clone(), self.sites[site].clone()));
+        }
+        assignments
+    }
+
+    fn run_worker(&self, creep: &CreepInfo, job: Option<&Job>) {
+        let mut creep = creep.clone();
+        let state = match run_state_machine(self.world.as_ref(), &mut creep, WorkerState::cycle) {
+            Some(state) => state,
+            None => return,
+        };
+        let creep = &creep;
+
+        if state == WorkerState::Collecting {
+            self.collect(creep);
+            return;
+        }
+        let task = match job {
+            Some(Job::Build(site)) => Task::build(site.id, site.pos),
+            Some(Job::Repair(entry)) => Task::repair(entry.id, entry.pos),
+            // nothing left to build or repair, the energy goes into the controller
+            None => match self.world.controller(self.room_name) {
+                Some(controller) => Task::upgrade(controller.id, controller.pos),
+                None => return,
+            },
+        };
+        if let Err(e) = do_task(self.world.as_ref(), creep, task) {
+            warn!("overlord:run_worker: unexpected error: {:?}", e);
+        }
+    }
+
+    // the storage, else the biggest provide of the logistics network, else the
+    // biggest pile on the ground, else the closest source. the controller
+    // container is left to the upgraders, the mining containers are shared
+    // with the haulers through their provides.
+    fn collect(&self, creep: &CreepInfo) {
+        let (buffer, provide) = {
+            let hive = self.hive.as_ref().borrow();
+            let buffer = hive
+                .logistics
+                .buffer()
+                .filter(|storage| storage.store.map(|store| store.energy > 0) == Some(true))
+                .map(|storage| (storage.id, storage.pos));
+            let provide = hive
+                .logistics
+                .provides()
+                .iter()
+                .filter(|entry| entry.resource == ResourceType::Energy)
+                .max_by_key(|entry| (entry.amount, std::cmp::Reverse(entry.pos.get_range_to(creep.pos))))
+                .cloned();
+            (buffer, provide)
+        };
+        if let Some((id, pos)) = buffer {
+            self.collect_with(creep, Task::withdraw(id, pos, ResourceType::Energy));
+            return;
+        }
+        if let Some(entry) = provide {
+            let task = match entry.target {
+                LogisticsTarget::Structure(id) => Task::withdraw(id, entry.pos, ResourceType::Energy),
+                LogisticsTarget::Dropped(id) => Task::pickup(id, entry.pos),
+                // creeps only ever request
+                LogisticsTarget::Creep(_) => return,
+            };
+            self.collect_with(creep, task);
+            return;
+        }
+        if let Some(resource) = self
+            .world
+            .dropped_resources(self.room_name)
+            .into_iter()
+            .filter(|resource| resource.resource_type == ResourceType::Energy)
+            .max_by_key(|resource| resource.amount)
+        {
+            self.collect_with(creep, Task::pickup(resource.id, resource.pos));
+            return;
+        }
+        if creep.count_parts(Part::Work) == 0 {
+            return;
+        }
+        if let Some(source) = self
+            .world
+            .sources(self.room_name)
+            .into_iter()
+            .filter(|source| source.energy > 0)
+            .min_by_key(|source| source.pos.get_range_to(creep.pos))
+        {
+            self.collect_with(creep, Task::harvest(source.id, source.pos));
+        }
+    }
+
+    fn collect_with(&self, creep: &CreepInfo, task: Task) {
+        match do_task(self.world.as_ref(), creep, task) {
+            Ok(()) => {}
+            // taken by someone else earlier this tick
+            Err(ErrorCode::NotEnough) | Err(ErrorCode::InvalidTarget) => {}
+            Err(e) => warn!("overlord:collect: unexpected error: {:?}", e),
+        }
+    }
+
+    // the site a creep of another overlord should help with
+    pub fn best_site(
+        world: &dyn World,
+        room_name: RoomName,
+        pos: Position,
+    ) -> Option<(RawObjectId, Position)> {
+        world
+            .construction_sites(room_name)
+            .into_iter()
+            .max_by_key(|site| {
+                (
+                    build_priority(site.structure_type),
+                    std::cmp::Reverse(site.pos.get_range_to(pos)),
+                )
+            })
+            .map(|site| (site.id, site.pos))
+    }
+}
+
+impl Overlord for WorkOverlord {
+    fn run(&self) -> Result<(), SwarmError> {
+        let workers = self.workers();
+        self.maintain_creep(workers.len());
+
+        let working: Vec<CreepInfo> = workers.into_iter().filter(|creep| !creep.spawning).collect();
+        let mut jobs: Vec<(String, Job)> = self
+            .assign_sites(&working)
+            .into_iter()
+            .map(|(name, site)| (name, Job::Build(site)))
+            .collect();
+        // repairs wait until everything is built
+        let idle: Vec<CreepInfo> = working
+            .iter()
+            .filter(|creep| !jobs.iter().any(|(name, _)| *name == creep.name))
+            .cloned()
+            .collect();
+        for (creep, entry) in self.hive.as_ref().borrow().repairs.assign(
+            &idle,
+            REPAIR_FORTIFY,
+            |creep| creep.pos,
+            |creep| creep.store.capacity * REPAIR_POWER,
+        ) {
+            jobs.push((creep.name.clone(), Job::Repair(entry)));
+        }
+        for creep in working.iter() {
+            let job = jobs
+                .iter()
+                .find(|(name, _)| *name == creep.name)
+                .map(|(_, job)| job);
+            self.run_worker(creep, job);
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), SwarmError> {
+        let name = self.get_name();
+        retain_own_creeps(self.world.as_ref(), &name, &mut self.creeps);
+        self.rcl = self
+            .world
+            .controller(self.room_name)
+            .map(|controller| controller.level)
+            .unwrap_or(0);
+        // best rank first, among equals the site closest to done
+        self.sites = self.world.construction_sites(self.room_name);
+        self.sites.sort_by_key(|site| {
+            (
+                std::cmp::Reverse(build_priority(site.structure_type)),
+                remaining(site),
+                site.pos.y().u8(),
+                site.pos.x().u8(),
+            )
+        });
+        // the haulers bring what is left to the workers
+        let workers = self.workers();
+        self.hive
+            .as_ref()
+            .borrow_mut()
+            .logistics
+            .request_for_creeps(&workers);
+        Ok(())
+    }
+
+    fn assign_creep(&mut self, creep_name: String) {
+        if self.creeps.contains(&creep_name) {
+            return;
+        }
+        self.creeps.push(creep_name);
+    }
+
+    fn get_name(&self) -> String {
+        Self::get_name_by_room(self.room_name)
+    }
+
+    fn get_type(&self) -> OverlordType {
+        self.overlord_type
+    }
+
+    fn get_cache(&self) -> String {
+        let cache = WorkOverlordCache {
+            creeps: self.creeps.clone(),
+        };
+        serde_json::to_string(&cache).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        creep_setup::creep_setup_templates::ROLE_WORKER,
+        world::mock::{position, MockAction, MockWorld},
+        zerg::{CreepMemory, CreepState},
+    };
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (Rc<MockWorld>, Rc<RefCell<Hive>>) {
+        let world = Rc::new(MockWorld::new());
+        world.add_room(ROOM, 550, 550);
+        world.add_controller(position(ROOM, 10, 10), 2);
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        let hive = Hive::new(world.clone(), RoomName::new(ROOM).unwrap()).unwrap();
+        (world, hive)
+    }
+
+    fn add_worker(world: &MockWorld, overlord: &mut WorkOverlord, name: &str, x: u8, y: u8) {
+        world.add_creep(
+            name,
+            position(ROOM, x, y),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: overlord.get_name(),
+                role: ROLE_WORKER.to_string(),
+                colony: ROOM.to_string(),
+                state: Some(CreepState::Worker(WorkerState::Building)),
+                task: None,
+            },
+        );
+        world.set_creep_energy(name, 50);
+        overlord.assign_creep(name.to_string());
+    }
+
+    fn build_targets(world: &MockWorld) -> Vec<(String, RawObjectId)> {
+        let mut targets: Vec<(String, RawObjectId)> = world
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                MockAction::Build { creep, target } => Some((creep, target)),
+                _ => None,
+            })
+            .collect();
+        targets.sort();
+        targets
+    }
+
+    #[test]
+    fn ranks_sites_by_structure_type() {
+        let mut ranked = vec![
+            StructureType::Wall,
+            StructureType::Road,
+            StructureType::Tower,
+            StructureType::Extension,
+            StructureType::Container,
+            StructureType::Spawn,
+        ];
+        ranked.sort_by_key(|structure_type| std::cmp::Reverse(build_priority(*structure_type)));
+        assert_eq!(
+            ranked,
+            vec![
+                StructureType::Spawn,
+                StructureType::Extension,
+                StructureType::Container,
+                StructureType::Tower,
+                StructureType::Road,
+                StructureType::Wall,
+            ]
+        );
+    }
+
+    #[test]
+    fn workers_scale_with_remaining_progress() {
+        let (world, hive) = setup();
+        for x in 20..23 {
+            world.add_construction_site(StructureType::Extension, position(ROOM, x, 20));
+        }
+        let overlord = WorkOverlord::new(world.clone(), hive.clone()).unwrap();
+        // three extensions are 9000 progress
+        assert_eq!(overlord.workers_needed(), 2);
+
+        let (world, hive) = setup();
+        let overlord = WorkOverlord::new(world.clone(), hive).unwrap();
+        assert_eq!(overlord.workers_needed(), 0);
+    }
+
+    #[test]
+    fn extension_goes_before_road() {
+        let (world, hive) = setup();
+        world.add_construction_site(StructureType::Road, position(ROOM, 20, 21));
+        let extension = world.add_construction_site(StructureType::Extension, position(ROOM, 22, 22));
+        let mut overlord = WorkOverlord::new(world.clone(), hive).unwrap();
+        add_worker(&world, &mut overlord, "worker", 20, 20);
+        world.take_actions();
+        overlord.run().unwrap();
+
+        assert_eq!(build_targets(&world), vec![("worker".to_string(), extension)]);
+    }
+
+    #[test]
+    fn workers_spread_over_sites_of_equal_rank() {
+        let (world, hive) = setup();
+        let almost_done = world.add_construction_site(StructureType::Road, position(ROOM, 20, 20));
+        world.set_site_progress(almost_done, 280);
+        let other = world.add_construction_site(StructureType::Road, position(ROOM, 22, 20));
+        let mut overlord = WorkOverlord::new(world.clone(), hive).unwrap();
+        add_worker(&world, &mut overlord, "a", 20, 21);
+        add_worker(&world, &mut overlord, "b", 20, 22);
+        world.take_actions();
+        overlord.run().unwrap();
+
+        // one load finishes the first road, the second worker moves on
+        assert_eq!(
+            build_targets(&world),
+            vec![("a".to_string(), almost_done), ("b".to_string(), other)]
+        );
+    }
+
+    #[test]
+    fn workers_repair_when_nothing_is_left_to_build() {
+        let (world, hive) = setup();
+        let road = world.add_structure(StructureType::Road, position(ROOM, 21, 21), None);
+        world.set_structure_hits(road, 1000, 5000);
+        hive.as_ref().borrow_mut().refresh().unwrap();
+        let mut overlord = WorkOverlord::new(world.clone(), hive).unwrap();
+        assert_eq!(overlord.workers_needed(), 1);
+        add_worker(&world, &mut overlord, "worker", 20, 20);
+        world.take_actions();
+        overlord.run().unwrap();
+
+        assert!(world.take_actions().contains(&MockAction::Repair {
+            creep: "worker".to_string(),
+            target: road,
+        }));
+    }
+
+    #[test]
+    fn workers_collect_from_provides_not_the_controller_container() {
+        let (world, hive) = setup();
+        let store = Some(crate::world::StoreInfo {
+            energy: 1000,
+            used: 1000,
+            capacity: 2000,
+        });
+        // the controller container is fuller and closer
+        let full = Some(crate::world::StoreInfo {
+            energy: 2000,
+            used: 2000,
+            capacity: 2000,
+        });
+        world.add_structure(StructureType::Container, position(ROOM, 11, 11), full);
+        let mining = world.add_structure(StructureType::Container, position(ROOM, 40, 40), store);
+        hive.as_ref().borrow_mut().logistics.provide(crate::hive::LogisticsEntry {
+            target: LogisticsTarget::Structure(mining),
+            pos: position(ROOM, 40, 40),
+            resource: ResourceType::Energy,
+            amount: 500,
+            priority: DEFAULT_PRIORITY,
+        });
+        let mut overlord = WorkOverlord::new(world.clone(), hive).unwrap();
+        add_worker(&world, &mut overlord, "worker", 12, 12);
+        world.set_creep_energy("worker", 0);
+        overlord.run().unwrap();
+
+        let memory = world.creep("worker").unwrap().memory.unwrap();
+        assert_eq!(memory.state, Some(CreepState::Worker(WorkerState::Collecting)));
+        assert_eq!(
+            memory.task,
+            Some(Task::withdraw(mining, position(ROOM, 40, 40), ResourceType::Energy))
+        );
+    }
+}
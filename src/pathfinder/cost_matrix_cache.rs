@@ -0,0 +1,206 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use screeps::{
+    LocalCostMatrix, LocalRoomTerrain, Part, Position, RoomName, StructureType, Terrain,
+};
+
+use super::{RoomCosts, IMPASSABLE, ROAD_COST};
+use crate::{planner::blocks_movement, world::World};
+
+// ticks the structures of a room are trusted before they are read again,
+// creeps stuck on a new structure search again anyway
+const STRUCTURES_TTL: u32 = 20;
+// tiles hostiles can hit are crossed only when there is no other way
+const HOSTILE_COST: u8 = 50;
+const RANGED_ATTACK_RANGE: u32 = 3;
+const ATTACK_RANGE: u32 = 1;
+
+// the cost matrices of the rooms we path through, built from the world. the
+// terrain is read once, structures every `STRUCTURES_TTL` ticks, creeps and
+// hostiles on every build.
+#[derive(Default)]
+pub struct CostMatrixCache {
+    terrain: HashMap<RoomName, LocalRoomTerrain>,
+    // roads and the tiles structures block, by the tick they were read
+    structures: HashMap<RoomName, (u32, LocalCostMatrix)>,
+}
+
+impl CostMatrixCache {
+    // the costs of a room for `pathfinder::search`, none for rooms without
+    // terrain. the creeps in the room are in the way when `creeps`.
+    pub fn room_costs(
+        &mut self,
+        world: &dyn World,
+        room_name: RoomName,
+        creeps: bool,
+    ) -> Option<RoomCosts> {
+        let terrain = self.terrain(world, room_name)?.clone();
+        let mut matrix = self.structures(world, room_name).clone();
+
+        for hostile in world.hostile_creeps(room_name) {
+            let range = if hostile.count_parts(Part::RangedAttack) > 0 {
+                RANGED_ATTACK_RANGE
+            } else if hostile.count_parts(Part::Attack) > 0 {
+                ATTACK_RANGE
+            } else {
+                continue;
+            };
+            for (xy, cost) in matrix.iter_mut() {
+                if *cost != IMPASSABLE
+                    && hostile
+                        .pos
+                        .get_range_to(Position::new(xy.x, xy.y, room_name))
+                        <= range
+                {
+                    *cost = HOSTILE_COST;
+                }
+            }
+        }
+        if creeps {
+            let ours = world.creeps().into_iter().map(|creep| creep.pos);
+            let hostiles = world
+                .hostile_creeps(room_name)
+                .into_iter()
+                .map(|hostile| hostile.pos);
+            for pos in ours
+                .chain(hostiles)
+                .filter(|pos| pos.room_name() == room_name)
+            {
+                matrix.set(pos.xy(), IMPASSABLE);
+            }
+        }
+        Some(RoomCosts { terrain, matrix })
+    }
+
+    // whether a creep can stand on the tile, by terrain and structures. rooms
+    // not built yet are taken as blocked.
+    pub fn walkable(&self, pos: Position) -> bool {
+        let room_name = pos.room_name();
+        let terrain = self.terrain.get(&room_name);
+        let structures = self.structures.get(&room_name);
+        match (terrain, structures) {
+            (Some(terrain), Some((_, matrix))) => {
+                terrain.get(pos.xy()) != Terrain::Wall && matrix.get(pos.xy()) != IMPASSABLE
+            }
+            _ => false,
+        }
+    }
+
+    fn terrain(&mut self, world: &dyn World, room_name: RoomName) -> Option<&LocalRoomTerrain> {
+        if let Entry::Vacant(entry) = self.terrain.entry(room_name) {
+            entry.insert(world.terrain(room_name)?);
+        }
+        self.terrain.get(&room_name)
+    }
+
+    // roads are cheap. structures we can't walk onto, ramparts of others,
+    // sources, minerals and the controller are impassable.
+    fn structures(&mut self, world: &dyn World, room_name: RoomName) -> &LocalCostMatrix {
+        let time = world.time();
+        let fresh = self
+            .structures
+            .get(&room_name)
+            .is_some_and(|(built, _)| built + STRUCTURES_TTL > time);
+        if !fresh {
+            let mut matrix = LocalCostMatrix::new();
+            for structure in world.structures(room_name) {
+                let hostile_rampart =
+                    structure.structure_type == StructureType::Rampart && !structure.my;
+                if blocks_movement(structure.structure_type) || hostile_rampart {
+                    matrix.set(structure.pos.xy(), IMPASSABLE);
+                } else if structure.structure_type == StructureType::Road
+                    && matrix.get(structure.pos.xy()) == 0
+                {
+                    matrix.set(structure.pos.xy(), ROAD_COST);
+                }
+            }
+            let sources = world
+                .sources(room_name)
+                .into_iter()
+                .map(|source| source.pos);
+            let minerals = world
+                .minerals(room_name)
+                .into_iter()
+                .map(|mineral| mineral.pos);
+            let controller = world.controller(room_name).map(|controller| controller.pos);
+            for pos in sources.chain(minerals).chain(controller) {
+                matrix.set(pos.xy(), IMPASSABLE);
+            }
+            self.structures.insert(room_name, (time, matrix));
+        }
+        &self.structures[&room_name].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::{position, MockWorld};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (MockWorld, RoomName) {
+        let world = MockWorld::new();
+        world.add_room(ROOM, 300, 300);
+        (world, RoomName::new(ROOM).unwrap())
+    }
+
+    fn cost(costs: &RoomCosts, x: u8, y: u8) -> u8 {
+        costs.matrix.get(position(ROOM, x, y).xy())
+    }
+
+    #[test]
+    fn structures_roads_and_sources() {
+        let (world, room_name) = setup();
+        world.add_structure(StructureType::Extension, position(ROOM, 10, 10), None);
+        world.add_structure(StructureType::Road, position(ROOM, 11, 10), None);
+        world.add_structure(StructureType::Container, position(ROOM, 12, 10), None);
+        world.add_source(position(ROOM, 20, 20));
+        let mut cache = CostMatrixCache::default();
+        let costs = cache.room_costs(&world, room_name, false).unwrap();
+        assert_eq!(cost(&costs, 10, 10), IMPASSABLE);
+        assert_eq!(cost(&costs, 11, 10), ROAD_COST);
+        assert_eq!(cost(&costs, 12, 10), 0);
+        assert_eq!(cost(&costs, 20, 20), IMPASSABLE);
+        assert!(!cache.walkable(position(ROOM, 10, 10)));
+        assert!(cache.walkable(position(ROOM, 11, 10)));
+
+        // new structures show up once the matrix is stale
+        world.add_structure(StructureType::Tower, position(ROOM, 30, 30), None);
+        world.state.borrow_mut().time += STRUCTURES_TTL - 1;
+        assert_eq!(
+            cost(&cache.room_costs(&world, room_name, false).unwrap(), 30, 30),
+            0
+        );
+        world.state.borrow_mut().time += 1;
+        assert_eq!(
+            cost(&cache.room_costs(&world, room_name, false).unwrap(), 30, 30),
+            IMPASSABLE
+        );
+    }
+
+    #[test]
+    fn creeps_and_hostiles() {
+        let (world, room_name) = setup();
+        world.add_hostile_creep(
+            "invader",
+            position(ROOM, 20, 20),
+            &[Part::RangedAttack, Part::Move],
+        );
+        world.add_hostile_creep("scout", position(ROOM, 40, 40), &[Part::Move]);
+        let mut cache = CostMatrixCache::default();
+        let costs = cache.room_costs(&world, room_name, false).unwrap();
+        assert_eq!(cost(&costs, 23, 23), HOSTILE_COST);
+        assert_eq!(cost(&costs, 24, 23), 0);
+        assert_eq!(cost(&costs, 40, 40), 0);
+
+        let costs = cache.room_costs(&world, room_name, true).unwrap();
+        assert_eq!(cost(&costs, 20, 20), IMPASSABLE);
+        assert_eq!(cost(&costs, 40, 40), IMPASSABLE);
+        // creeps are not cached
+        assert_eq!(
+            cost(&cache.room_costs(&world, room_name, false).unwrap(), 40, 40),
+            0
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+};
+
+use screeps::{Direction, LocalCostMatrix, LocalRoomTerrain, Position, RoomName, Terrain};
+
+mod cost_matrix_cache;
+mod route;
+
+pub use cost_matrix_cache::CostMatrixCache;
+pub use route::find_route;
+
+// cost matrix values, tiles left at 0 cost what their terrain costs like in
+// the game
+pub const ROAD_COST: u8 = 1;
+pub const IMPASSABLE: u8 = u8::MAX;
+
+const DIRECTIONS: [Direction; 8] = [
+    Direction::Top,
+    Direction::TopRight,
+    Direction::Right,
+    Direction::BottomRight,
+    Direction::Bottom,
+    Direction::BottomLeft,
+    Direction::Left,
+    Direction::TopLeft,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub plain_cost: u32,
+    pub swamp_cost: u32,
+    // tiles expanded before the search gives up
+    pub max_ops: u32,
+    // rooms the search may load, the one it starts in included
+    pub max_rooms: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            plain_cost: 2,
+            swamp_cost: 10,
+            max_ops: 20_000,
+            max_rooms: 16,
+        }
+    }
+}
+
+// a room as the search sees it
+pub struct RoomCosts {
+    pub terrain: LocalRoomTerrain,
+    pub matrix: LocalCostMatrix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    // the tiles to walk, the start left out
+    pub path: Vec<Position>,
+    pub cost: u32,
+    pub ops: u32,
+    // no way to the goal was found, the path leads as close as the search got
+    pub incomplete: bool,
+}
+
+// keeps a search in its room, creeps stepping on an exit end up in the next
+pub fn block_exits(matrix: &mut LocalCostMatrix) {
+    for (xy, cost) in matrix.iter_mut() {
+        if [xy.x.u8(), xy.y.u8()].iter().any(|c| *c == 0 || *c == 49) {
+            *cost = IMPASSABLE;
+        }
+    }
+}
+
+// the tiles next to `pos`. rooms are only left straight through an exit, like
+// creeps do.
+pub fn neighbours(pos: Position) -> impl Iterator<Item = Position> {
+    DIRECTIONS.iter().filter_map(move |direction| {
+        let next = pos.checked_add_direction(*direction).ok()?;
+        let crosses = next.room_name() != pos.room_name();
+        (!crosses || !direction.is_diagonal()).then_some(next)
+    })
+}
+
+// a* from `from` to the first tile within `range` of `goal`, over the rooms
+// `room_costs` knows. rooms it has none for are never entered.
+pub fn search(
+    from: Position,
+    goal: Position,
+    range: u32,
+    options: &SearchOptions,
+    room_costs: impl FnMut(RoomName) -> Option<RoomCosts>,
+) -> SearchResult {
+    let mut rooms = Rooms {
+        room_costs,
+        options,
+        tiles: HashMap::new(),
+    };
+    // every step costs at least 1, so the range is a lower bound of the cost
+    let heuristic = |pos: Position| pos.get_range_to(goal).saturating_sub(range);
+
+    // tile -> cost so far and the tile it was reached from
+    let mut reached: HashMap<Position, (u32, Option<Position>)> = HashMap::new();
+    let mut closed: HashSet<Position> = HashSet::new();
+    let mut open = BinaryHeap::new();
+    reached.insert(from, (0, None));
+    open.push(Reverse((heuristic(from), heuristic(from), from)));
+    let mut closest = (heuristic(from), from);
+    let mut ops = 0;
+    let mut found = None;
+
+    while let Some(Reverse((_, distance, pos))) = open.pop() {
+        if !closed.insert(pos) {
+            continue;
+        }
+        if distance == 0 {
+            found = Some(pos);
+            break;
+        }
+        if distance < closest.0 {
+            closest = (distance, pos);
+        }
+        ops += 1;
+        if ops > options.max_ops {
+            break;
+        }
+        let cost = reached[&pos].0;
+        for next in neighbours(pos) {
+            let step = match rooms.cost(next) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = cost + step;
+            match reached.entry(next) {
+                Entry::Occupied(entry) if entry.get().0 <= next_cost => continue,
+                entry => {
+                    entry.insert_entry((next_cost, Some(pos)));
+                }
+            }
+            let distance = heuristic(next);
+            open.push(Reverse((next_cost + distance, distance, next)));
+        }
+    }
+
+    let end = found.unwrap_or(closest.1);
+    let mut path = Vec::new();
+    let mut current = end;
+    while let Some((_, Some(previous))) = reached.get(&current) {
+        path.push(current);
+        current = *previous;
+    }
+    path.reverse();
+    SearchResult {
+        path,
+        cost: reached[&end].0,
+        ops,
+        incomplete: found.is_none(),
+    }
+}
+
+// the tile costs of the rooms loaded so far, none for rooms not to enter
+struct Rooms<'a, F> {
+    room_costs: F,
+    options: &'a SearchOptions,
+    tiles: HashMap<RoomName, Option<Vec<Option<u32>>>>,
+}
+
+impl<F> Rooms<'_, F>
+where
+    F: FnMut(RoomName) -> Option<RoomCosts>,
+{
+    fn cost(&mut self, pos: Position) -> Option<u32> {
+        let room_name = pos.room_name();
+        if !self.tiles.contains_key(&room_name) {
+            let costs = if self.tiles.len() < self.options.max_rooms {
+                (self.room_costs)(room_name).map(|costs| self.tile_costs(&costs))
+            } else {
+                None
+            };
+            self.tiles.insert(room_name, costs);
+        }
+        let tiles = self.tiles[&room_name].as_ref()?;
+        // column by column, the layout of the cost matrix
+        tiles[pos.x().u8() as usize * 50 + pos.y().u8() as usize]
+    }
+
+    fn tile_costs(&self, costs: &RoomCosts) -> Vec<Option<u32>> {
+        costs
+            .matrix
+            .iter()
+            .map(|(xy, cost)| match (cost, costs.terrain.get(xy)) {
+                (IMPASSABLE, _) => None,
+                (0, Terrain::Wall) => None,
+                (0, Terrain::Swamp) => Some(self.options.swamp_cost),
+                (0, Terrain::Plain) => Some(self.options.plain_cost),
+                (cost, _) => Some(cost as u32),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::position;
+    use screeps::RoomXY;
+
+    const ROOM: &str = "W1N1";
+
+    fn terrain(walls: &[(u8, u8)], swamps: &[(u8, u8)]) -> LocalRoomTerrain {
+        let mut bits = Box::new([0u8; 2500]);
+        for (x, y) in walls {
+            bits[*y as usize * 50 + *x as usize] = 1;
+        }
+        for (x, y) in swamps {
+            bits[*y as usize * 50 + *x as usize] = 2;
+        }
+        LocalRoomTerrain::new_from_bits(bits)
+    }
+
+    fn open_room(_: RoomName) -> Option<RoomCosts> {
+        Some(RoomCosts {
+            terrain: terrain(&[], &[]),
+            matrix: LocalCostMatrix::new(),
+        })
+    }
+
+    fn room(name: &str) -> RoomName {
+        RoomName::new(name).unwrap()
+    }
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        position(ROOM, x, y).xy()
+    }
+
+    #[test]
+    fn straight_line_in_an_open_room() {
+        let result = search(
+            position(ROOM, 10, 10),
+            position(ROOM, 20, 10),
+            1,
+            &SearchOptions::default(),
+            open_room,
+        );
+        assert!(!result.incomplete);
+        assert_eq!(result.path.len(), 9);
+        assert_eq!(result.cost, 18);
+        assert_eq!(
+            result
+                .path
+                .last()
+                .unwrap()
+                .get_range_to(position(ROOM, 20, 10)),
+            1
+        );
+    }
+
+    #[test]
+    fn follows_roads_around_swamps() {
+        // a swamp band across the way with a road over it further north
+        let swamps: Vec<(u8, u8)> = (3..47)
+            .flat_map(|y| (14..=16).map(move |x| (x, y)))
+            .collect();
+        let mut matrix = LocalCostMatrix::new();
+        for x in 13..=17 {
+            matrix.set(xy(x, 5), ROAD_COST);
+        }
+        let result = search(
+            position(ROOM, 10, 10),
+            position(ROOM, 20, 10),
+            0,
+            &SearchOptions::default(),
+            |_| {
+                Some(RoomCosts {
+                    terrain: terrain(&[], &swamps),
+                    matrix: matrix.clone(),
+                })
+            },
+        );
+        assert!(!result.incomplete);
+        assert!(result.path.contains(&position(ROOM, 15, 5)));
+    }
+
+    #[test]
+    fn impassable_tiles_and_walls_are_avoided() {
+        let walls: Vec<(u8, u8)> = (0..50).filter(|y| *y != 30).map(|y| (15, y)).collect();
+        let result = search(
+            position(ROOM, 10, 10),
+            position(ROOM, 20, 10),
+            0,
+            &SearchOptions::default(),
+            |_| {
+                Some(RoomCosts {
+                    terrain: terrain(&walls, &[]),
+                    matrix: LocalCostMatrix::new(),
+                })
+            },
+        );
+        assert!(result.path.contains(&position(ROOM, 15, 30)));
+
+        let mut matrix = LocalCostMatrix::new();
+        matrix.set(xy(15, 30), IMPASSABLE);
+        let result = search(
+            position(ROOM, 10, 10),
+            position(ROOM, 20, 10),
+            0,
+            &SearchOptions::default(),
+            |_| {
+                Some(RoomCosts {
+                    terrain: terrain(&walls, &[]),
+                    matrix: matrix.clone(),
+                })
+            },
+        );
+        assert!(result.incomplete);
+        // as close as it gets on this side of the wall
+        assert_eq!(result.path.last().unwrap().x().u8(), 14);
+    }
+
+    #[test]
+    fn crosses_into_the_next_room() {
+        let result = search(
+            position("W1N1", 45, 25),
+            position("W0N1", 5, 25),
+            0,
+            &SearchOptions::default(),
+            open_room,
+        );
+        assert!(!result.incomplete);
+        assert_eq!(result.path.len(), 10);
+        assert!(result
+            .path
+            .iter()
+            .any(|pos| pos.room_name() == room("W0N1") && pos.x().u8() == 0));
+        assert_eq!(result.path.last(), Some(&position("W0N1", 5, 25)));
+
+        // rooms without costs are not entered
+        let result = search(
+            position("W1N1", 45, 25),
+            position("W0N1", 5, 25),
+            0,
+            &SearchOptions::default(),
+            |room_name| (room_name == room("W1N1")).then(|| open_room(room_name).unwrap()),
+        );
+        assert!(result.incomplete);
+    }
+}
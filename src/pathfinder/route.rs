@@ -0,0 +1,106 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use screeps::RoomName;
+
+// rooms looked at before a route is given up
+const MAX_ROUTE_OPS: u32 = 500;
+
+const NEIGHBOURS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+// the rooms to cross from `from` to `to`, both included. `room_cost` is what
+// entering a room costs, rooms it has none for are avoided. every room is taken
+// to open into its four neighbours, the tile search finds out when it doesn't.
+pub fn find_route(
+    from: RoomName,
+    to: RoomName,
+    mut room_cost: impl FnMut(RoomName) -> Option<u32>,
+) -> Option<Vec<RoomName>> {
+    let distance = |room: RoomName| {
+        room.x_coord().abs_diff(to.x_coord()) + room.y_coord().abs_diff(to.y_coord())
+    };
+    let mut reached: HashMap<RoomName, (u32, Option<RoomName>)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    reached.insert(from, (0, None));
+    open.push(Reverse((distance(from), 0, from)));
+    let mut ops = 0;
+
+    while let Some(Reverse((_, cost, room))) = open.pop() {
+        if room == to {
+            let mut route = vec![to];
+            let mut current = to;
+            while let Some((_, Some(previous))) = reached.get(&current) {
+                route.push(*previous);
+                current = *previous;
+            }
+            route.reverse();
+            return Some(route);
+        }
+        if cost > reached[&room].0 {
+            continue;
+        }
+        ops += 1;
+        if ops > MAX_ROUTE_OPS {
+            break;
+        }
+        for offset in NEIGHBOURS {
+            let next = match room.checked_add(offset) {
+                Some(next) => next,
+                None => continue,
+            };
+            if reached.get(&next).is_some_and(|(known, _)| *known <= cost) {
+                continue;
+            }
+            let step = match room_cost(next) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = cost + step;
+            if reached
+                .get(&next)
+                .is_some_and(|(known, _)| *known <= next_cost)
+            {
+                continue;
+            }
+            reached.insert(next, (next_cost, Some(room)));
+            open.push(Reverse((next_cost + distance(next), next_cost, next)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(name: &str) -> RoomName {
+        RoomName::new(name).unwrap()
+    }
+
+    #[test]
+    fn straight_to_the_target() {
+        let route = find_route(room("W1N1"), room("W3N1"), |_| Some(1));
+        assert_eq!(route, Some(vec![room("W1N1"), room("W2N1"), room("W3N1")]));
+        assert_eq!(
+            find_route(room("W1N1"), room("W1N1"), |_| Some(1)),
+            Some(vec![room("W1N1")])
+        );
+    }
+
+    #[test]
+    fn avoids_rooms_without_cost() {
+        let blocked = room("W2N1");
+        let route =
+            find_route(room("W1N1"), room("W3N1"), |r| (r != blocked).then_some(1)).unwrap();
+        assert_eq!(route.len(), 5);
+        assert!(!route.contains(&blocked));
+
+        // walled in
+        let route = find_route(room("W1N1"), room("W3N1"), |r| {
+            (r == room("W1N1")).then_some(1)
+        });
+        assert_eq!(route, None);
+    }
+}
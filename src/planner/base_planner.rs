@@ -0,0 +1,582 @@
+use std::collections::VecDeque;
+
+use screeps::{LocalRoomTerrain, RoomCoordinate, RoomXY, StructureType, Terrain};
+
+use crate::error::SwarmError;
+
+use super::{PlannedStructure, RoomPlan};
+
+const ROOM_SIZE: i32 = 50;
+// structures keep off the tiles next to the exits, they are left to walls and
+// ramparts
+const EDGE_MARGIN: i32 = 2;
+// the core stamp reaches this far from the anchor
+const CORE_RADIUS: i32 = 2;
+// left free for the miners and their containers
+const SOURCE_RESERVE: u32 = 1;
+// left free for the upgraders and the controller container
+const CONTROLLER_RESERVE: u32 = 2;
+// see `LinkNetwork`, links are classified by these ranges
+const SOURCE_LINK_RANGE: u32 = 2;
+const CONTROLLER_LINK_RANGE: u32 = 3;
+// the roads of the base are not worth their energy before the towers
+const ROAD_MIN_RCL: u8 = 3;
+
+// the core around the anchor, in the order the structures are unlocked. the
+// first spawn decides where the anchor is in a room that already has one.
+const CORE: [(StructureType, (i32, i32)); 16] = [
+    (StructureType::Spawn, (0, -1)),
+    (StructureType::Storage, (1, 0)),
+    (StructureType::Link, (-1, 0)),
+    (StructureType::Terminal, (0, 1)),
+    (StructureType::Spawn, (-2, 0)),
+    (StructureType::Spawn, (2, 0)),
+    (StructureType::Factory, (0, 2)),
+    (StructureType::PowerSpawn, (0, -2)),
+    (StructureType::Nuker, (1, -2)),
+    (StructureType::Observer, (-1, -2)),
+    (StructureType::Tower, (2, 1)),
+    (StructureType::Tower, (-2, -1)),
+    (StructureType::Tower, (2, -1)),
+    (StructureType::Tower, (-2, 1)),
+    (StructureType::Tower, (1, 2)),
+    (StructureType::Tower, (-1, 2)),
+];
+
+// ten labs around a lattice cell, the first two are in range 2 of all others
+// so they can feed every reaction
+const LABS: [(i32, i32); 10] = [
+    (0, 0),
+    (1, 0),
+    (-1, 0),
+    (0, -1),
+    (0, 1),
+    (2, -1),
+    (2, 1),
+    (1, -2),
+    (1, 2),
+    (-1, -2),
+];
+
+pub struct PlannerInput<'a> {
+    pub terrain: &'a LocalRoomTerrain,
+    pub controller: (u8, u8),
+    pub sources: Vec<(u8, u8)>,
+    pub mineral: Option<(u8, u8)>,
+    // the first spawn, placed by hand
+    pub spawn: Option<(u8, u8)>,
+}
+
+// the base grows around an anchor on a diagonal road lattice: a tile is a road
+// when it lies on one of the diagonals through the anchor repeated every four
+// tiles. every other tile touches one of these roads, so it can hold a
+// structure that stays reachable.
+pub fn plan_base(input: &PlannerInput) -> Result<RoomPlan, SwarmError> {
+    let mut planner = BasePlanner::new(input);
+    let anchor = match input.spawn {
+        Some((x, y)) => {
+            let (dx, dy) = CORE[0].1;
+            let anchor = (x as i32 - dx, y as i32 - dy);
+            if !in_room(anchor) {
+                return Err(SwarmError::InternalAssertionFailed(
+                    "the spawn is too close to the edge for the core".to_string(),
+                ));
+            }
+            anchor
+        }
+        None => planner.find_anchor(input).ok_or(SwarmError::InternalAssertionFailed(
+            "no room for the core of the base".to_string(),
+        ))?,
+    };
+    planner.anchor = anchor;
+    planner.distances = walk_distances(input.terrain, &[anchor]);
+
+    planner.place_core();
+    planner.place_links(input);
+    if let Some(mineral) = input.mineral {
+        planner.add(StructureType::Extractor, (mineral.0 as i32, mineral.1 as i32));
+    }
+    planner.place_labs();
+    planner.place_extensions();
+    planner.place_roads();
+
+    Ok(RoomPlan {
+        anchor: (anchor.0 as u8, anchor.1 as u8),
+        structures: planner.unlock(),
+        roads: Vec::new(),
+        road_layout: 0,
+    })
+}
+
+struct BasePlanner<'a> {
+    terrain: &'a LocalRoomTerrain,
+    anchor: (i32, i32),
+    // tiles next to sources, the mineral and the controller
+    reserved: Vec<bool>,
+    used: Vec<bool>,
+    // steps from the anchor, u32::MAX where it can't be reached
+    distances: Vec<u32>,
+    planned: Vec<(StructureType, (i32, i32))>,
+    // core structures whose tile is blocked, they move to the extension area
+    displaced: Vec<StructureType>,
+}
+
+impl<'a> BasePlanner<'a> {
+    fn new(input: &PlannerInput<'a>) -> Self {
+        let mut reserved = vec![false; (ROOM_SIZE * ROOM_SIZE) as usize];
+        let mut reserve = |(x, y): (u8, u8), range: u32| {
+            for tile in tiles() {
+                if range_to(tile, (x as i32, y as i32)) <= range {
+                    reserved[index(tile)] = true;
+                }
+            }
+        };
+        for source in input.sources.iter() {
+            reserve(*source, SOURCE_RESERVE);
+        }
+        if let Some(mineral) = input.mineral {
+            reserve(mineral, SOURCE_RESERVE);
+        }
+        reserve(input.controller, CONTROLLER_RESERVE);
+
+        BasePlanner {
+            terrain: input.terrain,
+            anchor: (0, 0),
+            reserved,
+            used: vec![false; (ROOM_SIZE * ROOM_SIZE) as usize],
+            distances: Vec::new(),
+            planned: Vec::new(),
+            displaced: Vec::new(),
+        }
+    }
+
+    fn is_wall(&self, tile: (i32, i32)) -> bool {
+        self.terrain.get(xy(tile)) == Terrain::Wall
+    }
+
+    // inside the margin and not a wall, roads may go here
+    fn buildable(&self, tile: (i32, i32)) -> bool {
+        tile.0 >= EDGE_MARGIN
+            && tile.1 >= EDGE_MARGIN
+            && tile.0 < ROOM_SIZE - EDGE_MARGIN
+            && tile.1 < ROOM_SIZE - EDGE_MARGIN
+            && !self.is_wall(tile)
+    }
+
+    fn free(&self, tile: (i32, i32)) -> bool {
+        in_room(tile) && self.buildable(tile) && !self.reserved[index(tile)] && !self.used[index(tile)]
+    }
+
+    fn lattice_road(&self, tile: (i32, i32)) -> bool {
+        let (dx, dy) = (tile.0 - self.anchor.0, tile.1 - self.anchor.1);
+        (dx + dy).rem_euclid(4) == 0 || (dx - dy).rem_euclid(4) == 0
+    }
+
+    // a free tile off the roads that touches a road that can be built
+    fn lattice_slot(&self, tile: (i32, i32)) -> bool {
+        self.free(tile)
+            && !self.lattice_road(tile)
+            && neighbours(tile).any(|n| self.lattice_road(n) && self.buildable(n) && !self.used[index(n)])
+    }
+
+    fn add(&mut self, structure_type: StructureType, tile: (i32, i32)) {
+        self.used[index(tile)] = true;
+        self.planned.push((structure_type, tile));
+    }
+
+    fn distance(&self, tile: (i32, i32)) -> u32 {
+        self.distances[index(tile)]
+    }
+
+    // the tile the core fits on with the shortest walks to the controller and
+    // the sources
+    fn find_anchor(&self, input: &PlannerInput) -> Option<(i32, i32)> {
+        let mut targets: Vec<(u8, u8)> = input.sources.clone();
+        targets.push(input.controller);
+        let distances: Vec<Vec<u32>> = targets
+            .iter()
+            .map(|(x, y)| walk_distances(self.terrain, &[(*x as i32, *y as i32)]))
+            .collect();
+        tiles()
+            .filter(|tile| {
+                (-CORE_RADIUS..=CORE_RADIUS).all(|dx| {
+                    (-CORE_RADIUS..=CORE_RADIUS).all(|dy| self.free((tile.0 + dx, tile.1 + dy)))
+                })
+            })
+            .filter_map(|tile| {
+                distances
+                    .iter()
+                    .try_fold(0u32, |sum, distance| {
+                        let distance = distance[index(tile)];
+                        (distance != u32::MAX).then_some(sum + distance)
+                    })
+                    .map(|score| (score, tile.1, tile.0))
+            })
+            .min()
+            .map(|(_, y, x)| (x, y))
+    }
+
+    fn place_core(&mut self) {
+        for (structure_type, (dx, dy)) in CORE {
+            let tile = (self.anchor.0 + dx, self.anchor.1 + dy);
+            if self.free(tile) && self.distance(tile) != u32::MAX {
+                self.add(structure_type, tile);
+            } else {
+                self.displaced.push(structure_type);
+            }
+        }
+    }
+
+    // the storage link is part of the core. the source links go first, the
+    // farthest source first, then the controller link.
+    fn place_links(&mut self, input: &PlannerInput) {
+        let mut sources: Vec<(i32, i32)> = input
+            .sources
+            .iter()
+            .map(|(x, y)| (*x as i32, *y as i32))
+            .collect();
+        sources.sort_by_key(|source| std::cmp::Reverse(self.distance(*source)));
+        let controller = (input.controller.0 as i32, input.controller.1 as i32);
+        let targets = sources
+            .into_iter()
+            .map(|source| (source, SOURCE_LINK_RANGE))
+            .chain(std::iter::once((controller, CONTROLLER_LINK_RANGE)));
+        for (target, range) in targets {
+            let tile = tiles()
+                .filter(|tile| range_to(*tile, target) == range)
+                .filter(|tile| self.free(*tile) && self.distance(*tile) != u32::MAX)
+                .min_by_key(|tile| (self.lattice_road(*tile), self.distance(*tile), tile.1, tile.0));
+            if let Some(tile) = tile {
+                self.add(StructureType::Link, tile);
+            }
+        }
+    }
+
+    // the lattice cell closest to the anchor that takes the whole stamp
+    fn place_labs(&mut self) {
+        let center = tiles()
+            .filter(|tile| {
+                let (dx, dy) = (tile.0 - self.anchor.0, tile.1 - self.anchor.1);
+                (dx + dy).rem_euclid(4) == 2 && (dx - dy).rem_euclid(4) == 2
+            })
+            .filter(|tile| self.distance(*tile) != u32::MAX)
+            .filter(|tile| {
+                LABS.iter()
+                    .all(|(dx, dy)| self.lattice_slot((tile.0 + dx, tile.1 + dy)))
+            })
+            .min_by_key(|tile| (self.distance(*tile), tile.1, tile.0));
+        match center {
+            Some(center) => {
+                for (dx, dy) in LABS {
+                    self.add(StructureType::Lab, (center.0 + dx, center.1 + dy));
+                }
+            }
+            None => self.displaced.extend([StructureType::Lab; LABS.len()]),
+        }
+    }
+
+    // extensions, and whatever didn't fit its stamp, fill the lattice outwards
+    fn place_extensions(&mut self) {
+        let extensions = StructureType::Extension.controller_structures(8) as usize;
+        let mut wanted: Vec<StructureType> = std::mem::take(&mut self.displaced);
+        wanted.extend(std::iter::repeat_n(StructureType::Extension, extensions));
+
+        let mut slots: Vec<(i32, i32)> = tiles()
+            .filter(|tile| self.distance(*tile) != u32::MAX && self.lattice_slot(*tile))
+            .collect();
+        slots.sort_by_key(|tile| (self.distance(*tile), tile.1, tile.0));
+        for (structure_type, tile) in wanted.into_iter().zip(slots) {
+            self.add(structure_type, tile);
+        }
+    }
+
+    // the lattice roads next to the structures of the base
+    fn place_roads(&mut self) {
+        let base: Vec<(i32, i32)> = self
+            .planned
+            .iter()
+            .filter(|(structure_type, _)| {
+                !matches!(structure_type, StructureType::Extractor | StructureType::Link)
+            })
+            .map(|(_, tile)| *tile)
+            .chain(
+                self.planned
+                    .iter()
+                    .filter(|(structure_type, tile)| {
+                        *structure_type == StructureType::Link
+                            && range_to(*tile, self.anchor) <= CORE_RADIUS as u32
+                    })
+                    .map(|(_, tile)| *tile),
+            )
+            .collect();
+        let roads: Vec<(i32, i32)> = tiles()
+            .filter(|tile| {
+                self.lattice_road(*tile)
+                    && self.buildable(*tile)
+                    && !self.used[index(*tile)]
+                    && base.iter().any(|structure| range_to(*tile, *structure) <= 1)
+            })
+            .collect();
+        for tile in roads {
+            self.add(StructureType::Road, tile);
+        }
+    }
+
+    // the level each structure is placed at: the n-th structure of a type once
+    // the controller allows n of them, a road with the first structure it serves
+    fn unlock(&self) -> Vec<PlannedStructure> {
+        let mut structures: Vec<PlannedStructure> = Vec::new();
+        for (structure_type, tile) in self.planned.iter() {
+            if *structure_type == StructureType::Road {
+                continue;
+            }
+            let placed = structures
+                .iter()
+                .filter(|planned| planned.structure_type == *structure_type)
+                .count() as u32;
+            if let Some(rcl) = (1..=8u8).find(|rcl| structure_type.controller_structures(*rcl as u32) > placed) {
+                structures.push(PlannedStructure {
+                    structure_type: *structure_type,
+                    pos: (tile.0 as u8, tile.1 as u8),
+                    rcl,
+                });
+            }
+        }
+        let roads: Vec<PlannedStructure> = self
+            .planned
+            .iter()
+            .filter(|(structure_type, _)| *structure_type == StructureType::Road)
+            .filter_map(|(_, tile)| {
+                structures
+                    .iter()
+                    .filter(|planned| {
+                        range_to(*tile, (planned.pos.0 as i32, planned.pos.1 as i32)) <= 1
+                    })
+                    .map(|planned| planned.rcl)
+                    .min()
+                    .map(|rcl| PlannedStructure {
+                        structure_type: StructureType::Road,
+                        pos: (tile.0 as u8, tile.1 as u8),
+                        rcl: rcl.max(ROAD_MIN_RCL),
+                    })
+            })
+            .collect();
+        structures.extend(roads);
+        structures
+    }
+}
+
+fn in_room(tile: (i32, i32)) -> bool {
+    tile.0 >= 0 && tile.1 >= 0 && tile.0 < ROOM_SIZE && tile.1 < ROOM_SIZE
+}
+
+fn index(tile: (i32, i32)) -> usize {
+    (tile.1 * ROOM_SIZE + tile.0) as usize
+}
+
+fn xy(tile: (i32, i32)) -> RoomXY {
+    RoomXY {
+        x: RoomCoordinate::new(tile.0 as u8).unwrap(),
+        y: RoomCoordinate::new(tile.1 as u8).unwrap(),
+    }
+}
+
+fn range_to(a: (i32, i32), b: (i32, i32)) -> u32 {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs()) as u32
+}
+
+fn tiles() -> impl Iterator<Item = (i32, i32)> {
+    (0..ROOM_SIZE).flat_map(|y| (0..ROOM_SIZE).map(move |x| (x, y)))
+}
+
+fn neighbours(tile: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    (-1..=1)
+        .flat_map(move |dy| (-1..=1).map(move |dx| (tile.0 + dx, tile.1 + dy)))
+        .filter(move |n| *n != tile && in_room(*n))
+}
+
+// steps to the closest of `from` over all tiles that are not walls
+fn walk_distances(terrain: &LocalRoomTerrain, from: &[(i32, i32)]) -> Vec<u32> {
+    let mut distances = vec![u32::MAX; (ROOM_SIZE * ROOM_SIZE) as usize];
+    let mut queue = VecDeque::new();
+    for tile in from {
+        distances[index(*tile)] = 0;
+        queue.push_back(*tile);
+    }
+    while let Some(tile) = queue.pop_front() {
+        let distance = distances[index(tile)];
+        for n in neighbours(tile) {
+            if distances[index(n)] == u32::MAX && terrain.get(xy(n)) != Terrain::Wall {
+                distances[index(n)] = distance + 1;
+                queue.push_back(n);
+            }
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_terrain() -> LocalRoomTerrain {
+        let mut bits = Box::new([0u8; 2500]);
+        for i in 0..50 {
+            bits[i] = 1;
+            bits[49 * 50 + i] = 1;
+            bits[i * 50] = 1;
+            bits[i * 50 + 49] = 1;
+        }
+        LocalRoomTerrain::new_from_bits(bits)
+    }
+
+    fn plan(terrain: &LocalRoomTerrain, spawn: Option<(u8, u8)>) -> RoomPlan {
+        plan_base(&PlannerInput {
+            terrain,
+            controller: (25, 8),
+            sources: vec![(8, 40), (42, 12)],
+            mineral: Some((40, 42)),
+            spawn,
+        })
+        .unwrap()
+    }
+
+    fn count(plan: &RoomPlan, structure_type: StructureType) -> usize {
+        plan.structures
+            .iter()
+            .filter(|planned| planned.structure_type == structure_type)
+            .count()
+    }
+
+    #[test]
+    fn plans_everything_rcl_8_allows() {
+        let terrain = open_terrain();
+        let plan = plan(&terrain, None);
+        for structure_type in [
+            StructureType::Spawn,
+            StructureType::Extension,
+            StructureType::Tower,
+            StructureType::Lab,
+            StructureType::Storage,
+            StructureType::Terminal,
+            StructureType::Factory,
+            StructureType::PowerSpawn,
+            StructureType::Nuker,
+            StructureType::Observer,
+            StructureType::Extractor,
+        ] {
+            assert_eq!(
+                count(&plan, structure_type),
+                structure_type.controller_structures(8) as usize,
+                "{:?}",
+                structure_type
+            );
+        }
+        // storage, controller and source links
+        assert_eq!(count(&plan, StructureType::Link), 4);
+    }
+
+    #[test]
+    fn structures_are_reachable_and_do_not_overlap() {
+        let terrain = open_terrain();
+        let plan = plan(&terrain, None);
+        let roads: Vec<(u8, u8)> = plan
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Road)
+            .map(|planned| planned.pos)
+            .collect();
+        for (i, planned) in plan.structures.iter().enumerate() {
+            assert!(
+                !plan.structures[..i].iter().any(|other| other.pos == planned.pos),
+                "two structures at {:?}",
+                planned.pos
+            );
+            let tile = (planned.pos.0 as i32, planned.pos.1 as i32);
+            assert!(!terrain_is_wall(&terrain, tile));
+            if matches!(
+                planned.structure_type,
+                StructureType::Road | StructureType::Link | StructureType::Extractor
+            ) {
+                continue;
+            }
+            assert!(
+                roads
+                    .iter()
+                    .any(|road| range_to(tile, (road.0 as i32, road.1 as i32)) == 1),
+                "{:?} at {:?} has no road",
+                planned.structure_type,
+                planned.pos
+            );
+        }
+    }
+
+    fn terrain_is_wall(terrain: &LocalRoomTerrain, tile: (i32, i32)) -> bool {
+        terrain.get(xy(tile)) == Terrain::Wall
+    }
+
+    #[test]
+    fn labs_have_two_input_labs() {
+        let terrain = open_terrain();
+        let plan = plan(&terrain, None);
+        let labs: Vec<(i32, i32)> = plan
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Lab)
+            .map(|planned| (planned.pos.0 as i32, planned.pos.1 as i32))
+            .collect();
+        assert!(labs[2..]
+            .iter()
+            .all(|lab| range_to(*lab, labs[0]) <= 2 && range_to(*lab, labs[1]) <= 2));
+    }
+
+    #[test]
+    fn unlocks_structures_by_controller_level() {
+        let terrain = open_terrain();
+        let plan = plan(&terrain, None);
+        let unlocked = |rcl: u8, structure_type: StructureType| {
+            plan.structures
+                .iter()
+                .filter(|planned| planned.structure_type == structure_type && planned.rcl <= rcl)
+                .count()
+        };
+        assert_eq!(unlocked(1, StructureType::Spawn), 1);
+        assert_eq!(unlocked(1, StructureType::Extension), 0);
+        assert_eq!(unlocked(2, StructureType::Extension), 5);
+        assert_eq!(unlocked(2, StructureType::Road), 0);
+        assert_eq!(unlocked(3, StructureType::Tower), 1);
+        assert_eq!(unlocked(8, StructureType::Spawn), 3);
+    }
+
+    #[test]
+    fn core_is_built_around_the_existing_spawn() {
+        let terrain = open_terrain();
+        let plan = plan(&terrain, Some((25, 25)));
+        assert_eq!(plan.anchor, (25, 26));
+        assert!(plan.structures.contains(&PlannedStructure {
+            structure_type: StructureType::Spawn,
+            pos: (25, 25),
+            rcl: 1,
+        }));
+    }
+
+    #[test]
+    fn keeps_off_walls_and_the_tiles_around_sources() {
+        let mut bits = Box::new([0u8; 2500]);
+        // a wall across the room, with a gap
+        for x in 0..45 {
+            bits[30 * 50 + x] = 1;
+        }
+        let terrain = LocalRoomTerrain::new_from_bits(bits);
+        let plan = plan(&terrain, None);
+        for planned in plan.structures.iter() {
+            let tile = (planned.pos.0 as i32, planned.pos.1 as i32);
+            assert!(!terrain_is_wall(&terrain, tile));
+            if planned.structure_type != StructureType::Road {
+                assert!(range_to(tile, (8, 40)) > SOURCE_RESERVE);
+                assert!(range_to(tile, (25, 8)) > CONTROLLER_RESERVE);
+            }
+        }
+        assert_eq!(count(&plan, StructureType::Extension), 60);
+    }
+}
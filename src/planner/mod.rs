@@ -0,0 +1,340 @@
+use std::collections::HashSet;
+
+use log::{debug, warn};
+use screeps::{ErrorCode, Position, RoomCoordinate, RoomName, StructureType};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::SwarmError, overlord::work::build_priority, world::World};
+
+mod base_planner;
+mod road_planner;
+
+pub use base_planner::{plan_base, PlannerInput};
+pub use road_planner::{plan_roads, RoadCostMatrix, RoadTarget};
+
+// sites of the plan waiting in the room at the same time, the work overlord
+// builds them in order anyway
+const MAX_PLANNED_SITES: usize = 10;
+// the source and controller roads come with the first lattice roads, the
+// mineral road with the extractor
+const SOURCE_ROAD_RCL: u8 = 3;
+const CONTROLLER_ROAD_RCL: u8 = 3;
+const MINERAL_ROAD_RCL: u8 = 6;
+// upgraders work from anywhere in this range
+const CONTROLLER_ROAD_RANGE: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedStructure {
+    pub structure_type: StructureType,
+    pub pos: (u8, u8),
+    // the controller level the site is placed at
+    pub rcl: u8,
+}
+
+// full layout of a colony room up to RCL 8, planned once and kept in the
+// colony cache
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomPlan {
+    pub anchor: (u8, u8),
+    pub structures: Vec<PlannedStructure>,
+    // roads from the hub to the sources, the controller and the mineral
+    #[serde(default)]
+    pub roads: Vec<PlannedStructure>,
+    // fingerprint of the layout the roads were planned for
+    #[serde(default)]
+    pub road_layout: u64,
+}
+
+impl RoomPlan {
+    pub fn for_room(world: &dyn World, room_name: RoomName) -> Result<RoomPlan, SwarmError> {
+        let terrain = world.terrain(room_name).ok_or(SwarmError::InternalAssertionFailed(
+            "plan room without terrain".to_string(),
+        ))?;
+        let controller = world.controller(room_name).ok_or(SwarmError::InternalAssertionFailed(
+            "plan room without controller".to_string(),
+        ))?;
+        let input = PlannerInput {
+            terrain: &terrain,
+            controller: xy(controller.pos),
+            sources: world.sources(room_name).into_iter().map(|s| xy(s.pos)).collect(),
+            mineral: world.minerals(room_name).first().map(|m| xy(m.pos)),
+            spawn: world
+                .spawns(room_name)
+                .into_iter()
+                .min_by_key(|spawn| spawn.name.clone())
+                .map(|spawn| xy(spawn.pos)),
+        };
+        plan_base(&input)
+    }
+
+    // plans the road network again when the tiles it has to avoid or the
+    // places it leads to changed. returns whether the roads were planned.
+    pub fn update_roads(&mut self, world: &dyn World, room_name: RoomName) -> bool {
+        let terrain = match world.terrain(room_name) {
+            Some(terrain) => terrain,
+            None => return false,
+        };
+        let structures = world.structures(room_name);
+        let targets = road_targets(world, room_name);
+
+        let mut blocked: Vec<(u8, u8)> = self
+            .structures
+            .iter()
+            .filter(|planned| blocks_movement(planned.structure_type))
+            .map(|planned| planned.pos)
+            .chain(
+                structures
+                    .iter()
+                    .filter(|s| blocks_movement(s.structure_type))
+                    .map(|s| xy(s.pos)),
+            )
+            .chain(targets.iter().map(|target| target.pos))
+            .collect();
+        blocked.sort();
+        blocked.dedup();
+        let layout = layout_key(&blocked, &targets);
+        if layout == self.road_layout {
+            return false;
+        }
+
+        let mut matrix = RoadCostMatrix::new(&terrain);
+        for pos in blocked.iter() {
+            matrix.block(*pos);
+        }
+        let base_roads: HashSet<(u8, u8)> = self
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Road)
+            .map(|planned| planned.pos)
+            .collect();
+        for pos in base_roads.iter() {
+            matrix.set_road(*pos);
+        }
+        for road in structures.iter().filter(|s| s.structure_type == StructureType::Road) {
+            matrix.set_road(xy(road.pos));
+        }
+
+        self.roads = plan_roads(&mut matrix, self.anchor, &targets)
+            .into_iter()
+            .filter(|(pos, _)| !base_roads.contains(pos))
+            .map(|(pos, rcl)| PlannedStructure {
+                structure_type: StructureType::Road,
+                pos,
+                rcl,
+            })
+            .collect();
+        self.road_layout = layout;
+        true
+    }
+
+    // every road of the plan, the ones that are not here can go
+    pub fn wanted_roads(&self, room_name: RoomName) -> HashSet<Position> {
+        self.structures
+            .iter()
+            .chain(self.roads.iter())
+            .filter(|planned| planned.structure_type == StructureType::Road)
+            .map(|planned| position(planned.pos, room_name))
+            .collect()
+    }
+
+    // places the missing sites unlocked at `rcl`, the most important first
+    pub fn place_sites(&self, world: &dyn World, room_name: RoomName, rcl: u8) {
+        let structures = world.structures(room_name);
+        let sites = world.construction_sites(room_name);
+        let mut free_slots = MAX_PLANNED_SITES.saturating_sub(sites.len());
+        if free_slots == 0 {
+            return;
+        }
+
+        let mut missing: Vec<&PlannedStructure> = self
+            .structures
+            .iter()
+            .chain(self.roads.iter())
+            .filter(|planned| planned.rcl <= rcl)
+            .filter(|planned| {
+                let at = |pos: Position| (pos.x().u8(), pos.y().u8()) == planned.pos;
+                !structures
+                    .iter()
+                    .any(|s| at(s.pos) && s.structure_type == planned.structure_type)
+                    && !sites.iter().any(|s| at(s.pos))
+            })
+            .collect();
+        missing.sort_by_key(|planned| (std::cmp::Reverse(build_priority(planned.structure_type)), planned.rcl));
+
+        for planned in missing {
+            if free_slots == 0 {
+                break;
+            }
+            let pos = position(planned.pos, room_name);
+            match world.create_construction_site(pos, planned.structure_type) {
+                Ok(()) => {
+                    debug!("place planned {:?} at {}", planned.structure_type, pos);
+                    free_slots -= 1;
+                }
+                // hand placed structures of the same type use up the allowance,
+                // or something else stands on the tile
+                Err(ErrorCode::RclNotEnough) | Err(ErrorCode::InvalidTarget) => {}
+                Err(e) => warn!("place planned {:?} at {} failed: {:?}", planned.structure_type, pos, e),
+            }
+        }
+    }
+}
+
+// the places roads lead to: every source, the controller and the mineral
+fn road_targets(world: &dyn World, room_name: RoomName) -> Vec<RoadTarget> {
+    let mut sources: Vec<(u8, u8)> = world.sources(room_name).iter().map(|s| xy(s.pos)).collect();
+    sources.sort();
+    let mut targets: Vec<RoadTarget> = sources
+        .into_iter()
+        .map(|pos| RoadTarget {
+            pos,
+            range: 1,
+            rcl: SOURCE_ROAD_RCL,
+        })
+        .collect();
+    if let Some(controller) = world.controller(room_name) {
+        targets.push(RoadTarget {
+            pos: xy(controller.pos),
+            range: CONTROLLER_ROAD_RANGE,
+            rcl: CONTROLLER_ROAD_RCL,
+        });
+    }
+    if let Some(mineral) = world.minerals(room_name).first() {
+        targets.push(RoadTarget {
+            pos: xy(mineral.pos),
+            range: 1,
+            rcl: MINERAL_ROAD_RCL,
+        });
+    }
+    targets
+}
+
+// creeps can't walk onto these, ramparts only keep out the creeps of others
+pub fn blocks_movement(structure_type: StructureType) -> bool {
+    !matches!(
+        structure_type,
+        StructureType::Road | StructureType::Container | StructureType::Rampart
+    )
+}
+
+// fnv-1a over the tiles, stable across code uploads unlike the std hasher
+fn layout_key(blocked: &[(u8, u8)], targets: &[RoadTarget]) -> u64 {
+    blocked
+        .iter()
+        .chain(targets.iter().map(|target| &target.pos))
+        .flat_map(|pos| [pos.0, pos.1])
+        .fold(0xcbf2_9ce4_8422_2325, |key, byte| {
+            (key ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+fn xy(pos: Position) -> (u8, u8) {
+    (pos.x().u8(), pos.y().u8())
+}
+
+fn position(pos: (u8, u8), room_name: RoomName) -> Position {
+    Position::new(
+        RoomCoordinate::new(pos.0).unwrap(),
+        RoomCoordinate::new(pos.1).unwrap(),
+        room_name,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::{position, MockAction, MockWorld};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup(rcl: u8) -> MockWorld {
+        let world = MockWorld::new();
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 25, 8), rcl);
+        world.add_source(position(ROOM, 8, 40));
+        world.add_spawn("Spawn1", position(ROOM, 25, 25));
+        world
+    }
+
+    fn placed(world: &MockWorld) -> Vec<StructureType> {
+        world
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                MockAction::CreateConstructionSite { structure_type, .. } => Some(structure_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_the_extensions_of_rcl_2() {
+        let world = setup(2);
+        let room_name = RoomName::new(ROOM).unwrap();
+        let plan = RoomPlan::for_room(&world, room_name).unwrap();
+        plan.place_sites(&world, room_name, 2);
+        assert_eq!(placed(&world), vec![StructureType::Extension; 5]);
+    }
+
+    #[test]
+    fn skips_what_is_already_there() {
+        let world = setup(2);
+        let room_name = RoomName::new(ROOM).unwrap();
+        let plan = RoomPlan::for_room(&world, room_name).unwrap();
+        let extensions: Vec<(u8, u8)> = plan
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Extension && planned.rcl <= 2)
+            .map(|planned| planned.pos)
+            .collect();
+        world.add_structure(
+            StructureType::Extension,
+            position(ROOM, extensions[0].0, extensions[0].1),
+            None,
+        );
+        world.add_construction_site(
+            StructureType::Extension,
+            position(ROOM, extensions[1].0, extensions[1].1),
+        );
+        plan.place_sites(&world, room_name, 2);
+        assert_eq!(placed(&world).len(), 3);
+    }
+
+    #[test]
+    fn roads_lead_to_the_source_and_the_controller() {
+        let world = setup(3);
+        let room_name = RoomName::new(ROOM).unwrap();
+        let mut plan = RoomPlan::for_room(&world, room_name).unwrap();
+        assert!(plan.update_roads(&world, room_name));
+        let reaches = |to: (u8, u8), range: u32| {
+            plan.roads.iter().any(|road| {
+                position(ROOM, road.pos.0, road.pos.1).get_range_to(position(ROOM, to.0, to.1)) <= range
+            })
+        };
+        assert!(reaches((8, 40), 1));
+        assert!(reaches((25, 8), CONTROLLER_ROAD_RANGE));
+        // nothing changed, nothing to plan
+        assert!(!plan.update_roads(&world, room_name));
+    }
+
+    #[test]
+    fn roads_are_planned_again_around_a_new_structure() {
+        let world = setup(3);
+        let room_name = RoomName::new(ROOM).unwrap();
+        let mut plan = RoomPlan::for_room(&world, room_name).unwrap();
+        plan.update_roads(&world, room_name);
+        let blocked = plan.roads[plan.roads.len() / 2].pos;
+        world.add_structure(StructureType::Tower, position(ROOM, blocked.0, blocked.1), None);
+        assert!(plan.update_roads(&world, room_name));
+        assert!(plan.roads.iter().all(|road| road.pos != blocked));
+        assert!(!plan.wanted_roads(room_name).contains(&position(ROOM, blocked.0, blocked.1)));
+    }
+
+    #[test]
+    fn plan_round_trips_through_json() {
+        let world = setup(1);
+        let plan = RoomPlan::for_room(&world, RoomName::new(ROOM).unwrap()).unwrap();
+        let json = serde_json::to_string(&plan).unwrap();
+        assert_eq!(serde_json::from_str::<RoomPlan>(&json).unwrap(), plan);
+    }
+}
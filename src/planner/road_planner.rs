@@ -0,0 +1,176 @@
+use screeps::{
+    LocalCostMatrix, LocalRoomTerrain, Position, RoomCoordinate, RoomName, RoomXY, Terrain,
+};
+
+use crate::pathfinder::{self, RoomCosts, SearchOptions, IMPASSABLE, ROAD_COST};
+
+// roads are planned within one room, its name doesn't matter to the search
+const PLANNING_ROOM: &str = "W0N0";
+
+// a road to lay from the hub
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoadTarget {
+    pub pos: (u8, u8),
+    // the road ends this close to `pos`
+    pub range: u32,
+    pub rcl: u8,
+}
+
+// travel costs of a room for road planning: roads are cheapest, then plains,
+// then swamps. walls and blocking structures can't be crossed.
+pub struct RoadCostMatrix {
+    terrain: LocalRoomTerrain,
+    matrix: LocalCostMatrix,
+}
+
+impl RoadCostMatrix {
+    // the exit tiles are left out, roads don't leave the room
+    pub fn new(terrain: &LocalRoomTerrain) -> Self {
+        let mut matrix = LocalCostMatrix::new();
+        pathfinder::block_exits(&mut matrix);
+        RoadCostMatrix {
+            terrain: terrain.clone(),
+            matrix,
+        }
+    }
+
+    pub fn block(&mut self, pos: (u8, u8)) {
+        self.matrix.set(xy(pos), IMPASSABLE);
+    }
+
+    // existing and already planned roads are shared by the next paths
+    pub fn set_road(&mut self, pos: (u8, u8)) {
+        let open = self.matrix.get(xy(pos)) != IMPASSABLE;
+        if open && self.terrain.get(xy(pos)) != Terrain::Wall {
+            self.matrix.set(xy(pos), ROAD_COST);
+        }
+    }
+}
+
+// roads from `hub` to every target, the tiles of earlier paths are cheap for
+// the later ones so overlapping paths merge. returns each road tile once, with
+// the lowest level of the targets it leads to.
+pub fn plan_roads(
+    matrix: &mut RoadCostMatrix,
+    hub: (u8, u8),
+    targets: &[RoadTarget],
+) -> Vec<((u8, u8), u8)> {
+    let mut roads: Vec<((u8, u8), u8)> = Vec::new();
+    for target in targets {
+        let path = match shortest_path(matrix, hub, target.pos, target.range) {
+            Some(path) => path,
+            None => continue,
+        };
+        for pos in path {
+            matrix.set_road(pos);
+            match roads.iter_mut().find(|(road, _)| *road == pos) {
+                Some((_, rcl)) => *rcl = (*rcl).min(target.rcl),
+                None => roads.push((pos, target.rcl)),
+            }
+        }
+    }
+    roads
+}
+
+// the path leaves out `from` and ends on the first tile within `range` of `to`
+fn shortest_path(
+    matrix: &RoadCostMatrix,
+    from: (u8, u8),
+    to: (u8, u8),
+    range: u32,
+) -> Option<Vec<(u8, u8)>> {
+    let room_name = RoomName::new(PLANNING_ROOM).ok()?;
+    let position = |tile| Position::new(xy(tile).x, xy(tile).y, room_name);
+    let result = pathfinder::search(
+        position(from),
+        position(to),
+        range,
+        &SearchOptions::default(),
+        |room| {
+            (room == room_name).then(|| RoomCosts {
+                terrain: matrix.terrain.clone(),
+                matrix: matrix.matrix.clone(),
+            })
+        },
+    );
+    if result.incomplete {
+        return None;
+    }
+    Some(
+        result
+            .path
+            .into_iter()
+            .map(|pos| (pos.x().u8(), pos.y().u8()))
+            .collect(),
+    )
+}
+
+fn xy(tile: (u8, u8)) -> RoomXY {
+    RoomXY {
+        x: RoomCoordinate::new(tile.0).unwrap(),
+        y: RoomCoordinate::new(tile.1).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terrain(swamps: &[(usize, usize)], walls: &[(usize, usize)]) -> LocalRoomTerrain {
+        let mut bits = Box::new([0u8; 2500]);
+        for (x, y) in swamps {
+            bits[y * 50 + x] = 2;
+        }
+        for (x, y) in walls {
+            bits[y * 50 + x] = 1;
+        }
+        LocalRoomTerrain::new_from_bits(bits)
+    }
+
+    fn range_to(a: (u8, u8), b: (u8, u8)) -> u8 {
+        a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+    }
+
+    fn target(pos: (u8, u8)) -> RoadTarget {
+        RoadTarget { pos, range: 1, rcl: 3 }
+    }
+
+    #[test]
+    fn road_ends_next_to_the_target() {
+        let mut matrix = RoadCostMatrix::new(&terrain(&[], &[]));
+        let roads = plan_roads(&mut matrix, (10, 10), &[target((20, 10))]);
+        assert_eq!(roads.len(), 9);
+        assert_eq!(range_to(roads.last().unwrap().0, (20, 10)), 1);
+    }
+
+    #[test]
+    fn goes_around_swamps_and_walls() {
+        // a swamp band with a gap at the top, a wall where the gap ends
+        let swamps: Vec<(usize, usize)> = (5..45).flat_map(|y| (14..=16).map(move |x| (x, y))).collect();
+        let mut matrix = RoadCostMatrix::new(&terrain(&swamps, &[(14, 4), (15, 4), (16, 4)]));
+        let roads = plan_roads(&mut matrix, (10, 10), &[target((20, 10))]);
+        assert!(roads.iter().all(|(pos, _)| !(14..=16).contains(&pos.0) || pos.1 < 4));
+    }
+
+    #[test]
+    fn paths_to_nearby_targets_share_tiles() {
+        let mut matrix = RoadCostMatrix::new(&terrain(&[], &[]));
+        let roads = plan_roads(&mut matrix, (10, 10), &[target((40, 10)), target((40, 14))]);
+        // the second road branches off the first one
+        assert!(roads.len() < 29 + 29);
+        let mut seen: Vec<(u8, u8)> = roads.iter().map(|(pos, _)| *pos).collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), roads.len());
+    }
+
+    #[test]
+    fn shared_tiles_take_the_lowest_level() {
+        let mut matrix = RoadCostMatrix::new(&terrain(&[], &[]));
+        let mineral = RoadTarget { pos: (40, 10), range: 1, rcl: 6 };
+        let roads = plan_roads(&mut matrix, (10, 10), &[mineral, target((30, 10))]);
+        // the mineral road comes first, its start is shared with the other one
+        assert_eq!(roads[0].1, 3);
+        assert_eq!(roads[28].1, 6);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Write};
+
+use log::debug;
+
+use crate::{overlord::OverlordPriority, world::World};
+
+// weight of the latest tick in the moving averages, roughly the last 20 ticks
+// count
+const AVERAGE_WEIGHT: f64 = 0.1;
+// below this bucket low priority overlords are deferred
+const SAVING_BUCKET: i32 = 3000;
+// below this bucket low priority overlords stop, normal ones are deferred
+const CRITICAL_BUCKET: i32 = 1000;
+// deferred overlords run once in this many ticks
+const DEFER_INTERVAL: u32 = 5;
+// entries not measured for this long are dropped, their colony or overlord is
+// gone
+const STATS_TTL: u32 = 1000;
+// the whole tick, up to `finish_tick`
+const TICK: &str = "tick";
+
+// measurements live on the heap, after a global reset they start over
+thread_local! {
+  static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuStats {
+    // moving average over the ticks it was measured in
+    pub average: f64,
+    pub last: f64,
+    pub max: f64,
+    pub last_tick: u32,
+}
+
+#[derive(Default)]
+struct Profiler {
+    // cpu of this tick so far, a name measured twice adds up
+    current: HashMap<String, f64>,
+    stats: HashMap<String, CpuStats>,
+}
+
+// how much of the tick goes to overlords that can wait, by the bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetMode {
+    Full,
+    Saving,
+    Critical,
+}
+
+impl BudgetMode {
+    pub fn from_bucket(bucket: i32) -> Self {
+        if bucket < CRITICAL_BUCKET {
+            BudgetMode::Critical
+        } else if bucket < SAVING_BUCKET {
+            BudgetMode::Saving
+        } else {
+            BudgetMode::Full
+        }
+    }
+
+    // whether an overlord of `priority` runs this tick
+    pub fn allows(self, priority: OverlordPriority, time: u32) -> bool {
+        let deferred_turn = time.is_multiple_of(DEFER_INTERVAL);
+        match (self, priority) {
+            (BudgetMode::Full, _) | (_, OverlordPriority::Critical) => true,
+            (BudgetMode::Saving, OverlordPriority::Normal) => true,
+            (BudgetMode::Saving, OverlordPriority::Low) => deferred_turn,
+            (BudgetMode::Critical, OverlordPriority::Normal) => deferred_turn,
+            (BudgetMode::Critical, OverlordPriority::Low) => false,
+        }
+    }
+}
+
+pub fn budget(world: &dyn World) -> BudgetMode {
+    BudgetMode::from_bucket(world.cpu_bucket())
+}
+
+// runs `f` and books the cpu it used on `name`. measurements nest, a colony
+// includes the cpu of its overlords.
+pub fn measure<T>(world: &dyn World, name: &str, f: impl FnOnce() -> T) -> T {
+    let start = world.cpu_used();
+    let result = f();
+    let used = world.cpu_used() - start;
+    PROFILER.with(|profiler| {
+        *profiler
+            .borrow_mut()
+            .current
+            .entry(name.to_string())
+            .or_default() += used
+    });
+    result
+}
+
+// folds the measurements of the tick into the averages, called once at the end
+// of every tick
+pub fn finish_tick(world: &dyn World) {
+    let time = world.time();
+    let used = world.cpu_used();
+    debug!("tick {} done, cpu: {:.2}", time, used);
+    PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        profiler.current.insert(TICK.to_string(), used);
+        profiler.finish(time);
+    })
+}
+
+// the averages of everything measured, most expensive first. nested entries
+// are included in their parents, they don't add up to the tick.
+pub fn report(world: &dyn World) -> String {
+    PROFILER.with(|profiler| profiler.borrow().report(world.cpu_bucket()))
+}
+
+impl Profiler {
+    fn finish(&mut self, time: u32) {
+        for (name, used) in self.current.drain() {
+            let stats = self.stats.entry(name).or_insert(CpuStats {
+                average: used,
+                ..CpuStats::default()
+            });
+            stats.average += (used - stats.average) * AVERAGE_WEIGHT;
+            stats.last = used;
+            stats.max = stats.max.max(used);
+            stats.last_tick = time;
+        }
+        self.stats
+            .retain(|_, stats| stats.last_tick + STATS_TTL > time);
+    }
+
+    fn report(&self, bucket: i32) -> String {
+        let mut entries: Vec<(&String, &CpuStats)> = self.stats.iter().collect();
+        entries.sort_by(|a, b| b.1.average.total_cmp(&a.1.average).then_with(|| a.0.cmp(b.0)));
+        let mut report = format!(
+            "cpu bucket {} ({:?})\n{:<40}{:>8}{:>8}{:>8}",
+            bucket,
+            BudgetMode::from_bucket(bucket),
+            "",
+            "avg",
+            "last",
+            "max"
+        );
+        for (name, stats) in entries {
+            let _ = write!(
+                report,
+                "\n{:<40}{:>8.2}{:>8.2}{:>8.2}",
+                name, stats.average, stats.last, stats.max
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::mock::MockWorld;
+
+    fn spend(world: &MockWorld, cpu: f64) {
+        world.state.borrow_mut().cpu_used += cpu;
+    }
+
+    fn stats(name: &str) -> CpuStats {
+        PROFILER.with(|profiler| profiler.borrow().stats[name])
+    }
+
+    #[test]
+    fn nested_measurements_and_moving_averages() {
+        let world = MockWorld::new();
+        world.state.borrow_mut().time = 1;
+        measure(&world, "W1N1", || {
+            spend(&world, 1.0);
+            measure(&world, "mine-W1N1", || spend(&world, 2.0));
+        });
+        measure(&world, "mine-W1N1", || spend(&world, 1.0));
+        finish_tick(&world);
+        assert_eq!(stats("W1N1").average, 3.0);
+        assert_eq!(stats("mine-W1N1").average, 3.0);
+        assert_eq!(stats(TICK).average, 4.0);
+
+        world.state.borrow_mut().time = 2;
+        world.state.borrow_mut().cpu_used = 0.0;
+        measure(&world, "W1N1", || spend(&world, 13.0));
+        finish_tick(&world);
+        let colony = stats("W1N1");
+        assert!((colony.average - 4.0).abs() < 1e-9);
+        assert_eq!((colony.last, colony.max), (13.0, 13.0));
+
+        let report = report(&world);
+        assert!(report.starts_with("cpu bucket 10000 (Full)"));
+        // most expensive first
+        let lines: Vec<&str> = report.lines().skip(2).collect();
+        assert!(lines[0].starts_with("tick"));
+        assert!(lines[1].starts_with("W1N1"));
+    }
+
+    #[test]
+    fn stale_entries_are_dropped() {
+        let world = MockWorld::new();
+        world.state.borrow_mut().time = 1;
+        measure(&world, "gone", || spend(&world, 1.0));
+        finish_tick(&world);
+        world.state.borrow_mut().time += STATS_TTL;
+        finish_tick(&world);
+        assert!(!report(&world).contains("gone"));
+    }
+
+    #[test]
+    fn low_bucket_defers_and_skips_overlords() {
+        let saving = BudgetMode::from_bucket(SAVING_BUCKET - 1);
+        assert_eq!(saving, BudgetMode::Saving);
+        assert!(saving.allows(OverlordPriority::Normal, 1));
+        assert!(!saving.allows(OverlordPriority::Low, 1));
+        assert!(saving.allows(OverlordPriority::Low, DEFER_INTERVAL));
+
+        let critical = BudgetMode::from_bucket(0);
+        assert_eq!(critical, BudgetMode::Critical);
+        assert!(critical.allows(OverlordPriority::Critical, 1));
+        assert!(!critical.allows(OverlordPriority::Normal, 1));
+        assert!(critical.allows(OverlordPriority::Normal, DEFER_INTERVAL));
+        assert!(!critical.allows(OverlordPriority::Low, DEFER_INTERVAL));
+
+        assert!(BudgetMode::from_bucket(SAVING_BUCKET).allows(OverlordPriority::Low, 1));
+    }
+}
@@ -0,0 +1,82 @@
+use screeps::StructureType;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SwarmError;
+
+// room description loaded by the simulator, e.g.
+// {
+//   "room": "W1N1",
+//   "terrain": ["##########...", ...],
+//   "controller": [10, 10],
+//   "sources": [[5, 40], [40, 8]],
+//   "mineral": [45, 45],
+//   "spawn": [25, 25],
+//   "construction_sites": [{ "structure_type": "extension", "pos": [24, 23] }]
+// }
+// terrain is optional, 50 rows of 50 characters: '#' wall, '~' swamp, anything else plain.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomLayout {
+    pub room: String,
+    #[serde(default)]
+    pub terrain: Vec<String>,
+    pub controller: (u8, u8),
+    pub sources: Vec<(u8, u8)>,
+    #[serde(default)]
+    pub mineral: Option<(u8, u8)>,
+    pub spawn: (u8, u8),
+    #[serde(default)]
+    pub construction_sites: Vec<LayoutSite>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LayoutSite {
+    pub structure_type: StructureType,
+    pub pos: (u8, u8),
+}
+
+impl RoomLayout {
+    pub fn from_json(json: &str) -> Result<Self, SwarmError> {
+        let layout: RoomLayout = serde_json::from_str(json).map_err(|e| {
+            SwarmError::InternalAssertionFailed(format!("parse room layout failed. {}", e))
+        })?;
+
+        if !layout.terrain.is_empty()
+            && (layout.terrain.len() != 50 || layout.terrain.iter().any(|row| row.len() != 50))
+        {
+            return Err(SwarmError::InternalAssertionFailed(
+                "terrain must be 50 rows of 50 characters".to_string(),
+            ));
+        }
+        let fixed = [layout.controller, layout.spawn];
+        let positions = layout
+            .sources
+            .iter()
+            .chain(fixed.iter())
+            .chain(layout.mineral.iter())
+            .chain(layout.construction_sites.iter().map(|site| &site.pos));
+        for (x, y) in positions {
+            if *x > 49 || *y > 49 {
+                return Err(SwarmError::InternalAssertionFailed(format!(
+                    "position ({}, {}) out of room",
+                    x, y
+                )));
+            }
+        }
+        Ok(layout)
+    }
+
+    // terrain bytes in the game's encoding, row-major
+    pub fn terrain_bits(&self) -> Box<[u8; 2500]> {
+        let mut bits = Box::new([0u8; 2500]);
+        for (y, row) in self.terrain.iter().enumerate() {
+            for (x, tile) in row.chars().enumerate() {
+                bits[y * 50 + x] = match tile {
+                    '#' => 1,
+                    '~' => 2,
+                    _ => 0,
+                };
+            }
+        }
+        bits
+    }
+}
@@ -0,0 +1,152 @@
+use std::rc::Rc;
+
+use log::info;
+
+use crate::{error::SwarmError, memory::COLONIES, world::World};
+
+pub mod layout;
+mod sim_world;
+
+pub use layout::{LayoutSite, RoomLayout};
+pub use sim_world::{SimStats, SimWorld};
+
+// headless runs of the whole swarm against `SimWorld`, used by the `simulator`
+// binary and by regression tests. runs are deterministic, the same layout and
+// code always produce the same report. anything the swarm or `SimWorld`
+// iterates over is ordered, std hash maps are seeded per process.
+#[derive(Debug, Clone)]
+pub struct SimulationOptions {
+    pub max_ticks: u32,
+    // stop as soon as the controller reaches this level
+    pub until_rcl: Option<u8>,
+    // log a progress line every n ticks, 0 to disable
+    pub report_every: u32,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        SimulationOptions {
+            max_ticks: 10_000,
+            until_rcl: None,
+            report_every: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub ticks: u32,
+    pub rcl: u8,
+    pub progress: u32,
+    pub progress_total: u32,
+    pub creeps: usize,
+    pub stats: SimStats,
+}
+
+impl SimulationReport {
+    // tick the controller reached `rcl`, none if it never did
+    pub fn ticks_to_rcl(&self, rcl: u8) -> Option<u32> {
+        if rcl <= 1 {
+            return Some(0);
+        }
+        self.stats.rcl_reached.get(&rcl).copied()
+    }
+}
+
+pub fn run_simulation(
+    layout: &RoomLayout,
+    options: &SimulationOptions,
+) -> Result<SimulationReport, SwarmError> {
+    let sim_world = Rc::new(SimWorld::new(layout)?);
+    let world: Rc<dyn World> = sim_world.clone();
+    let room_name = world.spawn_rooms()[0];
+
+    // colonies of an earlier run in this thread must not leak into this one
+    COLONIES.with(|colonies| colonies.borrow_mut().clear());
+
+    let report = |ticks: u32| {
+        let controller = world.controller(room_name);
+        SimulationReport {
+            ticks,
+            rcl: controller.as_ref().map(|c| c.level).unwrap_or(0),
+            progress: controller.as_ref().map(|c| c.progress).unwrap_or(0),
+            progress_total: controller.as_ref().map(|c| c.progress_total).unwrap_or(0),
+            creeps: sim_world.creep_count(),
+            stats: sim_world.stats(),
+        }
+    };
+
+    for tick in 0..options.max_ticks {
+        crate::run_tick(&world);
+        sim_world.tick();
+
+        let current = report(tick + 1);
+        if options.report_every > 0 && (tick + 1) % options.report_every == 0 {
+            info!(
+                "tick {}: rcl {} ({}/{}), creeps {}, harvested {}",
+                current.ticks,
+                current.rcl,
+                current.progress,
+                current.progress_total,
+                current.creeps,
+                current.stats.energy_harvested
+            );
+        }
+        if options.until_rcl.is_some_and(|rcl| current.rcl >= rcl) {
+            COLONIES.with(|colonies| colonies.borrow_mut().clear());
+            return Ok(current);
+        }
+    }
+
+    COLONIES.with(|colonies| colonies.borrow_mut().clear());
+    Ok(report(options.max_ticks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_room() -> RoomLayout {
+        RoomLayout::from_json(
+            r#"{
+                "room": "W1N1",
+                "controller": [25, 10],
+                "sources": [[10, 25], [40, 25]],
+                "spawn": [25, 25]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn colony_reaches_rcl_2() {
+        let report = run_simulation(
+            &open_room(),
+            &SimulationOptions {
+                max_ticks: 3000,
+                until_rcl: Some(2),
+                report_every: 0,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(report.rcl, 2);
+        assert!(report.stats.creeps_spawned >= 2);
+        assert!(report.ticks_to_rcl(2).is_some());
+    }
+
+    #[test]
+    fn simulation_is_deterministic() {
+        let options = SimulationOptions {
+            max_ticks: 2000,
+            ..Default::default()
+        };
+        // threads seed their hash maps apart and have heaps of their own
+        let run = || {
+            let options = options.clone();
+            std::thread::spawn(move || run_simulation(&open_room(), &options).unwrap())
+        };
+        let (first, second) = (run(), run());
+        assert_eq!(first.join().unwrap(), second.join().unwrap());
+    }
+}
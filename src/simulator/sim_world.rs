@@ -0,0 +1,1273 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+};
+
+use screeps::{
+    constants::{
+        controller_downgrade, controller_levels, extension_energy_capacity, rampart_hits_max,
+        BUILD_POWER, CARRY_CAPACITY, CONTAINER_DECAY, CONTAINER_DECAY_TIME_OWNED, CPU_BUCKET_MAX,
+        CREEP_LIFE_TIME, CREEP_SPAWN_TIME, ENERGY_REGEN_TIME, HARVEST_POWER, HEAL_POWER,
+        LINK_COOLDOWN, LINK_LOSS_RATIO, RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME, REPAIR_COST,
+        REPAIR_POWER, ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME, SOURCE_ENERGY_CAPACITY,
+        SPAWN_ENERGY_CAPACITY, TOWER_ENERGY_COST, TOWER_POWER_HEAL, TOWER_POWER_REPAIR,
+        UPGRADE_CONTROLLER_POWER, WALL_HITS_MAX,
+    },
+    Direction, ErrorCode, LocalRoomTerrain, Part, Position, RawObjectId, ResourceType,
+    RoomCoordinate, RoomName, RoomXY, StructureType, Terrain,
+};
+
+use crate::{
+    error::SwarmError,
+    hive::tower_power,
+    world::{
+        ConstructionSiteInfo, ControllerInfo, CreepInfo, DroppedResourceInfo, HostileCreepInfo,
+        MineralInfo, RoomInfo, SourceInfo, SpawnInfo, StoreInfo, StructureInfo, World,
+    },
+    zerg::CreepMemory,
+};
+
+use super::layout::RoomLayout;
+
+// a single room that advances on its own. actions change the state right away,
+// except for moves. `tick` then runs everything the game does between two
+// ticks: moves, spawning, source regeneration, fatigue, aging and controller
+// downgrade.
+//
+// what is left out on purpose: structures decay all on the same tick, there
+// are no hostiles and only energy is modelled.
+pub struct SimWorld {
+    state: RefCell<SimState>,
+}
+
+struct SimState {
+    time: u32,
+    room_name: RoomName,
+    terrain: LocalRoomTerrain,
+    controller: ControllerInfo,
+    sources: Vec<SimSource>,
+    minerals: Vec<MineralInfo>,
+    spawns: Vec<SimSpawn>,
+    structures: Vec<StructureInfo>,
+    construction_sites: Vec<ConstructionSiteInfo>,
+    dropped_resources: Vec<DroppedResourceInfo>,
+    // link id -> tick the link can send again
+    link_cooldowns: BTreeMap<RawObjectId, u32>,
+    // layout sites the controller level doesn't allow yet
+    pending_sites: Vec<(StructureType, Position)>,
+    creeps: BTreeMap<String, CreepInfo>,
+    // the step each creep asked for this tick, taken in `tick`
+    moves: BTreeMap<String, RoomXY>,
+    // creeps that already used their work action this tick
+    worked: HashSet<String>,
+    snapshot: Option<String>,
+    next_id: u128,
+    stats: SimStats,
+}
+
+struct SimSource {
+    info: SourceInfo,
+    // tick the source refills, counted from the first harvest after a refill
+    regen_at: Option<u32>,
+}
+
+struct SimSpawn {
+    info: SpawnInfo,
+    // (creep name, ticks left)
+    spawning: Option<(String, u32)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimStats {
+    pub energy_harvested: u32,
+    pub energy_spent_on_spawning: u32,
+    pub energy_spent_on_building: u32,
+    pub energy_spent_on_upgrading: u32,
+    pub energy_spent_on_repairing: u32,
+    pub creeps_spawned: u32,
+    pub creeps_died: u32,
+    pub structures_built: u32,
+    // rcl -> tick the controller reached it
+    pub rcl_reached: BTreeMap<u8, u32>,
+}
+
+impl SimWorld {
+    pub fn new(layout: &RoomLayout) -> Result<Self, SwarmError> {
+        let room_name = RoomName::new(&layout.room).map_err(|e| {
+            SwarmError::InternalAssertionFailed(format!("invalid room name. {:?}", e))
+        })?;
+        let pos = |(x, y): (u8, u8)| {
+            Position::new(
+                RoomCoordinate::new(x).unwrap(),
+                RoomCoordinate::new(y).unwrap(),
+                room_name,
+            )
+        };
+
+        let mut next_id = 0;
+        let mut new_id = || {
+            next_id += 1;
+            RawObjectId::from(next_id)
+        };
+
+        let controller = ControllerInfo {
+            id: new_id(),
+            pos: pos(layout.controller),
+            my: true,
+            level: 1,
+            progress: 0,
+            progress_total: controller_levels(1).unwrap_or(0),
+            ticks_to_downgrade: controller_downgrade(1).unwrap_or(0),
+            safe_mode: 0,
+            safe_mode_available: 0,
+            safe_mode_cooldown: 0,
+        };
+        let sources = layout
+            .sources
+            .iter()
+            .map(|source| SimSource {
+                info: SourceInfo {
+                    id: new_id(),
+                    pos: pos(*source),
+                    energy: SOURCE_ENERGY_CAPACITY,
+                    energy_capacity: SOURCE_ENERGY_CAPACITY,
+                },
+                regen_at: None,
+            })
+            .collect();
+        let minerals = layout
+            .mineral
+            .iter()
+            .map(|mineral| MineralInfo {
+                id: new_id(),
+                pos: pos(*mineral),
+            })
+            .collect();
+        let spawn_id = new_id();
+        let spawns = vec![SimSpawn {
+            info: SpawnInfo {
+                id: spawn_id,
+                name: "Spawn1".to_string(),
+                pos: pos(layout.spawn),
+                spawning: false,
+            },
+            spawning: None,
+        }];
+        let structures = vec![new_structure(
+            spawn_id,
+            StructureType::Spawn,
+            pos(layout.spawn),
+            1,
+            SPAWN_ENERGY_CAPACITY,
+        )];
+        let pending_sites = layout
+            .construction_sites
+            .iter()
+            .map(|site| (site.structure_type, pos(site.pos)))
+            .collect();
+
+        let sim_world = SimWorld {
+            state: RefCell::new(SimState {
+                time: 0,
+                room_name,
+                terrain: LocalRoomTerrain::new_from_bits(layout.terrain_bits()),
+                controller,
+                sources,
+                minerals,
+                spawns,
+                structures,
+                construction_sites: Vec::new(),
+                dropped_resources: Vec::new(),
+                link_cooldowns: BTreeMap::new(),
+                pending_sites,
+                creeps: BTreeMap::new(),
+                moves: BTreeMap::new(),
+                worked: HashSet::new(),
+                snapshot: None,
+                next_id,
+                stats: SimStats::default(),
+            }),
+        };
+        sim_world.state.borrow_mut().place_pending_sites();
+        Ok(sim_world)
+    }
+
+    pub fn stats(&self) -> SimStats {
+        self.state.borrow().stats.clone()
+    }
+
+    pub fn creep_count(&self) -> usize {
+        self.state.borrow().creeps.len()
+    }
+
+    // everything that happens between the end of one tick and the start of the next
+    pub fn tick(&self) {
+        let mut state = self.state.borrow_mut();
+        state.time += 1;
+        let time = state.time;
+        state.worked.clear();
+        state.resolve_moves();
+
+        // spawning
+        let mut finished = Vec::new();
+        for spawn in state.spawns.iter_mut() {
+            if let Some((name, ticks)) = spawn.spawning.as_mut() {
+                *ticks -= 1;
+                if *ticks == 0 {
+                    finished.push((name.clone(), spawn.info.pos));
+                    spawn.spawning = None;
+                    spawn.info.spawning = false;
+                }
+            }
+        }
+        for (name, spawn_pos) in finished {
+            let exit = state.free_neighbour(spawn_pos);
+            if let Some(creep) = state.creeps.get_mut(&name) {
+                creep.spawning = false;
+                creep.ticks_to_live = Some(CREEP_LIFE_TIME);
+                creep.pos = exit;
+            }
+        }
+
+        // spawns refill themselves while the room is short of energy
+        if state.energy_available() < SPAWN_ENERGY_CAPACITY {
+            for structure in state.structures.iter_mut() {
+                if structure.structure_type == StructureType::Spawn {
+                    if let Some(store) = structure.store.as_mut() {
+                        if store.energy < store.capacity {
+                            store.energy += 1;
+                            store.used += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        for source in state.sources.iter_mut() {
+            if source.regen_at == Some(time) {
+                source.info.energy = source.info.energy_capacity;
+                source.regen_at = None;
+            }
+        }
+
+        // dropped piles lose a thousandth of their amount a tick, rounded up
+        for resource in state.dropped_resources.iter_mut() {
+            resource.amount -= resource.amount.div_ceil(1000).min(resource.amount);
+        }
+        state.dropped_resources.retain(|resource| resource.amount > 0);
+
+        for structure in state.structures.iter_mut() {
+            let (amount, every) = match structure.structure_type {
+                StructureType::Road => (ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME),
+                StructureType::Container => (CONTAINER_DECAY, CONTAINER_DECAY_TIME_OWNED),
+                StructureType::Rampart => (RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME),
+                _ => continue,
+            };
+            if time.is_multiple_of(every) {
+                structure.hits = structure.hits.saturating_sub(amount);
+            }
+        }
+        state.structures.retain(|structure| structure.hits > 0);
+
+        // fatigue and aging
+        let mut dead = Vec::new();
+        for creep in state.creeps.values_mut() {
+            if creep.spawning {
+                continue;
+            }
+            let moves = creep.count_parts(Part::Move) * 2;
+            creep.fatigue = creep.fatigue.saturating_sub(moves);
+            let ticks_to_live = creep.ticks_to_live.unwrap_or(0).saturating_sub(1);
+            creep.ticks_to_live = Some(ticks_to_live);
+            if ticks_to_live == 0 {
+                dead.push(creep.name.clone());
+            }
+        }
+        for name in dead {
+            state.creeps.remove(&name);
+            state.stats.creeps_died += 1;
+        }
+
+        let controller = &mut state.controller;
+        controller.ticks_to_downgrade = controller.ticks_to_downgrade.saturating_sub(1);
+        if controller.ticks_to_downgrade == 0 && controller.level > 1 {
+            controller.level -= 1;
+            controller.progress = 0;
+            controller.progress_total = controller_levels(controller.level as u32).unwrap_or(0);
+            controller.ticks_to_downgrade =
+                controller_downgrade(controller.level).unwrap_or(0) / 2;
+        }
+
+        state.place_pending_sites();
+    }
+}
+
+fn new_structure(
+    id: RawObjectId,
+    structure_type: StructureType,
+    pos: Position,
+    rcl: u8,
+    capacity: u32,
+) -> StructureInfo {
+    let hits = structure_type.initial_hits().unwrap_or(1);
+    let hits_max = match structure_type {
+        StructureType::Rampart => rampart_hits_max(rcl as u32),
+        StructureType::Wall => WALL_HITS_MAX,
+        _ => hits,
+    };
+    let store = match structure_type {
+        StructureType::Spawn => Some(SPAWN_ENERGY_CAPACITY),
+        StructureType::Extension => Some(extension_energy_capacity(rcl as u32)),
+        StructureType::Tower => Some(screeps::constants::TOWER_CAPACITY),
+        StructureType::Container => Some(screeps::constants::CONTAINER_CAPACITY),
+        StructureType::Storage => Some(screeps::constants::STORAGE_CAPACITY),
+        StructureType::Link => Some(screeps::constants::LINK_CAPACITY),
+        _ => None,
+    }
+    .map(|store_capacity| StoreInfo {
+        energy: capacity.min(store_capacity),
+        used: capacity.min(store_capacity),
+        capacity: store_capacity,
+    });
+    StructureInfo {
+        id,
+        structure_type,
+        pos,
+        hits,
+        hits_max,
+        my: structure_type != StructureType::Road
+            && structure_type != StructureType::Container
+            && structure_type != StructureType::Wall,
+        store,
+    }
+}
+
+fn walkable_structure(structure_type: StructureType) -> bool {
+    matches!(
+        structure_type,
+        StructureType::Road | StructureType::Container | StructureType::Rampart
+    )
+}
+
+impl SimState {
+    fn new_id(&mut self) -> RawObjectId {
+        self.next_id += 1;
+        RawObjectId::from(self.next_id)
+    }
+
+    // layout sites are placed in order as soon as the controller level allows
+    // them, the way a player would place them by hand.
+    fn place_pending_sites(&mut self) {
+        let rcl = self.controller.level as u32;
+        let pending = std::mem::take(&mut self.pending_sites);
+        for (structure_type, pos) in pending {
+            let placed = self
+                .structures
+                .iter()
+                .map(|s| s.structure_type)
+                .chain(self.construction_sites.iter().map(|s| s.structure_type))
+                .filter(|placed_type| *placed_type == structure_type)
+                .count() as u32;
+            if placed >= structure_type.controller_structures(rcl) {
+                self.pending_sites.push((structure_type, pos));
+                continue;
+            }
+            let id = self.new_id();
+            self.construction_sites.push(ConstructionSiteInfo {
+                id,
+                structure_type,
+                pos,
+                progress: 0,
+                progress_total: structure_type.construction_cost().unwrap_or(1),
+            });
+        }
+    }
+
+    fn energy_available(&self) -> u32 {
+        self.energy_stores().map(|store| store.energy).sum()
+    }
+
+    fn energy_capacity_available(&self) -> u32 {
+        self.energy_stores().map(|store| store.capacity).sum()
+    }
+
+    // stores the room spends energy from when spawning
+    fn energy_stores(&self) -> impl Iterator<Item = &StoreInfo> {
+        self.structures
+            .iter()
+            .filter(|s| {
+                s.structure_type == StructureType::Spawn
+                    || s.structure_type == StructureType::Extension
+            })
+            .filter_map(|s| s.store.as_ref())
+    }
+
+    fn walkable(&self, xy: RoomXY) -> bool {
+        if self.terrain.get(xy) == Terrain::Wall {
+            return false;
+        }
+        !self.structures.iter().any(|s| {
+            s.pos.xy() == xy && !walkable_structure(s.structure_type)
+        }) && !self.sources.iter().any(|s| s.info.pos.xy() == xy)
+            && !self.minerals.iter().any(|m| m.pos.xy() == xy)
+            && self.controller.pos.xy() != xy
+    }
+
+    // the tile a freshly spawned creep steps out to
+    fn free_neighbour(&self, pos: Position) -> Position {
+        neighbours(pos.xy())
+            .find(|xy| {
+                self.walkable(*xy) && !self.creeps.values().any(|c| c.pos.xy() == *xy)
+            })
+            .map(|xy| Position::new(xy.x, xy.y, self.room_name))
+            .unwrap_or(pos)
+    }
+
+    fn find_pos(&self, id: RawObjectId) -> Option<Position> {
+        if self.controller.id == id {
+            return Some(self.controller.pos);
+        }
+        self.sources
+            .iter()
+            .filter(|s| s.info.id == id)
+            .map(|s| s.info.pos)
+            .chain(self.structures.iter().filter(|s| s.id == id).map(|s| s.pos))
+            .chain(
+                self.construction_sites
+                    .iter()
+                    .filter(|s| s.id == id)
+                    .map(|s| s.pos),
+            )
+            .chain(
+                self.dropped_resources
+                    .iter()
+                    .filter(|r| r.id == id)
+                    .map(|r| r.pos),
+            )
+            .next()
+    }
+
+    // energy on the ground joins the pile already lying on the tile
+    fn drop_energy(&mut self, pos: Position, amount: u32) {
+        if amount == 0 {
+            return;
+        }
+        if let Some(resource) = self.dropped_resources.iter_mut().find(|r| r.pos == pos) {
+            resource.amount += amount;
+            return;
+        }
+        let id = self.new_id();
+        self.dropped_resources.push(DroppedResourceInfo {
+            id,
+            pos,
+            resource_type: ResourceType::Energy,
+            amount,
+        });
+    }
+
+    // the checks every work action shares, returns the acting creep
+    fn check_action(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        range: u32,
+    ) -> Result<CreepInfo, ErrorCode> {
+        let creep_info = self.creeps.get(creep).ok_or(ErrorCode::NotFound)?;
+        if creep_info.spawning {
+            return Err(ErrorCode::Busy);
+        }
+        let target_pos = self.find_pos(target).ok_or(ErrorCode::InvalidTarget)?;
+        if creep_info.pos.get_range_to(target_pos) > range {
+            return Err(ErrorCode::NotInRange);
+        }
+        Ok(creep_info.clone())
+    }
+
+    fn change_creep_energy(&mut self, creep: &str, energy: i64) {
+        if let Some(creep) = self.creeps.get_mut(creep) {
+            creep.store.energy = (creep.store.energy as i64 + energy) as u32;
+            creep.store.used = creep.store.energy;
+        }
+    }
+
+    // the checks every move shares, returns the moving creep
+    fn check_move(&self, creep: &str) -> Result<&CreepInfo, ErrorCode> {
+        let creep_info = self.creeps.get(creep).ok_or(ErrorCode::NotFound)?;
+        if creep_info.spawning {
+            return Err(ErrorCode::Busy);
+        }
+        if creep_info.fatigue > 0 {
+            return Err(ErrorCode::Tired);
+        }
+        if creep_info.count_parts(Part::Move) == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        Ok(creep_info)
+    }
+
+    // takes the steps asked for like the game does: a creep can't step onto a
+    // tile another creep stays on, creeps heading for each other's tile swap
+    // and of the creeps heading for the same tile the first by name gets it
+    fn resolve_moves(&mut self) {
+        let mut moves = std::mem::take(&mut self.moves);
+        moves.retain(|_, step| self.walkable(*step));
+        loop {
+            let kept: HashSet<RoomXY> = self
+                .creeps
+                .values()
+                .filter(|c| !c.spawning && !moves.contains_key(&c.name))
+                .map(|c| c.pos.xy())
+                .collect();
+            let mut taken = HashSet::new();
+            let before = moves.len();
+            moves.retain(|_, step| !kept.contains(step) && taken.insert(*step));
+            if moves.len() == before {
+                break;
+            }
+        }
+
+        let room_name = self.room_name;
+        for (name, step) in moves {
+            let fatigue = match self.creeps.get(&name) {
+                Some(creep) => self.fatigue(creep, step),
+                None => continue,
+            };
+            if let Some(creep) = self.creeps.get_mut(&name) {
+                creep.pos = Position::new(step.x, step.y, room_name);
+                creep.fatigue = fatigue;
+            }
+        }
+    }
+
+    // every part but MOVE and empty CARRY weighs on the creep, roads halve plain terrain
+    fn fatigue(&self, creep: &CreepInfo, step: RoomXY) -> u32 {
+        let loaded_carry = creep.store.used.div_ceil(CARRY_CAPACITY);
+        let weight = creep
+            .body
+            .iter()
+            .filter(|p| **p != Part::Move && **p != Part::Carry)
+            .count() as u32
+            + loaded_carry.min(creep.count_parts(Part::Carry));
+        let on_road = self
+            .structures
+            .iter()
+            .any(|s| s.pos.xy() == step && s.structure_type == StructureType::Road);
+        let factor = if on_road {
+            1
+        } else {
+            match self.terrain.get(step) {
+                Terrain::Swamp => 10,
+                _ => 2,
+            }
+        };
+        weight * factor
+    }
+}
+
+fn neighbours(xy: RoomXY) -> impl Iterator<Item = RoomXY> {
+    [
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+    ]
+    .into_iter()
+    .filter_map(move |offset| xy.checked_add(offset))
+}
+
+impl World for SimWorld {
+    fn time(&self) -> u32 {
+        self.state.borrow().time
+    }
+
+    fn cpu_used(&self) -> f64 {
+        0.0
+    }
+
+    // the simulator has cpu to spare
+    fn cpu_bucket(&self) -> i32 {
+        CPU_BUCKET_MAX as i32
+    }
+
+    fn spawn_rooms(&self) -> Vec<RoomName> {
+        vec![self.state.borrow().room_name]
+    }
+
+    fn room(&self, room_name: RoomName) -> Option<RoomInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return None;
+        }
+        Some(RoomInfo {
+            name: room_name,
+            energy_available: state.energy_available(),
+            energy_capacity_available: state.energy_capacity_available(),
+        })
+    }
+
+    fn terrain(&self, room_name: RoomName) -> Option<LocalRoomTerrain> {
+        let state = self.state.borrow();
+        (state.room_name == room_name).then(|| state.terrain.clone())
+    }
+
+    fn controller(&self, room_name: RoomName) -> Option<ControllerInfo> {
+        let state = self.state.borrow();
+        (state.room_name == room_name).then(|| state.controller.clone())
+    }
+
+    fn sources(&self, room_name: RoomName) -> Vec<SourceInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.sources.iter().map(|s| s.info.clone()).collect()
+    }
+
+    fn minerals(&self, room_name: RoomName) -> Vec<MineralInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.minerals.clone()
+    }
+
+    fn spawns(&self, room_name: RoomName) -> Vec<SpawnInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.spawns.iter().map(|s| s.info.clone()).collect()
+    }
+
+    fn structures(&self, room_name: RoomName) -> Vec<StructureInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.structures.clone()
+    }
+
+    fn construction_sites(&self, room_name: RoomName) -> Vec<ConstructionSiteInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.construction_sites.clone()
+    }
+
+    fn dropped_resources(&self, room_name: RoomName) -> Vec<DroppedResourceInfo> {
+        let state = self.state.borrow();
+        if state.room_name != room_name {
+            return Vec::new();
+        }
+        state.dropped_resources.clone()
+    }
+
+    fn creep(&self, name: &str) -> Option<CreepInfo> {
+        self.state.borrow().creeps.get(name).cloned()
+    }
+
+    fn creeps(&self) -> Vec<CreepInfo> {
+        let mut creeps: Vec<CreepInfo> = self.state.borrow().creeps.values().cloned().collect();
+        creeps.sort_by(|a, b| a.name.cmp(&b.name));
+        creeps
+    }
+
+    // the simulated room has no other players
+    fn hostile_creeps(&self, _room_name: RoomName) -> Vec<HostileCreepInfo> {
+        Vec::new()
+    }
+
+    fn set_creep_memory(&self, name: &str, memory: &CreepMemory) {
+        if let Some(creep) = self.state.borrow_mut().creeps.get_mut(name) {
+            creep.memory = Some(memory.clone());
+        }
+    }
+
+    fn spawn_creep(
+        &self,
+        spawn: RawObjectId,
+        body: &[Part],
+        name: &str,
+        memory: &CreepMemory,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let spawn_pos = {
+            let spawn_info = state
+                .spawns
+                .iter()
+                .find(|s| s.info.id == spawn)
+                .ok_or(ErrorCode::InvalidTarget)?;
+            if spawn_info.spawning.is_some() {
+                return Err(ErrorCode::Busy);
+            }
+            spawn_info.info.pos
+        };
+        if body.is_empty() || body.len() > 50 {
+            return Err(ErrorCode::InvalidArgs);
+        }
+        if state.creeps.contains_key(name) {
+            return Err(ErrorCode::NameExists);
+        }
+        let cost: u32 = body.iter().map(|part| part.cost()).sum();
+        if state.energy_available() < cost {
+            return Err(ErrorCode::NotEnough);
+        }
+
+        // like the game, spawns are drained before extensions
+        let mut remaining = cost;
+        state.structures.sort_by_key(|s| s.structure_type != StructureType::Spawn);
+        for structure in state.structures.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+            if structure.structure_type != StructureType::Spawn
+                && structure.structure_type != StructureType::Extension
+            {
+                continue;
+            }
+            if let Some(store) = structure.store.as_mut() {
+                let taken = store.energy.min(remaining);
+                store.energy -= taken;
+                store.used -= taken;
+                remaining -= taken;
+            }
+        }
+
+        for sim_spawn in state.spawns.iter_mut() {
+            if sim_spawn.info.id == spawn {
+                sim_spawn.spawning = Some((name.to_string(), body.len() as u32 * CREEP_SPAWN_TIME));
+                sim_spawn.info.spawning = true;
+            }
+        }
+        let carry = body.iter().filter(|part| **part == Part::Carry).count() as u32;
+        state.creeps.insert(
+            name.to_string(),
+            CreepInfo {
+                name: name.to_string(),
+                pos: spawn_pos,
+                body: body.to_vec(),
+                store: StoreInfo {
+                    energy: 0,
+                    used: 0,
+                    capacity: carry * CARRY_CAPACITY,
+                },
+                spawning: true,
+                ticks_to_live: None,
+                hits: body.len() as u32 * 100,
+                hits_max: body.len() as u32 * 100,
+                fatigue: 0,
+                memory: Some(memory.clone()),
+            },
+        );
+        state.stats.energy_spent_on_spawning += cost;
+        state.stats.creeps_spawned += 1;
+        Ok(())
+    }
+
+    fn harvest(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 1)?;
+        if state.worked.contains(creep) {
+            return Err(ErrorCode::Busy);
+        }
+        let time = state.time;
+        let source = state
+            .sources
+            .iter_mut()
+            .find(|s| s.info.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let work = creep_info.count_parts(Part::Work);
+        if work == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        if source.info.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if source.regen_at.is_none() {
+            source.regen_at = Some(time + ENERGY_REGEN_TIME);
+        }
+        let amount = (work * HARVEST_POWER).min(source.info.energy);
+        source.info.energy -= amount;
+        let stored = amount.min(creep_info.store.free());
+
+        // what doesn't fit into the creep drops, into a container below it if
+        // there is one, the rest onto the ground.
+        let mut dropped = amount - stored;
+        if let Some(store) = state
+            .structures
+            .iter_mut()
+            .filter(|s| s.structure_type == StructureType::Container && s.pos == creep_info.pos)
+            .find_map(|s| s.store.as_mut())
+        {
+            let kept = dropped.min(store.free());
+            store.energy += kept;
+            store.used += kept;
+            dropped -= kept;
+        }
+        state.drop_energy(creep_info.pos, dropped);
+        state.change_creep_energy(creep, stored as i64);
+        state.stats.energy_harvested += amount;
+        state.worked.insert(creep.to_string());
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 1)?;
+        if resource != ResourceType::Energy {
+            return Err(ErrorCode::NotEnough);
+        }
+        let structure = state
+            .structures
+            .iter_mut()
+            .find(|s| s.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let store = structure.store.as_mut().ok_or(ErrorCode::InvalidTarget)?;
+        let wanted = amount.unwrap_or(creep_info.store.energy);
+        if wanted > creep_info.store.energy || wanted == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if store.free() == 0 {
+            return Err(ErrorCode::Full);
+        }
+        let moved = wanted.min(store.free());
+        store.energy += moved;
+        store.used += moved;
+
+        state.change_creep_energy(creep, -(moved as i64));
+        Ok(())
+    }
+
+    fn transfer_to_creep(
+        &self,
+        creep: &str,
+        target: &str,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.creeps.get(creep).ok_or(ErrorCode::NotFound)?.clone();
+        if creep_info.spawning {
+            return Err(ErrorCode::Busy);
+        }
+        let target_info = state.creeps.get(target).ok_or(ErrorCode::InvalidTarget)?;
+        if creep_info.pos.get_range_to(target_info.pos) > 1 {
+            return Err(ErrorCode::NotInRange);
+        }
+        if resource != ResourceType::Energy {
+            return Err(ErrorCode::NotEnough);
+        }
+        let wanted = amount.unwrap_or(creep_info.store.energy);
+        if wanted > creep_info.store.energy || wanted == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        let free = target_info.store.free();
+        if free == 0 {
+            return Err(ErrorCode::Full);
+        }
+        let moved = wanted.min(free);
+        state.change_creep_energy(creep, -(moved as i64));
+        state.change_creep_energy(target, moved as i64);
+        Ok(())
+    }
+
+    fn withdraw(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 1)?;
+        if resource != ResourceType::Energy {
+            return Err(ErrorCode::NotEnough);
+        }
+        let structure = state
+            .structures
+            .iter_mut()
+            .find(|s| s.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let store = structure.store.as_mut().ok_or(ErrorCode::InvalidTarget)?;
+        if store.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if creep_info.store.free() == 0 {
+            return Err(ErrorCode::Full);
+        }
+        let moved = amount
+            .unwrap_or(u32::MAX)
+            .min(store.energy)
+            .min(creep_info.store.free());
+        store.energy -= moved;
+        store.used -= moved;
+
+        state.change_creep_energy(creep, moved as i64);
+        Ok(())
+    }
+
+    fn pickup(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 1)?;
+        if creep_info.store.free() == 0 {
+            return Err(ErrorCode::Full);
+        }
+        let index = state
+            .dropped_resources
+            .iter()
+            .position(|r| r.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let resource = &mut state.dropped_resources[index];
+        let moved = resource.amount.min(creep_info.store.free());
+        resource.amount -= moved;
+        if resource.amount == 0 {
+            state.dropped_resources.remove(index);
+        }
+        state.change_creep_energy(creep, moved as i64);
+        Ok(())
+    }
+
+    fn build(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 3)?;
+        if state.worked.contains(creep) {
+            return Err(ErrorCode::Busy);
+        }
+        let work = creep_info.count_parts(Part::Work);
+        if work == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        if creep_info.store.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        let index = state
+            .construction_sites
+            .iter()
+            .position(|s| s.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let site = &mut state.construction_sites[index];
+        let amount = (work * BUILD_POWER)
+            .min(creep_info.store.energy)
+            .min(site.progress_total - site.progress);
+        site.progress += amount;
+
+        if site.progress >= site.progress_total {
+            let site = state.construction_sites.remove(index);
+            let id = state.new_id();
+            let rcl = state.controller.level;
+            state
+                .structures
+                .push(new_structure(id, site.structure_type, site.pos, rcl, 0));
+            state.stats.structures_built += 1;
+        }
+        state.change_creep_energy(creep, -(amount as i64));
+        state.stats.energy_spent_on_building += amount;
+        state.worked.insert(creep.to_string());
+        Ok(())
+    }
+
+    fn upgrade_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 3)?;
+        if state.controller.id != target {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        if state.worked.contains(creep) {
+            return Err(ErrorCode::Busy);
+        }
+        let work = creep_info.count_parts(Part::Work);
+        if work == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        if creep_info.store.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        let amount = (work * UPGRADE_CONTROLLER_POWER).min(creep_info.store.energy);
+        let time = state.time;
+
+        let controller = &mut state.controller;
+        controller.ticks_to_downgrade = controller_downgrade(controller.level).unwrap_or(0);
+        let mut level_up = None;
+        if controller.level < 8 {
+            controller.progress += amount;
+            if controller.progress >= controller.progress_total {
+                controller.progress -= controller.progress_total;
+                controller.level += 1;
+                controller.progress_total =
+                    controller_levels(controller.level as u32).unwrap_or(0);
+                level_up = Some(controller.level);
+            }
+        }
+        if let Some(level) = level_up {
+            // extensions hold more at higher levels
+            for structure in state.structures.iter_mut() {
+                if structure.structure_type == StructureType::Extension {
+                    if let Some(store) = structure.store.as_mut() {
+                        store.capacity = extension_energy_capacity(level as u32);
+                    }
+                }
+            }
+            state.stats.rcl_reached.entry(level).or_insert(time);
+        }
+
+        state.change_creep_energy(creep, -(amount as i64));
+        state.stats.energy_spent_on_upgrading += amount;
+        state.worked.insert(creep.to_string());
+        Ok(())
+    }
+
+    // a hundred hits per WORK part, a hundredth of an energy per hit
+    fn repair(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.check_action(creep, target, 3)?;
+        if state.worked.contains(creep) {
+            return Err(ErrorCode::Busy);
+        }
+        let work = creep_info.count_parts(Part::Work);
+        if work == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        if creep_info.store.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        let structure = state
+            .structures
+            .iter_mut()
+            .find(|s| s.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let energy = work.min(creep_info.store.energy);
+        let hits = (energy * REPAIR_POWER).min(structure.hits_max - structure.hits);
+        structure.hits += hits;
+        let cost = (hits as f32 * REPAIR_COST).ceil() as u32;
+        state.change_creep_energy(creep, -(cost as i64));
+        state.stats.energy_spent_on_repairing += cost;
+        state.worked.insert(creep.to_string());
+        Ok(())
+    }
+
+    fn move_direction(&self, creep: &str, direction: Direction) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let from = state.check_move(creep)?.pos.xy();
+        // the only room has no exits
+        let step = from.checked_add_direction(direction).ok_or(ErrorCode::NoPath)?;
+        state.moves.insert(creep.to_string(), step);
+        Ok(())
+    }
+
+    // 3% of the energy is lost on the way, the sender cools down one tick per
+    // tile between the links
+    fn transfer_energy(
+        &self,
+        link: RawObjectId,
+        target: RawObjectId,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let time = state.time;
+        let find_link = |state: &SimState, id: RawObjectId| {
+            state
+                .structures
+                .iter()
+                .position(|s| s.id == id && s.structure_type == StructureType::Link)
+                .ok_or(ErrorCode::InvalidTarget)
+        };
+        let from = find_link(&state, link)?;
+        let to = find_link(&state, target)?;
+        if from == to {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        if state.link_cooldowns.get(&link).is_some_and(|ready| *ready > time) {
+            return Err(ErrorCode::Tired);
+        }
+        let available = state.structures[from].store.map_or(0, |store| store.energy);
+        let free = state.structures[to].store.map_or(0, |store| store.free());
+        let wanted = amount.unwrap_or(available);
+        if wanted == 0 || wanted > available {
+            return Err(ErrorCode::NotEnough);
+        }
+        if wanted > free {
+            return Err(ErrorCode::Full);
+        }
+        let received = wanted - (wanted as f32 * LINK_LOSS_RATIO).ceil() as u32;
+        let range = state.structures[from]
+            .pos
+            .get_range_to(state.structures[to].pos);
+        if let Some(store) = state.structures[from].store.as_mut() {
+            store.energy -= wanted;
+            store.used -= wanted;
+        }
+        if let Some(store) = state.structures[to].store.as_mut() {
+            store.energy += received;
+            store.used += received;
+        }
+        state
+            .link_cooldowns
+            .insert(link, time + LINK_COOLDOWN * range);
+        Ok(())
+    }
+
+    // full power up to 5 tiles, falling off to a quarter at 20
+    fn tower_repair(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let from = state
+            .structures
+            .iter()
+            .position(|s| s.id == tower && s.structure_type == StructureType::Tower)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let to = state
+            .structures
+            .iter()
+            .position(|s| s.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let worker = format!("tower-{}", tower);
+        if state.worked.contains(&worker) {
+            return Err(ErrorCode::Busy);
+        }
+        if state.structures[from].store.map_or(0, |store| store.energy) < TOWER_ENERGY_COST {
+            return Err(ErrorCode::NotEnough);
+        }
+        let range = state.structures[from]
+            .pos
+            .get_range_to(state.structures[to].pos);
+        let power = tower_power(TOWER_POWER_REPAIR, range);
+        let structure = &mut state.structures[to];
+        structure.hits = (structure.hits + power).min(structure.hits_max);
+        if let Some(store) = state.structures[from].store.as_mut() {
+            store.energy -= TOWER_ENERGY_COST;
+            store.used -= TOWER_ENERGY_COST;
+        }
+        state.stats.energy_spent_on_repairing += TOWER_ENERGY_COST;
+        state.worked.insert(worker);
+        Ok(())
+    }
+
+    // the simulated controller comes without safe mode charges
+    fn activate_safe_mode(&self, _controller: RawObjectId) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NotEnough)
+    }
+
+    fn attack(&self, _creep: &str, _target: RawObjectId) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidTarget)
+    }
+
+    fn heal(&self, creep: &str, target: &str) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let creep_info = state.creeps.get(creep).ok_or(ErrorCode::NotFound)?.clone();
+        if creep_info.spawning {
+            return Err(ErrorCode::Busy);
+        }
+        let heal = creep_info.count_parts(Part::Heal) * HEAL_POWER;
+        if heal == 0 {
+            return Err(ErrorCode::NoBodypart);
+        }
+        let target = state.creeps.get_mut(target).ok_or(ErrorCode::InvalidTarget)?;
+        if creep_info.pos.get_range_to(target.pos) > 1 {
+            return Err(ErrorCode::NotInRange);
+        }
+        target.hits = (target.hits + heal).min(target.hits_max);
+        Ok(())
+    }
+
+    // the simulated room is the only one, there is no controller to take
+    fn claim_controller(&self, _creep: &str, _target: RawObjectId) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidTarget)
+    }
+
+    fn reserve_controller(&self, _creep: &str, _target: RawObjectId) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidTarget)
+    }
+
+    // there are no other players to shoot at
+    fn tower_attack(&self, _tower: RawObjectId, _target: RawObjectId) -> Result<(), ErrorCode> {
+        Err(ErrorCode::InvalidTarget)
+    }
+
+    fn tower_heal(&self, tower: RawObjectId, creep: &str) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let from = state
+            .structures
+            .iter()
+            .position(|s| s.id == tower && s.structure_type == StructureType::Tower)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let worker = format!("tower-{}", tower);
+        if state.worked.contains(&worker) {
+            return Err(ErrorCode::Busy);
+        }
+        if state.structures[from].store.map_or(0, |store| store.energy) < TOWER_ENERGY_COST {
+            return Err(ErrorCode::NotEnough);
+        }
+        let tower_pos = state.structures[from].pos;
+        let target = state.creeps.get_mut(creep).ok_or(ErrorCode::InvalidTarget)?;
+        let power = tower_power(TOWER_POWER_HEAL, tower_pos.get_range_to(target.pos));
+        target.hits = (target.hits + power).min(target.hits_max);
+        if let Some(store) = state.structures[from].store.as_mut() {
+            store.energy -= TOWER_ENERGY_COST;
+            store.used -= TOWER_ENERGY_COST;
+        }
+        state.worked.insert(worker);
+        Ok(())
+    }
+
+    fn create_construction_site(
+        &self,
+        pos: Position,
+        structure_type: StructureType,
+    ) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        if pos.room_name() != state.room_name {
+            return Err(ErrorCode::NotInRange);
+        }
+        if state.terrain.get(pos.xy()) == Terrain::Wall && structure_type != StructureType::Road {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        let occupied = state.structures.iter().any(|s| {
+            s.pos == pos
+                && (s.structure_type == structure_type
+                    || (s.structure_type != StructureType::Road
+                        && s.structure_type != StructureType::Rampart))
+        }) || state.construction_sites.iter().any(|s| s.pos == pos);
+        if occupied {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        let rcl = state.controller.level as u32;
+        let placed = state
+            .structures
+            .iter()
+            .map(|s| s.structure_type)
+            .chain(state.construction_sites.iter().map(|s| s.structure_type))
+            .filter(|placed_type| *placed_type == structure_type)
+            .count() as u32;
+        if placed >= structure_type.controller_structures(rcl) {
+            return Err(ErrorCode::RclNotEnough);
+        }
+
+        let id = state.new_id();
+        state.construction_sites.push(ConstructionSiteInfo {
+            id,
+            structure_type,
+            pos,
+            progress: 0,
+            progress_total: structure_type.construction_cost().unwrap_or(1),
+        });
+        Ok(())
+    }
+
+    fn load_snapshot(&self) -> Option<String> {
+        self.state.borrow().snapshot.clone()
+    }
+
+    fn store_snapshot(&self, snapshot: &str) {
+        self.state.borrow_mut().snapshot = Some(snapshot.to_string());
+    }
+}
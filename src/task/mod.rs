@@ -0,0 +1,497 @@
+use log::debug;
+use screeps::{ErrorCode, Part, Position, RawObjectId, ResourceType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    movement::{self, MovePriority},
+    world::{CreepInfo, World},
+};
+
+// what a task does to its target
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskAction {
+    Harvest {
+        target: RawObjectId,
+    },
+    Transfer {
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    },
+    Withdraw {
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    },
+    // one of our creeps, by name
+    TransferToCreep {
+        target: String,
+        resource: ResourceType,
+    },
+    Pickup {
+        target: RawObjectId,
+    },
+    Build {
+        target: RawObjectId,
+    },
+    Repair {
+        target: RawObjectId,
+    },
+    Upgrade {
+        target: RawObjectId,
+    },
+    GoTo {
+        range: u32,
+    },
+    Claim {
+        target: RawObjectId,
+    },
+    Reserve {
+        target: RawObjectId,
+    },
+    Attack {
+        target: RawObjectId,
+    },
+    // one of our creeps, by name
+    Heal {
+        target: String,
+    },
+}
+
+// one thing for a creep to do, kept in its memory until it is done. the creep
+// walks up to the target on its own. tasks chain with `then`, the next one
+// takes over once this one is no longer valid.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub action: TaskAction,
+    // where the target is
+    pub pos: Position,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<Box<Task>>,
+}
+
+impl Task {
+    pub fn new(action: TaskAction, pos: Position) -> Self {
+        Task {
+            action,
+            pos,
+            next: None,
+        }
+    }
+
+    pub fn harvest(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Harvest { target }, pos)
+    }
+
+    pub fn transfer(target: RawObjectId, pos: Position, resource: ResourceType) -> Self {
+        Self::new(
+            TaskAction::Transfer {
+                target,
+                resource,
+                amount: None,
+            },
+            pos,
+        )
+    }
+
+    pub fn withdraw(target: RawObjectId, pos: Position, resource: ResourceType) -> Self {
+        Self::new(
+            TaskAction::Withdraw {
+                target,
+                resource,
+                amount: None,
+            },
+            pos,
+        )
+    }
+
+    // `pos` is where the target stands now, it is given again once it moved
+    pub fn transfer_to_creep(target: &str, pos: Position, resource: ResourceType) -> Self {
+        Self::new(
+            TaskAction::TransferToCreep {
+                target: target.to_string(),
+                resource,
+            },
+            pos,
+        )
+    }
+
+    pub fn pickup(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Pickup { target }, pos)
+    }
+
+    pub fn build(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Build { target }, pos)
+    }
+
+    pub fn repair(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Repair { target }, pos)
+    }
+
+    pub fn upgrade(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Upgrade { target }, pos)
+    }
+
+    pub fn go_to(pos: Position, range: u32) -> Self {
+        Self::new(TaskAction::GoTo { range }, pos)
+    }
+
+    pub fn claim(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Claim { target }, pos)
+    }
+
+    pub fn reserve(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Reserve { target }, pos)
+    }
+
+    pub fn attack(target: RawObjectId, pos: Position) -> Self {
+        Self::new(TaskAction::Attack { target }, pos)
+    }
+
+    pub fn heal(target: &CreepInfo) -> Self {
+        Self::new(
+            TaskAction::Heal {
+                target: target.name.clone(),
+            },
+            target.pos,
+        )
+    }
+
+    // appends `task` to the end of the chain
+    pub fn then(mut self, task: Task) -> Self {
+        let mut last = &mut self;
+        while let Some(ref mut next) = last.next {
+            last = next;
+        }
+        last.next = Some(Box::new(task));
+        self
+    }
+
+    // how close the creep has to be to act
+    pub fn range(&self) -> u32 {
+        match self.action {
+            TaskAction::Build { .. } | TaskAction::Repair { .. } | TaskAction::Upgrade { .. } => 3,
+            TaskAction::GoTo { range } => range,
+            _ => 1,
+        }
+    }
+
+    // who gets a contested tile first on the way to the target
+    pub fn priority(&self) -> MovePriority {
+        match self.action {
+            TaskAction::Attack { .. } | TaskAction::Heal { .. } => MovePriority::High,
+            TaskAction::Build { .. } | TaskAction::Repair { .. } | TaskAction::Upgrade { .. } => {
+                MovePriority::Low
+            }
+            _ => MovePriority::Normal,
+        }
+    }
+
+    // `other` is this task or one further down its chain, so a creep working
+    // on it already follows this chain
+    pub fn leads_to(&self, other: &Task) -> bool {
+        let mut task = Some(self);
+        while let Some(current) = task {
+            if current == other {
+                return true;
+            }
+            task = current.next.as_deref();
+        }
+        false
+    }
+
+    // whether the creep can still do something about the target. targets in
+    // rooms out of sight are taken on trust.
+    pub fn is_valid(&self, world: &dyn World, creep: &CreepInfo) -> bool {
+        let room_name = self.pos.room_name();
+        if !matches!(
+            self.action,
+            TaskAction::GoTo { .. } | TaskAction::Heal { .. } | TaskAction::TransferToCreep { .. }
+        )
+            && world.room(room_name).is_none()
+        {
+            return true;
+        }
+        let has = |part: Part| creep.count_parts(part) > 0;
+        let structure = |id: RawObjectId| {
+            world
+                .structures(room_name)
+                .into_iter()
+                .find(|structure| structure.id == id)
+        };
+        let controller = |id: RawObjectId| {
+            world
+                .controller(room_name)
+                .filter(|controller| controller.id == id)
+        };
+        match &self.action {
+            // static miners harvest on while full, the overflow drops
+            TaskAction::Harvest { target } => {
+                has(Part::Work)
+                    && world
+                        .sources(room_name)
+                        .iter()
+                        .any(|source| source.id == *target && source.energy > 0)
+            }
+            TaskAction::Transfer {
+                target, resource, ..
+            } => {
+                carries(creep, *resource)
+                    && structure(*target)
+                        .and_then(|structure| structure.store)
+                        .is_some_and(|store| store.free() > 0)
+            }
+            TaskAction::Withdraw {
+                target, resource, ..
+            } => {
+                creep.store.free() > 0
+                    && structure(*target)
+                        .and_then(|structure| structure.store)
+                        .is_some_and(|store| match resource {
+                            ResourceType::Energy => store.energy > 0,
+                            _ => store.used > store.energy,
+                        })
+            }
+            TaskAction::TransferToCreep { target, resource } => {
+                carries(creep, *resource)
+                    && world
+                        .creep(target)
+                        .is_some_and(|target| target.store.free() > 0)
+            }
+            TaskAction::Pickup { target } => {
+                creep.store.free() > 0
+                    && world
+                        .dropped_resources(room_name)
+                        .iter()
+                        .any(|resource| resource.id == *target)
+            }
+            TaskAction::Build { target } => {
+                has(Part::Work)
+                    && creep.store.energy > 0
+                    && world
+                        .construction_sites(room_name)
+                        .iter()
+                        .any(|site| site.id == *target)
+            }
+            TaskAction::Repair { target } => {
+                has(Part::Work)
+                    && creep.store.energy > 0
+                    && structure(*target).is_some_and(|structure| structure.hits < structure.hits_max)
+            }
+            TaskAction::Upgrade { target } => {
+                has(Part::Work)
+                    && creep.store.energy > 0
+                    && controller(*target).is_some_and(|controller| controller.my)
+            }
+            TaskAction::GoTo { range } => creep.pos.get_range_to(self.pos) > *range,
+            TaskAction::Claim { target } | TaskAction::Reserve { target } => {
+                has(Part::Claim) && controller(*target).is_some_and(|controller| !controller.my)
+            }
+            TaskAction::Attack { target } => {
+                has(Part::Attack)
+                    && world
+                        .hostile_creeps(room_name)
+                        .iter()
+                        .any(|hostile| hostile.id == *target)
+            }
+            TaskAction::Heal { target } => {
+                has(Part::Heal)
+                    && world
+                        .creep(target)
+                        .is_some_and(|target| target.hits < target.hits_max)
+            }
+        }
+    }
+
+    // acts on the target, or walks up to it while it is out of range. ok while
+    // the creep is on its way.
+    pub fn run(&self, world: &dyn World, creep: &CreepInfo) -> Result<(), ErrorCode> {
+        let name = creep.name.as_str();
+        let result = match &self.action {
+            TaskAction::Harvest { target } => world.harvest(name, *target),
+            TaskAction::Transfer {
+                target,
+                resource,
+                amount,
+            } => world.transfer(name, *target, *resource, *amount),
+            TaskAction::Withdraw {
+                target,
+                resource,
+                amount,
+            } => world.withdraw(name, *target, *resource, *amount),
+            TaskAction::TransferToCreep { target, resource } => {
+                world.transfer_to_creep(name, target, *resource, None)
+            }
+            TaskAction::Pickup { target } => world.pickup(name, *target),
+            TaskAction::Build { target } => world.build(name, *target),
+            TaskAction::Repair { target } => world.repair(name, *target),
+            TaskAction::Upgrade { target } => world.upgrade_controller(name, *target),
+            TaskAction::GoTo { range } if creep.pos.get_range_to(self.pos) <= *range => Ok(()),
+            TaskAction::GoTo { .. } => Err(ErrorCode::NotInRange),
+            TaskAction::Claim { target } => world.claim_controller(name, *target),
+            TaskAction::Reserve { target } => world.reserve_controller(name, *target),
+            TaskAction::Attack { target } => world.attack(name, *target),
+            TaskAction::Heal { target } => world.heal(name, target),
+        };
+        match result {
+            // a creep that can't move this tick tries again the next one
+            Err(ErrorCode::NotInRange) => {
+                let _ = movement::travel(world, creep, self.pos, self.range(), self.priority());
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+fn carries(creep: &CreepInfo, resource: ResourceType) -> bool {
+    match resource {
+        ResourceType::Energy => creep.store.energy > 0,
+        _ => creep.store.used > creep.store.energy,
+    }
+}
+
+// gives the creep `task`, unless it works on it already. a creep further down
+// the same chain keeps its place.
+pub fn assign_task(world: &dyn World, creep: &mut CreepInfo, task: Task) {
+    let memory = match creep.memory.as_mut() {
+        Some(memory) => memory,
+        None => return,
+    };
+    if memory.task.as_ref().is_some_and(|current| task.leads_to(current)) {
+        return;
+    }
+    memory.task = Some(task);
+    world.set_creep_memory(&creep.name, memory);
+}
+
+// runs the task in the memory of the creep. tasks that are no longer valid make
+// way for the next one of their chain. none once the creep has nothing to do.
+pub fn run_task(world: &dyn World, creep: &CreepInfo) -> Option<Result<(), ErrorCode>> {
+    let mut memory = creep.memory.clone()?;
+    let mut task = memory.task.clone();
+    while let Some(current) = task.take() {
+        if current.is_valid(world, creep) {
+            task = Some(current);
+            break;
+        }
+        debug!("{} done with {:?}", creep.name, current.action);
+        task = current.next.map(|next| *next);
+    }
+    if task != memory.task {
+        memory.task = task.clone();
+        world.set_creep_memory(&creep.name, &memory);
+    }
+    Some(task?.run(world, creep))
+}
+
+// `assign_task` and `run_task` in one go, for overlords that pick the task of
+// a creep every tick. ok if the task turned out to be done already.
+pub fn do_task(world: &dyn World, creep: &CreepInfo, task: Task) -> Result<(), ErrorCode> {
+    let mut creep = creep.clone();
+    assign_task(world, &mut creep, task);
+    run_task(world, &creep).unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::mock::{position, MockAction, MockWorld},
+        zerg::CreepMemory,
+    };
+    use screeps::{Direction, RoomName};
+
+    const ROOM: &str = "W1N1";
+
+    fn setup() -> (MockWorld, RawObjectId) {
+        let world = MockWorld::new();
+        world.add_room(ROOM, 300, 300);
+        world.add_controller(position(ROOM, 10, 10), 2);
+        let source = world.add_source(position(ROOM, 20, 20));
+        world.add_creep(
+            "creep",
+            position(ROOM, 30, 30),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: "mine".to_string(),
+                role: "drone".to_string(),
+                colony: ROOM.to_string(),
+                state: None,
+                task: None,
+            },
+        );
+        (world, source)
+    }
+
+    fn creep(world: &MockWorld) -> CreepInfo {
+        world.creep("creep").unwrap()
+    }
+
+    #[test]
+    fn walks_up_to_the_target() {
+        let (world, source) = setup();
+        let task = Task::harvest(source, position(ROOM, 20, 20));
+        assert_eq!(do_task(&world, &creep(&world), task.clone()), Ok(()));
+        movement::run_traffic(&world);
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::Move {
+                creep: "creep".to_string(),
+                direction: Direction::TopLeft
+            }]
+        );
+        assert_eq!(creep(&world).memory.unwrap().task, Some(task));
+    }
+
+    #[test]
+    fn chained_task_takes_over_once_the_first_is_done() {
+        let (world, source) = setup();
+        let task = Task::go_to(position(ROOM, 21, 21), 0)
+            .then(Task::harvest(source, position(ROOM, 20, 20)));
+        let mut creep = creep(&world);
+        assign_task(&world, &mut creep, task.clone());
+        run_task(&world, &creep);
+        movement::run_traffic(&world);
+        assert_eq!(world.take_actions().len(), 1);
+
+        world.state.borrow_mut().creeps.get_mut("creep").unwrap().pos = position(ROOM, 21, 21);
+        let mut creep = self::creep(&world);
+        assert_eq!(run_task(&world, &creep), Some(Ok(())));
+        assert_eq!(
+            world.take_actions(),
+            vec![MockAction::Harvest {
+                creep: "creep".to_string(),
+                target: source
+            }]
+        );
+        // the overlord asks for the whole chain again, the creep stays where it is
+        assign_task(&world, &mut creep, task);
+        let memory = self::creep(&world).memory.unwrap();
+        assert_eq!(memory.task.unwrap().action, TaskAction::Harvest { target: source });
+    }
+
+    #[test]
+    fn invalid_tasks_are_dropped() {
+        let (world, _) = setup();
+        let controller = world.controller(RoomName::new(ROOM).unwrap()).unwrap();
+        // nothing to upgrade with
+        let task = Task::upgrade(controller.id, controller.pos);
+        assert_eq!(do_task(&world, &creep(&world), task), Ok(()));
+        assert!(world.take_actions().is_empty());
+        assert_eq!(creep(&world).memory.unwrap().task, None);
+    }
+
+    #[test]
+    fn tasks_survive_in_memory() {
+        let task = Task::go_to(position(ROOM, 5, 5), 1).then(Task::transfer(
+            RawObjectId::from(7),
+            position(ROOM, 5, 6),
+            ResourceType::Energy,
+        ));
+        let json = serde_json::to_string(&task).unwrap();
+        assert!(json.contains(r#""type":"go_to""#));
+        assert_eq!(serde_json::from_str::<Task>(&json).unwrap(), task);
+    }
+}
@@ -0,0 +1,923 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use screeps::{
+    constants::CPU_BUCKET_MAX, Direction, ErrorCode, LocalRoomTerrain, Part, Position, RawObjectId, ResourceType, RoomCoordinate,
+    RoomName, StructureType,
+};
+
+use crate::zerg::CreepMemory;
+
+use super::{
+    BodyPartInfo, ConstructionSiteInfo, ControllerInfo, CreepInfo, DroppedResourceInfo,
+    HostileCreepInfo, MineralInfo, RoomInfo, SourceInfo, SpawnInfo, StoreInfo, StructureInfo,
+    World,
+};
+
+// in-memory `World` for native tests. reads come from `MockState`, every action
+// is recorded in `actions`. actions only check ranges and costs, they don't
+// advance the game, tests change the state by hand instead.
+#[derive(Default)]
+pub struct MockWorld {
+    pub state: RefCell<MockState>,
+    pub actions: RefCell<Vec<MockAction>>,
+}
+
+#[derive(Default)]
+pub struct MockState {
+    pub time: u32,
+    pub cpu_used: f64,
+    // a full bucket unless set
+    pub cpu_bucket: Option<i32>,
+    pub rooms: HashMap<RoomName, MockRoom>,
+    pub creeps: HashMap<String, CreepInfo>,
+    pub snapshot: Option<String>,
+    next_id: u128,
+}
+
+pub struct MockRoom {
+    pub info: RoomInfo,
+    // row-major terrain bits, all plain unless set with `set_terrain`
+    pub terrain: Box<[u8; 2500]>,
+    pub controller: Option<ControllerInfo>,
+    pub sources: Vec<SourceInfo>,
+    pub minerals: Vec<MineralInfo>,
+    pub spawns: Vec<SpawnInfo>,
+    pub structures: Vec<StructureInfo>,
+    pub construction_sites: Vec<ConstructionSiteInfo>,
+    pub dropped_resources: Vec<DroppedResourceInfo>,
+    pub hostile_creeps: Vec<HostileCreepInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockAction {
+    SpawnCreep {
+        spawn: RawObjectId,
+        body: Vec<Part>,
+        name: String,
+    },
+    Harvest {
+        creep: String,
+        target: RawObjectId,
+    },
+    Transfer {
+        creep: String,
+        target: RawObjectId,
+        resource: ResourceType,
+    },
+    Withdraw {
+        creep: String,
+        target: RawObjectId,
+        resource: ResourceType,
+    },
+    TransferToCreep {
+        creep: String,
+        target: String,
+        resource: ResourceType,
+    },
+    Pickup {
+        creep: String,
+        target: RawObjectId,
+    },
+    Build {
+        creep: String,
+        target: RawObjectId,
+    },
+    UpgradeController {
+        creep: String,
+        target: RawObjectId,
+    },
+    Repair {
+        creep: String,
+        target: RawObjectId,
+    },
+    Attack {
+        creep: String,
+        target: RawObjectId,
+    },
+    Heal {
+        creep: String,
+        target: String,
+    },
+    ClaimController {
+        creep: String,
+        target: RawObjectId,
+    },
+    ReserveController {
+        creep: String,
+        target: RawObjectId,
+    },
+    ActivateSafeMode {
+        controller: RawObjectId,
+    },
+    TowerRepair {
+        tower: RawObjectId,
+        target: RawObjectId,
+    },
+    TowerAttack {
+        tower: RawObjectId,
+        target: RawObjectId,
+    },
+    TowerHeal {
+        tower: RawObjectId,
+        creep: String,
+    },
+    Move {
+        creep: String,
+        direction: Direction,
+    },
+    TransferEnergy {
+        link: RawObjectId,
+        target: RawObjectId,
+    },
+    CreateConstructionSite {
+        pos: Position,
+        structure_type: StructureType,
+    },
+}
+
+pub fn position(room_name: &str, x: u8, y: u8) -> Position {
+    Position::new(
+        RoomCoordinate::new(x).unwrap(),
+        RoomCoordinate::new(y).unwrap(),
+        RoomName::new(room_name).unwrap(),
+    )
+}
+
+impl MockWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next_id(&self) -> RawObjectId {
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        RawObjectId::from(state.next_id)
+    }
+
+    pub fn add_room(&self, room_name: &str, energy_available: u32, energy_capacity: u32) {
+        let name = RoomName::new(room_name).unwrap();
+        self.state.borrow_mut().rooms.insert(
+            name,
+            MockRoom {
+                info: RoomInfo {
+                    name,
+                    energy_available,
+                    energy_capacity_available: energy_capacity,
+                },
+                terrain: Box::new([0; 2500]),
+                controller: None,
+                sources: Vec::new(),
+                minerals: Vec::new(),
+                spawns: Vec::new(),
+                structures: Vec::new(),
+                construction_sites: Vec::new(),
+                dropped_resources: Vec::new(),
+                hostile_creeps: Vec::new(),
+            },
+        );
+    }
+
+    pub fn add_controller(&self, pos: Position, level: u8) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.controller = Some(ControllerInfo {
+                id,
+                pos,
+                my: true,
+                level,
+                progress: 0,
+                progress_total: 200,
+                ticks_to_downgrade: 20000,
+                safe_mode: 0,
+                safe_mode_available: 1,
+                safe_mode_cooldown: 0,
+            })
+        });
+        id
+    }
+
+    pub fn add_source(&self, pos: Position) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.sources.push(SourceInfo {
+                id,
+                pos,
+                energy: 3000,
+                energy_capacity: 3000,
+            })
+        });
+        id
+    }
+
+    pub fn add_mineral(&self, pos: Position) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| room.minerals.push(MineralInfo { id, pos }));
+        id
+    }
+
+    pub fn add_spawn(&self, name: &str, pos: Position) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.spawns.push(SpawnInfo {
+                id,
+                name: name.to_string(),
+                pos,
+                spawning: false,
+            })
+        });
+        self.add_structure_with_id(
+            id,
+            StructureType::Spawn,
+            pos,
+            Some(StoreInfo {
+                energy: 300,
+                used: 300,
+                capacity: 300,
+            }),
+        );
+        id
+    }
+
+    pub fn add_structure(
+        &self,
+        structure_type: StructureType,
+        pos: Position,
+        store: Option<StoreInfo>,
+    ) -> RawObjectId {
+        let id = self.next_id();
+        self.add_structure_with_id(id, structure_type, pos, store);
+        id
+    }
+
+    fn add_structure_with_id(
+        &self,
+        id: RawObjectId,
+        structure_type: StructureType,
+        pos: Position,
+        store: Option<StoreInfo>,
+    ) {
+        let hits = structure_type.initial_hits().unwrap_or(1);
+        self.with_room(pos, |room| {
+            room.structures.push(StructureInfo {
+                id,
+                structure_type,
+                pos,
+                hits,
+                hits_max: hits,
+                my: structure_type != StructureType::Road
+                    && structure_type != StructureType::Container
+                    && structure_type != StructureType::Wall,
+                store,
+            })
+        });
+    }
+
+    pub fn add_construction_site(
+        &self,
+        structure_type: StructureType,
+        pos: Position,
+    ) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.construction_sites.push(ConstructionSiteInfo {
+                id,
+                structure_type,
+                pos,
+                progress: 0,
+                progress_total: structure_type.construction_cost().unwrap_or(1),
+            })
+        });
+        id
+    }
+
+    pub fn add_dropped_resource(&self, pos: Position, amount: u32) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.dropped_resources.push(DroppedResourceInfo {
+                id,
+                pos,
+                resource_type: ResourceType::Energy,
+                amount,
+            })
+        });
+        id
+    }
+
+    pub fn add_creep(&self, name: &str, pos: Position, body: &[Part], memory: CreepMemory) {
+        let capacity = body.iter().filter(|part| **part == Part::Carry).count() as u32 * 50;
+        self.state.borrow_mut().creeps.insert(
+            name.to_string(),
+            CreepInfo {
+                name: name.to_string(),
+                pos,
+                body: body.to_vec(),
+                store: StoreInfo {
+                    energy: 0,
+                    used: 0,
+                    capacity,
+                },
+                spawning: false,
+                ticks_to_live: Some(1500),
+                hits: body.len() as u32 * 100,
+                hits_max: body.len() as u32 * 100,
+                fatigue: 0,
+                memory: Some(memory),
+            },
+        );
+    }
+
+    pub fn add_hostile_creep(&self, owner: &str, pos: Position, body: &[Part]) -> RawObjectId {
+        let id = self.next_id();
+        self.with_room(pos, |room| {
+            room.hostile_creeps.push(HostileCreepInfo {
+                id,
+                owner: owner.to_string(),
+                pos,
+                body: body
+                    .iter()
+                    .map(|part| BodyPartInfo {
+                        part: *part,
+                        boost: None,
+                    })
+                    .collect(),
+                hits: body.len() as u32 * 100,
+                hits_max: body.len() as u32 * 100,
+            })
+        });
+        id
+    }
+
+    pub fn set_safe_mode(&self, room_name: &str, active: u32, available: u32, cooldown: u32) {
+        let room_name = RoomName::new(room_name).unwrap();
+        if let Some(controller) = self
+            .state
+            .borrow_mut()
+            .rooms
+            .get_mut(&room_name)
+            .and_then(|room| room.controller.as_mut())
+        {
+            controller.safe_mode = active;
+            controller.safe_mode_available = available;
+            controller.safe_mode_cooldown = cooldown;
+        }
+    }
+
+    pub fn set_creep_hits(&self, name: &str, hits: u32) {
+        if let Some(creep) = self.state.borrow_mut().creeps.get_mut(name) {
+            creep.hits = hits;
+        }
+    }
+
+    // `terrain` is 0 for plain, 1 for wall and 2 for swamp
+    pub fn set_terrain(&self, pos: Position, terrain: u8) {
+        self.with_room(pos, |room| {
+            room.terrain[pos.y().u8() as usize * 50 + pos.x().u8() as usize] = terrain
+        });
+    }
+
+    pub fn set_creep_energy(&self, name: &str, energy: u32) {
+        if let Some(creep) = self.state.borrow_mut().creeps.get_mut(name) {
+            creep.store.energy = energy;
+            creep.store.used = energy;
+        }
+    }
+
+    pub fn set_site_progress(&self, id: RawObjectId, progress: u32) {
+        for room in self.state.borrow_mut().rooms.values_mut() {
+            for site in room.construction_sites.iter_mut().filter(|site| site.id == id) {
+                site.progress = progress;
+            }
+        }
+    }
+
+    pub fn set_structure_hits(&self, id: RawObjectId, hits: u32, hits_max: u32) {
+        for room in self.state.borrow_mut().rooms.values_mut() {
+            for structure in room.structures.iter_mut().filter(|s| s.id == id) {
+                structure.hits = hits;
+                structure.hits_max = hits_max;
+            }
+        }
+    }
+
+    pub fn take_actions(&self) -> Vec<MockAction> {
+        std::mem::take(&mut self.actions.borrow_mut())
+    }
+
+    fn with_room<F>(&self, pos: Position, f: F)
+    where
+        F: FnOnce(&mut MockRoom),
+    {
+        let mut state = self.state.borrow_mut();
+        let room = state
+            .rooms
+            .get_mut(&pos.room_name())
+            .expect("add the room before adding objects to it");
+        f(room)
+    }
+
+    fn find_pos(&self, id: RawObjectId) -> Option<Position> {
+        let state = self.state.borrow();
+        state.rooms.values().find_map(|room| {
+            room.controller
+                .iter()
+                .filter(|controller| controller.id == id)
+                .map(|controller| controller.pos)
+                .chain(room.sources.iter().filter(|s| s.id == id).map(|s| s.pos))
+                .chain(room.structures.iter().filter(|s| s.id == id).map(|s| s.pos))
+                .chain(
+                    room.construction_sites
+                        .iter()
+                        .filter(|s| s.id == id)
+                        .map(|s| s.pos),
+                )
+                .chain(
+                    room.dropped_resources
+                        .iter()
+                        .filter(|r| r.id == id)
+                        .map(|r| r.pos),
+                )
+                .chain(room.hostile_creeps.iter().filter(|c| c.id == id).map(|c| c.pos))
+                .next()
+        })
+    }
+
+    // the checks every creep action shares: creep exists, target exists and is in range
+    fn check_action(&self, creep: &str, target: RawObjectId, range: u32) -> Result<(), ErrorCode> {
+        let creep_pos = self
+            .state
+            .borrow()
+            .creeps
+            .get(creep)
+            .map(|creep| creep.pos)
+            .ok_or(ErrorCode::NotFound)?;
+        let target_pos = self.find_pos(target).ok_or(ErrorCode::InvalidTarget)?;
+        if creep_pos.get_range_to(target_pos) > range {
+            return Err(ErrorCode::NotInRange);
+        }
+        Ok(())
+    }
+
+    // the same for actions on another one of our creeps, next to the creep
+    fn check_creep_action(&self, creep: &str, target: &str) -> Result<(), ErrorCode> {
+        let state = self.state.borrow();
+        let creep_pos = state.creeps.get(creep).map(|c| c.pos).ok_or(ErrorCode::NotFound)?;
+        let target_pos = state
+            .creeps
+            .get(target)
+            .map(|c| c.pos)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if creep_pos.get_range_to(target_pos) > 1 {
+            return Err(ErrorCode::NotInRange);
+        }
+        Ok(())
+    }
+}
+
+impl World for MockWorld {
+    fn time(&self) -> u32 {
+        self.state.borrow().time
+    }
+
+    fn cpu_used(&self) -> f64 {
+        self.state.borrow().cpu_used
+    }
+
+    fn cpu_bucket(&self) -> i32 {
+        self.state
+            .borrow()
+            .cpu_bucket
+            .unwrap_or(CPU_BUCKET_MAX as i32)
+    }
+
+    fn spawn_rooms(&self) -> Vec<RoomName> {
+        let mut room_names: Vec<RoomName> = self
+            .state
+            .borrow()
+            .rooms
+            .values()
+            .filter(|room| !room.spawns.is_empty())
+            .map(|room| room.info.name)
+            .collect();
+        room_names.sort();
+        room_names
+    }
+
+    fn room(&self, room_name: RoomName) -> Option<RoomInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.info.clone())
+    }
+
+    fn terrain(&self, room_name: RoomName) -> Option<LocalRoomTerrain> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| LocalRoomTerrain::new_from_bits(room.terrain.clone()))
+    }
+
+    fn controller(&self, room_name: RoomName) -> Option<ControllerInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .and_then(|room| room.controller.clone())
+    }
+
+    fn sources(&self, room_name: RoomName) -> Vec<SourceInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.sources.clone())
+            .unwrap_or_default()
+    }
+
+    fn minerals(&self, room_name: RoomName) -> Vec<MineralInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.minerals.clone())
+            .unwrap_or_default()
+    }
+
+    fn spawns(&self, room_name: RoomName) -> Vec<SpawnInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.spawns.clone())
+            .unwrap_or_default()
+    }
+
+    fn structures(&self, room_name: RoomName) -> Vec<StructureInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.structures.clone())
+            .unwrap_or_default()
+    }
+
+    fn construction_sites(&self, room_name: RoomName) -> Vec<ConstructionSiteInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.construction_sites.clone())
+            .unwrap_or_default()
+    }
+
+    fn dropped_resources(&self, room_name: RoomName) -> Vec<DroppedResourceInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.dropped_resources.clone())
+            .unwrap_or_default()
+    }
+
+    fn creep(&self, name: &str) -> Option<CreepInfo> {
+        self.state.borrow().creeps.get(name).cloned()
+    }
+
+    fn creeps(&self) -> Vec<CreepInfo> {
+        let mut creeps: Vec<CreepInfo> = self.state.borrow().creeps.values().cloned().collect();
+        creeps.sort_by(|a, b| a.name.cmp(&b.name));
+        creeps
+    }
+
+    fn hostile_creeps(&self, room_name: RoomName) -> Vec<HostileCreepInfo> {
+        self.state
+            .borrow()
+            .rooms
+            .get(&room_name)
+            .map(|room| room.hostile_creeps.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_creep_memory(&self, name: &str, memory: &CreepMemory) {
+        if let Some(creep) = self.state.borrow_mut().creeps.get_mut(name) {
+            creep.memory = Some(memory.clone());
+        }
+    }
+
+    fn spawn_creep(
+        &self,
+        spawn: RawObjectId,
+        body: &[Part],
+        name: &str,
+        memory: &CreepMemory,
+    ) -> Result<(), ErrorCode> {
+        let spawn_info = self
+            .state
+            .borrow()
+            .rooms
+            .values()
+            .flat_map(|room| room.spawns.iter())
+            .find(|spawn_info| spawn_info.id == spawn)
+            .cloned()
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if spawn_info.spawning {
+            return Err(ErrorCode::Busy);
+        }
+        if self.state.borrow().creeps.contains_key(name) {
+            return Err(ErrorCode::NameExists);
+        }
+
+        let cost: u32 = body.iter().map(|part| part.cost()).sum();
+        {
+            let mut state = self.state.borrow_mut();
+            let room = state.rooms.get_mut(&spawn_info.pos.room_name()).unwrap();
+            if room.info.energy_available < cost {
+                return Err(ErrorCode::NotEnough);
+            }
+            room.info.energy_available -= cost;
+            for room_spawn in room.spawns.iter_mut() {
+                if room_spawn.id == spawn {
+                    room_spawn.spawning = true;
+                }
+            }
+        }
+
+        self.add_creep(name, spawn_info.pos, body, memory.clone());
+        if let Some(creep) = self.state.borrow_mut().creeps.get_mut(name) {
+            creep.spawning = true;
+        }
+        self.actions.borrow_mut().push(MockAction::SpawnCreep {
+            spawn,
+            body: body.to_vec(),
+            name: name.to_string(),
+        });
+        Ok(())
+    }
+
+    fn harvest(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::Harvest {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        _amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::Transfer {
+            creep: creep.to_string(),
+            target,
+            resource,
+        });
+        Ok(())
+    }
+
+    fn withdraw(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        _amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::Withdraw {
+            creep: creep.to_string(),
+            target,
+            resource,
+        });
+        Ok(())
+    }
+
+    fn transfer_to_creep(
+        &self,
+        creep: &str,
+        target: &str,
+        resource: ResourceType,
+        _amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        self.check_creep_action(creep, target)?;
+        self.actions.borrow_mut().push(MockAction::TransferToCreep {
+            creep: creep.to_string(),
+            target: target.to_string(),
+            resource,
+        });
+        Ok(())
+    }
+
+    fn pickup(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::Pickup {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn build(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 3)?;
+        self.actions.borrow_mut().push(MockAction::Build {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn upgrade_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 3)?;
+        self.actions
+            .borrow_mut()
+            .push(MockAction::UpgradeController {
+                creep: creep.to_string(),
+                target,
+            });
+        Ok(())
+    }
+
+    fn repair(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 3)?;
+        self.actions.borrow_mut().push(MockAction::Repair {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn attack(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::Attack {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn heal(&self, creep: &str, target: &str) -> Result<(), ErrorCode> {
+        self.check_creep_action(creep, target)?;
+        self.actions.borrow_mut().push(MockAction::Heal {
+            creep: creep.to_string(),
+            target: target.to_string(),
+        });
+        Ok(())
+    }
+
+    fn claim_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::ClaimController {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn reserve_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        self.check_action(creep, target, 1)?;
+        self.actions.borrow_mut().push(MockAction::ReserveController {
+            creep: creep.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn move_direction(&self, creep: &str, direction: Direction) -> Result<(), ErrorCode> {
+        if !self.state.borrow().creeps.contains_key(creep) {
+            return Err(ErrorCode::NotFound);
+        }
+        self.actions.borrow_mut().push(MockAction::Move {
+            creep: creep.to_string(),
+            direction,
+        });
+        Ok(())
+    }
+
+    // links send across the whole room, only checks both ends are links
+    fn transfer_energy(
+        &self,
+        link: RawObjectId,
+        target: RawObjectId,
+        _amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let is_link = |id: RawObjectId| {
+            self.state.borrow().rooms.values().any(|room| {
+                room.structures.iter().any(|structure| {
+                    structure.id == id && structure.structure_type == StructureType::Link
+                })
+            })
+        };
+        if !is_link(link) || !is_link(target) {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        self.actions
+            .borrow_mut()
+            .push(MockAction::TransferEnergy { link, target });
+        Ok(())
+    }
+
+    fn tower_repair(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode> {
+        let is_structure = |id: RawObjectId, structure_type: Option<StructureType>| {
+            self.state.borrow().rooms.values().any(|room| {
+                room.structures.iter().any(|structure| {
+                    structure.id == id
+                        && structure_type.map(|t| t == structure.structure_type) != Some(false)
+                })
+            })
+        };
+        if !is_structure(tower, Some(StructureType::Tower)) || !is_structure(target, None) {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        self.actions
+            .borrow_mut()
+            .push(MockAction::TowerRepair { tower, target });
+        Ok(())
+    }
+
+    fn activate_safe_mode(&self, controller: RawObjectId) -> Result<(), ErrorCode> {
+        let mut state = self.state.borrow_mut();
+        let controller_info = state
+            .rooms
+            .values_mut()
+            .filter_map(|room| room.controller.as_mut())
+            .find(|c| c.id == controller)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if controller_info.safe_mode_available == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if controller_info.safe_mode > 0 || controller_info.safe_mode_cooldown > 0 {
+            return Err(ErrorCode::Tired);
+        }
+        controller_info.safe_mode_available -= 1;
+        self.actions
+            .borrow_mut()
+            .push(MockAction::ActivateSafeMode { controller });
+        Ok(())
+    }
+
+    fn tower_attack(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode> {
+        let state = self.state.borrow();
+        let tower_exists = state.rooms.values().any(|room| {
+            room.structures
+                .iter()
+                .any(|s| s.id == tower && s.structure_type == StructureType::Tower)
+        });
+        let target_exists = state
+            .rooms
+            .values()
+            .any(|room| room.hostile_creeps.iter().any(|creep| creep.id == target));
+        if !tower_exists || !target_exists {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        self.actions
+            .borrow_mut()
+            .push(MockAction::TowerAttack { tower, target });
+        Ok(())
+    }
+
+    fn tower_heal(&self, tower: RawObjectId, creep: &str) -> Result<(), ErrorCode> {
+        let state = self.state.borrow();
+        let tower_exists = state.rooms.values().any(|room| {
+            room.structures
+                .iter()
+                .any(|s| s.id == tower && s.structure_type == StructureType::Tower)
+        });
+        if !tower_exists || !state.creeps.contains_key(creep) {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        self.actions.borrow_mut().push(MockAction::TowerHeal {
+            tower,
+            creep: creep.to_string(),
+        });
+        Ok(())
+    }
+
+    fn create_construction_site(
+        &self,
+        pos: Position,
+        structure_type: StructureType,
+    ) -> Result<(), ErrorCode> {
+        if !self.state.borrow().rooms.contains_key(&pos.room_name()) {
+            return Err(ErrorCode::NotInRange);
+        }
+        self.actions
+            .borrow_mut()
+            .push(MockAction::CreateConstructionSite {
+                pos,
+                structure_type,
+            });
+        Ok(())
+    }
+
+    fn load_snapshot(&self) -> Option<String> {
+        self.state.borrow().snapshot.clone()
+    }
+
+    fn store_snapshot(&self, snapshot: &str) {
+        self.state.borrow_mut().snapshot = Some(snapshot.to_string());
+    }
+}
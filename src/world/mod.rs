@@ -0,0 +1,275 @@
+use screeps::{
+    Direction, ErrorCode, LocalRoomTerrain, Part, Position, RawObjectId, ResourceType, RoomName,
+    StructureType,
+};
+
+use crate::zerg::CreepMemory;
+
+// native tests only, kept out of the wasm we upload
+#[cfg(any(test, not(target_arch = "wasm32")))]
+pub mod mock;
+mod screeps_world;
+
+pub use screeps_world::ScreepsWorld;
+
+// everything the colony logic knows about the game goes through `World`, so the
+// logic can run against `ScreepsWorld` in game and against `MockWorld` natively.
+// reads reflect the state at the beginning of the tick, like the game does.
+pub trait World {
+    fn time(&self) -> u32;
+
+    fn cpu_used(&self) -> f64;
+
+    // cpu saved up from earlier ticks, up to `CPU_BUCKET_MAX`
+    fn cpu_bucket(&self) -> i32;
+
+    // rooms that contain at least one of our spawns
+    fn spawn_rooms(&self) -> Vec<RoomName>;
+
+    fn room(&self, room_name: RoomName) -> Option<RoomInfo>;
+
+    fn terrain(&self, room_name: RoomName) -> Option<LocalRoomTerrain>;
+
+    fn controller(&self, room_name: RoomName) -> Option<ControllerInfo>;
+
+    fn sources(&self, room_name: RoomName) -> Vec<SourceInfo>;
+
+    fn minerals(&self, room_name: RoomName) -> Vec<MineralInfo>;
+
+    // our spawns in the room
+    fn spawns(&self, room_name: RoomName) -> Vec<SpawnInfo>;
+
+    // all structures in the room, including the ones not owned by us
+    fn structures(&self, room_name: RoomName) -> Vec<StructureInfo>;
+
+    // our construction sites in the room
+    fn construction_sites(&self, room_name: RoomName) -> Vec<ConstructionSiteInfo>;
+
+    // resources lying on the ground
+    fn dropped_resources(&self, room_name: RoomName) -> Vec<DroppedResourceInfo>;
+
+    fn creep(&self, name: &str) -> Option<CreepInfo>;
+
+    // all of our creeps
+    fn creeps(&self) -> Vec<CreepInfo>;
+
+    // creeps of other players in the room
+    fn hostile_creeps(&self, room_name: RoomName) -> Vec<HostileCreepInfo>;
+
+    fn set_creep_memory(&self, name: &str, memory: &CreepMemory);
+
+    fn spawn_creep(
+        &self,
+        spawn: RawObjectId,
+        body: &[Part],
+        name: &str,
+        memory: &CreepMemory,
+    ) -> Result<(), ErrorCode>;
+
+    fn harvest(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn transfer(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode>;
+
+    fn withdraw(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode>;
+
+    // hand resources to one of our creeps next to the creep
+    fn transfer_to_creep(
+        &self,
+        creep: &str,
+        target: &str,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode>;
+
+    fn pickup(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn build(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn upgrade_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn repair(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    // melee attack on a hostile creep
+    fn attack(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    // heal one of our creeps next to the creep
+    fn heal(&self, creep: &str, target: &str) -> Result<(), ErrorCode>;
+
+    fn claim_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn reserve_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    // one step to the next tile, see `movement::travel` for walking further
+    fn move_direction(&self, creep: &str, direction: Direction) -> Result<(), ErrorCode>;
+
+    // send energy from one link to another
+    fn transfer_energy(
+        &self,
+        link: RawObjectId,
+        target: RawObjectId,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode>;
+
+    // towers reach the whole room, the repair gets weaker with range
+    fn tower_repair(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn activate_safe_mode(&self, controller: RawObjectId) -> Result<(), ErrorCode>;
+
+    fn tower_attack(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode>;
+
+    // heal one of our creeps
+    fn tower_heal(&self, tower: RawObjectId, creep: &str) -> Result<(), ErrorCode>;
+
+    fn create_construction_site(
+        &self,
+        pos: Position,
+        structure_type: StructureType,
+    ) -> Result<(), ErrorCode>;
+
+    // persisted snapshot of the swarm, see `memory::SwarmMemory`
+    fn load_snapshot(&self) -> Option<String>;
+
+    fn store_snapshot(&self, snapshot: &str);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StoreInfo {
+    pub energy: u32,
+    pub used: u32,
+    pub capacity: u32,
+}
+
+impl StoreInfo {
+    pub fn free(&self) -> u32 {
+        self.capacity.saturating_sub(self.used)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomInfo {
+    pub name: RoomName,
+    pub energy_available: u32,
+    pub energy_capacity_available: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub id: RawObjectId,
+    pub pos: Position,
+    pub my: bool,
+    pub level: u8,
+    pub progress: u32,
+    pub progress_total: u32,
+    pub ticks_to_downgrade: u32,
+    // ticks of safe mode left, 0 while it is off
+    pub safe_mode: u32,
+    pub safe_mode_available: u32,
+    pub safe_mode_cooldown: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceInfo {
+    pub id: RawObjectId,
+    pub pos: Position,
+    pub energy: u32,
+    pub energy_capacity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MineralInfo {
+    pub id: RawObjectId,
+    pub pos: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnInfo {
+    pub id: RawObjectId,
+    pub name: String,
+    pub pos: Position,
+    pub spawning: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructureInfo {
+    pub id: RawObjectId,
+    pub structure_type: StructureType,
+    pub pos: Position,
+    pub hits: u32,
+    pub hits_max: u32,
+    pub my: bool,
+    pub store: Option<StoreInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstructionSiteInfo {
+    pub id: RawObjectId,
+    pub structure_type: StructureType,
+    pub pos: Position,
+    pub progress: u32,
+    pub progress_total: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedResourceInfo {
+    pub id: RawObjectId,
+    pub pos: Position,
+    pub resource_type: ResourceType,
+    pub amount: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreepInfo {
+    pub name: String,
+    pub pos: Position,
+    // active body parts only
+    pub body: Vec<Part>,
+    pub store: StoreInfo,
+    pub spawning: bool,
+    pub ticks_to_live: Option<u32>,
+    pub hits: u32,
+    pub hits_max: u32,
+    pub fatigue: u32,
+    // none if the creep memory can not be parsed
+    pub memory: Option<CreepMemory>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyPartInfo {
+    pub part: Part,
+    pub boost: Option<ResourceType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostileCreepInfo {
+    pub id: RawObjectId,
+    pub owner: String,
+    pub pos: Position,
+    // active body parts only
+    pub body: Vec<BodyPartInfo>,
+    pub hits: u32,
+    pub hits_max: u32,
+}
+
+impl HostileCreepInfo {
+    pub fn count_parts(&self, part: Part) -> u32 {
+        self.body.iter().filter(|body_part| body_part.part == part).count() as u32
+    }
+}
+
+impl CreepInfo {
+    pub fn count_parts(&self, part: Part) -> u32 {
+        self.body.iter().filter(|body_part| **body_part == part).count() as u32
+    }
+}
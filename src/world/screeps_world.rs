@@ -0,0 +1,470 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use js_sys::JsString;
+use log::warn;
+use screeps::{
+    find, game, memory::ROOT, ConstructionSite, Creep, Direction, ErrorCode, HasId, HasPosition,
+    LocalRoomTerrain, MaybeHasId, OwnedStructureProperties, Part, Position, RawObjectId,
+    ResourceType, Room, RoomName, RoomObject, SharedCreepProperties, Source, Resource,
+    SpawnOptions, Store, Structure, StructureController, StructureLink, StructureObject,
+    StructureProperties, StructureSpawn, StructureTower, StructureType,
+};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::zerg::CreepMemory;
+
+use super::{
+    BodyPartInfo, ConstructionSiteInfo, ControllerInfo, CreepInfo, DroppedResourceInfo,
+    HostileCreepInfo, MineralInfo, RoomInfo, SourceInfo, SpawnInfo, StoreInfo, StructureInfo,
+    World,
+};
+
+const SNAPSHOT_KEY: &str = "swarm";
+
+// `World` backed by screeps-game-api. game objects are looked up by id or name on
+// every call, so nothing held here goes stale between ticks.
+#[derive(Default)]
+pub struct ScreepsWorld {
+    // find(STRUCTURES) is the most expensive read, and asked by every overlord.
+    structure_cache: RefCell<HashMap<RoomName, (u32, Vec<StructureInfo>)>>,
+    // terrain never changes, it is kept until the next global reset
+    terrain_cache: RefCell<HashMap<RoomName, LocalRoomTerrain>>,
+}
+
+impl ScreepsWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get_room(room_name: RoomName) -> Option<Room> {
+        game::rooms().get(room_name)
+    }
+
+    fn get_creep(name: &str) -> Result<Creep, ErrorCode> {
+        game::creeps()
+            .get(name.to_string())
+            .ok_or(ErrorCode::NotFound)
+    }
+
+    fn get_object<T>(id: RawObjectId) -> Result<T, ErrorCode>
+    where
+        T: JsCast,
+    {
+        game::get_object_by_id_erased(&id)
+            .map(|object| JsValue::from(object).unchecked_into::<T>())
+            .ok_or(ErrorCode::InvalidTarget)
+    }
+
+    fn store_info(store: &Store) -> StoreInfo {
+        let energy = store.get_used_capacity(Some(ResourceType::Energy));
+        let capacity = store.get_capacity(Some(ResourceType::Energy));
+        // single resource stores (spawn, extension, tower...) report nothing without a type
+        let used = if store.get_capacity(None) == 0 {
+            energy
+        } else {
+            store.get_used_capacity(None)
+        };
+        StoreInfo {
+            energy,
+            used,
+            capacity,
+        }
+    }
+
+    fn structure_info(structure: &StructureObject) -> StructureInfo {
+        let raw: &Structure = structure.as_structure();
+        StructureInfo {
+            id: raw.raw_id(),
+            structure_type: structure.structure_type(),
+            pos: raw.pos(),
+            hits: raw.hits(),
+            hits_max: raw.hits_max(),
+            my: structure.as_owned().map(|owned| owned.my()).unwrap_or(false),
+            store: structure
+                .as_has_store()
+                .map(|has_store| Self::store_info(&has_store.store())),
+        }
+    }
+
+    fn creep_info(creep: &Creep) -> CreepInfo {
+        CreepInfo {
+            name: creep.name(),
+            pos: creep.pos(),
+            body: creep
+                .body()
+                .iter()
+                .filter(|body_part| body_part.hits() > 0)
+                .map(|body_part| body_part.part())
+                .collect(),
+            store: Self::store_info(&creep.store()),
+            spawning: creep.spawning(),
+            ticks_to_live: creep.ticks_to_live(),
+            hits: creep.hits(),
+            hits_max: creep.hits_max(),
+            fatigue: creep.fatigue(),
+            memory: CreepMemory::from_value(creep.memory()).ok(),
+        }
+    }
+}
+
+impl World for ScreepsWorld {
+    fn time(&self) -> u32 {
+        game::time()
+    }
+
+    fn cpu_used(&self) -> f64 {
+        game::cpu::get_used()
+    }
+
+    fn cpu_bucket(&self) -> i32 {
+        game::cpu::bucket()
+    }
+
+    fn spawn_rooms(&self) -> Vec<RoomName> {
+        let mut room_names: Vec<RoomName> = game::spawns()
+            .values()
+            .map(|spawn| spawn.pos().room_name())
+            .collect();
+        room_names.sort();
+        room_names.dedup();
+        room_names
+    }
+
+    fn room(&self, room_name: RoomName) -> Option<RoomInfo> {
+        Self::get_room(room_name).map(|room| RoomInfo {
+            name: room_name,
+            energy_available: room.energy_available(),
+            energy_capacity_available: room.energy_capacity_available(),
+        })
+    }
+
+    fn terrain(&self, room_name: RoomName) -> Option<LocalRoomTerrain> {
+        if let Some(terrain) = self.terrain_cache.borrow().get(&room_name) {
+            return Some(terrain.clone());
+        }
+        let terrain = LocalRoomTerrain::from(game::map::get_room_terrain(room_name));
+        self.terrain_cache
+            .borrow_mut()
+            .insert(room_name, terrain.clone());
+        Some(terrain)
+    }
+
+    fn controller(&self, room_name: RoomName) -> Option<ControllerInfo> {
+        let controller = Self::get_room(room_name)?.controller()?;
+        Some(ControllerInfo {
+            id: controller.raw_id(),
+            pos: controller.pos(),
+            my: controller.my(),
+            level: controller.level(),
+            progress: controller.progress(),
+            progress_total: controller.progress_total(),
+            ticks_to_downgrade: controller.ticks_to_downgrade(),
+            safe_mode: controller.safe_mode().unwrap_or(0),
+            safe_mode_available: controller.safe_mode_available(),
+            safe_mode_cooldown: controller.safe_mode_cooldown().unwrap_or(0),
+        })
+    }
+
+    fn sources(&self, room_name: RoomName) -> Vec<SourceInfo> {
+        let room = match Self::get_room(room_name) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+        room.find(find::SOURCES, None)
+            .iter()
+            .map(|source| SourceInfo {
+                id: source.raw_id(),
+                pos: source.pos(),
+                energy: source.energy(),
+                energy_capacity: source.energy_capacity(),
+            })
+            .collect()
+    }
+
+    fn minerals(&self, room_name: RoomName) -> Vec<MineralInfo> {
+        let room = match Self::get_room(room_name) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+        room.find(find::MINERALS, None)
+            .iter()
+            .map(|mineral| MineralInfo {
+                id: mineral.raw_id(),
+                pos: mineral.pos(),
+            })
+            .collect()
+    }
+
+    fn spawns(&self, room_name: RoomName) -> Vec<SpawnInfo> {
+        game::spawns()
+            .values()
+            .filter(|spawn| spawn.pos().room_name() == room_name)
+            .map(|spawn| SpawnInfo {
+                id: spawn.raw_id(),
+                name: spawn.name().into(),
+                pos: spawn.pos(),
+                spawning: spawn.spawning().is_some(),
+            })
+            .collect()
+    }
+
+    fn structures(&self, room_name: RoomName) -> Vec<StructureInfo> {
+        let time = game::time();
+        if let Some((cached_time, structures)) = self.structure_cache.borrow().get(&room_name) {
+            if *cached_time == time {
+                return structures.clone();
+            }
+        }
+
+        let structures: Vec<StructureInfo> = match Self::get_room(room_name) {
+            Some(room) => room
+                .find(find::STRUCTURES, None)
+                .iter()
+                .map(Self::structure_info)
+                .collect(),
+            None => Vec::new(),
+        };
+        self.structure_cache
+            .borrow_mut()
+            .insert(room_name, (time, structures.clone()));
+        structures
+    }
+
+    fn construction_sites(&self, room_name: RoomName) -> Vec<ConstructionSiteInfo> {
+        let room = match Self::get_room(room_name) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+        room.find(find::MY_CONSTRUCTION_SITES, None)
+            .iter()
+            .filter_map(|site| {
+                Some(ConstructionSiteInfo {
+                    id: site.try_raw_id()?,
+                    structure_type: site.structure_type(),
+                    pos: site.pos(),
+                    progress: site.progress(),
+                    progress_total: site.progress_total(),
+                })
+            })
+            .collect()
+    }
+
+    fn dropped_resources(&self, room_name: RoomName) -> Vec<DroppedResourceInfo> {
+        let room = match Self::get_room(room_name) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+        room.find(find::DROPPED_RESOURCES, None)
+            .iter()
+            .map(|resource| DroppedResourceInfo {
+                id: resource.raw_id(),
+                pos: resource.pos(),
+                resource_type: resource.resource_type(),
+                amount: resource.amount(),
+            })
+            .collect()
+    }
+
+    fn creep(&self, name: &str) -> Option<CreepInfo> {
+        Self::get_creep(name).ok().map(|creep| Self::creep_info(&creep))
+    }
+
+    fn creeps(&self) -> Vec<CreepInfo> {
+        game::creeps()
+            .values()
+            .map(|creep| Self::creep_info(&creep))
+            .collect()
+    }
+
+    fn hostile_creeps(&self, room_name: RoomName) -> Vec<HostileCreepInfo> {
+        let room = match Self::get_room(room_name) {
+            Some(room) => room,
+            None => return Vec::new(),
+        };
+        room.find(find::HOSTILE_CREEPS, None)
+            .iter()
+            .filter_map(|creep| {
+                Some(HostileCreepInfo {
+                    id: creep.try_raw_id()?,
+                    owner: creep.owner().username(),
+                    pos: creep.pos(),
+                    body: creep
+                        .body()
+                        .iter()
+                        .filter(|body_part| body_part.hits() > 0)
+                        .map(|body_part| BodyPartInfo {
+                            part: body_part.part(),
+                            boost: body_part.boost(),
+                        })
+                        .collect(),
+                    hits: creep.hits(),
+                    hits_max: creep.hits_max(),
+                })
+            })
+            .collect()
+    }
+
+    fn set_creep_memory(&self, name: &str, memory: &CreepMemory) {
+        match Self::get_creep(name) {
+            Ok(creep) => creep.set_memory(&memory.into_value()),
+            Err(_) => warn!("set creep memory failed, creep {} not found", name),
+        }
+    }
+
+    fn spawn_creep(
+        &self,
+        spawn: RawObjectId,
+        body: &[Part],
+        name: &str,
+        memory: &CreepMemory,
+    ) -> Result<(), ErrorCode> {
+        let spawn = Self::get_object::<StructureSpawn>(spawn)?;
+        let spawn_opts = SpawnOptions::new().memory(memory.into_value());
+        spawn.spawn_creep_with_options(body, name, &spawn_opts)
+    }
+
+    fn harvest(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.harvest(&Self::get_object::<Source>(target)?)
+    }
+
+    fn transfer(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        let structure = StructureObject::from(Self::get_object::<Structure>(target)?);
+        let transferable = structure
+            .as_transferable()
+            .ok_or(ErrorCode::InvalidTarget)?;
+        creep.transfer(transferable, resource, amount)
+    }
+
+    fn withdraw(
+        &self,
+        creep: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        let structure = StructureObject::from(Self::get_object::<Structure>(target)?);
+        let withdrawable = structure
+            .as_withdrawable()
+            .ok_or(ErrorCode::InvalidTarget)?;
+        creep.withdraw(withdrawable, resource, amount)
+    }
+
+    fn transfer_to_creep(
+        &self,
+        creep: &str,
+        target: &str,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.transfer(&Self::get_creep(target)?, resource, amount)
+    }
+
+    fn pickup(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.pickup(&Self::get_object::<Resource>(target)?)
+    }
+
+    fn build(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.build(&Self::get_object::<ConstructionSite>(target)?)
+    }
+
+    fn upgrade_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.upgrade_controller(&Self::get_object::<StructureController>(target)?)
+    }
+
+    fn repair(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.repair(&Self::get_object::<RoomObject>(target)?)
+    }
+
+    fn attack(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.attack(&Self::get_object::<Creep>(target)?)
+    }
+
+    fn heal(&self, creep: &str, target: &str) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.heal(&Self::get_creep(target)?)
+    }
+
+    fn claim_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.claim_controller(&Self::get_object::<StructureController>(target)?)
+    }
+
+    fn reserve_controller(&self, creep: &str, target: RawObjectId) -> Result<(), ErrorCode> {
+        let creep = Self::get_creep(creep)?;
+        creep.reserve_controller(&Self::get_object::<StructureController>(target)?)
+    }
+
+    fn move_direction(&self, creep: &str, direction: Direction) -> Result<(), ErrorCode> {
+        Self::get_creep(creep)?.move_direction(direction)
+    }
+
+    fn transfer_energy(
+        &self,
+        link: RawObjectId,
+        target: RawObjectId,
+        amount: Option<u32>,
+    ) -> Result<(), ErrorCode> {
+        let link = Self::get_object::<StructureLink>(link)?;
+        link.transfer_energy(&Self::get_object::<StructureLink>(target)?, amount)
+    }
+
+    fn tower_repair(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode> {
+        let tower = Self::get_object::<StructureTower>(tower)?;
+        tower.repair(&Self::get_object::<Structure>(target)?)
+    }
+
+    fn activate_safe_mode(&self, controller: RawObjectId) -> Result<(), ErrorCode> {
+        Self::get_object::<StructureController>(controller)?.activate_safe_mode()
+    }
+
+    fn tower_attack(&self, tower: RawObjectId, target: RawObjectId) -> Result<(), ErrorCode> {
+        let tower = Self::get_object::<StructureTower>(tower)?;
+        tower.attack(&Self::get_object::<Creep>(target)?)
+    }
+
+    fn tower_heal(&self, tower: RawObjectId, creep: &str) -> Result<(), ErrorCode> {
+        let tower = Self::get_object::<StructureTower>(tower)?;
+        tower.heal(&Self::get_creep(creep)?)
+    }
+
+    fn create_construction_site(
+        &self,
+        pos: Position,
+        structure_type: StructureType,
+    ) -> Result<(), ErrorCode> {
+        let room = Self::get_room(pos.room_name()).ok_or(ErrorCode::NotInRange)?;
+        room.create_construction_site(pos.x().u8(), pos.y().u8(), structure_type, None)
+    }
+
+    fn load_snapshot(&self) -> Option<String> {
+        js_sys::Reflect::get(&ROOT, &JsValue::from_str(SNAPSHOT_KEY))
+            .ok()
+            .and_then(|value| value.as_string())
+    }
+
+    fn store_snapshot(&self, snapshot: &str) {
+        let result = js_sys::Reflect::set(
+            &ROOT,
+            &JsValue::from_str(SNAPSHOT_KEY),
+            &JsString::from(snapshot),
+        );
+        if let Err(e) = result {
+            warn!("write swarm memory failed. {:?}", e);
+        }
+    }
+}
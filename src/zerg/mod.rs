@@ -1,26 +1,37 @@
+mod state;
+
+pub use state::{
+    run_state_machine, CreepState, EnergyCycle, HaulerState, MinerState, RoleState, WorkerState,
+};
+
 use log::warn;
 use wasm_bindgen::JsValue;
 
-use crate::error::SwarmError;
+use crate::{error::SwarmError, task::Task};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CreepMemory {
     pub overlord: String,
     pub role: String,
-    // pub colony: String,
-    pub state: Option<String>,
+    // room of the colony the creep works for, empty in memory written before
+    // creeps knew their colony
+    #[serde(default)]
+    pub colony: String,
+    #[serde(default, deserialize_with = "state::deserialize_state")]
+    pub state: Option<CreepState>,
+    // what the creep is doing, see `task::run_task`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<Task>,
 }
 
 impl CreepMemory {
-    pub fn from_value(js_value: JsValue) -> Self {
-        let result: Result<CreepMemory, _> = serde_wasm_bindgen::from_value(js_value);
-        if result.is_err() {
-            warn!("Parse creep memory failed.");
-        }
-
-        result.unwrap()
+    pub fn from_value(js_value: JsValue) -> Result<Self, SwarmError> {
+        serde_wasm_bindgen::from_value(js_value).map_err(|e| {
+            warn!("Parse creep memory failed. {:?}", e);
+            SwarmError::InternalAssertionFailed("parse creep memory failed".to_string())
+        })
     }
 
     pub fn into_value(&self) -> JsValue {
@@ -0,0 +1,254 @@
+use std::fmt::Debug;
+
+use log::debug;
+use serde::{de::IgnoredAny, Deserialize, Deserializer, Serialize};
+
+use crate::world::{CreepInfo, World};
+
+// the state of a creep, tagged by the role it belongs to so a creep handed to
+// an overlord of another role starts over instead of misreading it.
+// stored as `{"role": "miner", "state": "mining"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "role", content = "state", rename_all = "snake_case")]
+pub enum CreepState {
+    Miner(MinerState),
+    Worker(WorkerState),
+    Hauler(HaulerState),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MinerState {
+    Mining,
+    Transfering,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    Collecting,
+    Building,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HaulerState {
+    Collecting,
+    Delivering,
+}
+
+// states written by older versions, or anything else we don't know, read as
+// no state at all and the creep starts over
+pub(super) fn deserialize_state<'de, D>(deserializer: D) -> Result<Option<CreepState>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Known(CreepState),
+        Unknown(IgnoredAny),
+    }
+    Ok(match Option::<Stored>::deserialize(deserializer)? {
+        Some(Stored::Known(state)) => Some(state),
+        _ => None,
+    })
+}
+
+// the states of one role. the hooks run on every change of state, after the
+// new state is chosen and before it is persisted.
+pub trait RoleState: Copy + PartialEq + Debug {
+    // the state of a creep without one of this role
+    fn initial(creep: &CreepInfo) -> Self;
+
+    fn from_state(state: CreepState) -> Option<Self>;
+
+    fn into_state(self) -> CreepState;
+
+    fn on_exit(self, _world: &dyn World, _creep: &CreepInfo) {}
+
+    fn on_enter(self, _world: &dyn World, _creep: &CreepInfo) {}
+}
+
+// roles that fill up and then spend everything they carry
+pub trait EnergyCycle: RoleState {
+    const FILLING: Self;
+    const SPENDING: Self;
+
+    // full creeps spend, empty ones fill up, the others keep at what they do
+    fn cycle(self, creep: &CreepInfo) -> Self {
+        if self == Self::FILLING && creep.store.free() == 0 {
+            Self::SPENDING
+        } else if self == Self::SPENDING && creep.store.used == 0 {
+            Self::FILLING
+        } else {
+            self
+        }
+    }
+}
+
+// loads the state of the creep, lets `transition` pick the state of this tick
+// and persists it when it changed. the memory of `creep` is updated along, so
+// it can be written again later in the tick. returns none for creeps without
+// memory.
+pub fn run_state_machine<S, F>(world: &dyn World, creep: &mut CreepInfo, transition: F) -> Option<S>
+where
+    S: RoleState,
+    F: FnOnce(S, &CreepInfo) -> S,
+{
+    let current = creep.memory.as_ref()?.state.and_then(S::from_state);
+    let state = transition(current.unwrap_or_else(|| S::initial(creep)), creep);
+    if current == Some(state) {
+        return Some(state);
+    }
+    if let Some(current) = current {
+        debug!("{} {:?} -> {:?}", creep.name, current, state);
+        current.on_exit(world, creep);
+    }
+    state.on_enter(world, creep);
+    let memory = creep.memory.as_mut()?;
+    memory.state = Some(state.into_state());
+    world.set_creep_memory(&creep.name, memory);
+    Some(state)
+}
+
+impl RoleState for MinerState {
+    // drones may be born next to a container, they deliver what they pick up
+    // on the way first
+    fn initial(_creep: &CreepInfo) -> Self {
+        MinerState::Transfering
+    }
+
+    fn from_state(state: CreepState) -> Option<Self> {
+        match state {
+            CreepState::Miner(state) => Some(state),
+            _ => None,
+        }
+    }
+
+    fn into_state(self) -> CreepState {
+        CreepState::Miner(self)
+    }
+}
+
+impl EnergyCycle for MinerState {
+    const FILLING: Self = MinerState::Mining;
+    const SPENDING: Self = MinerState::Transfering;
+}
+
+impl RoleState for WorkerState {
+    fn initial(_creep: &CreepInfo) -> Self {
+        WorkerState::Collecting
+    }
+
+    fn from_state(state: CreepState) -> Option<Self> {
+        match state {
+            CreepState::Worker(state) => Some(state),
+            _ => None,
+        }
+    }
+
+    fn into_state(self) -> CreepState {
+        CreepState::Worker(self)
+    }
+}
+
+impl EnergyCycle for WorkerState {
+    const FILLING: Self = WorkerState::Collecting;
+    const SPENDING: Self = WorkerState::Building;
+}
+
+impl RoleState for HaulerState {
+    fn initial(_creep: &CreepInfo) -> Self {
+        HaulerState::Collecting
+    }
+
+    fn from_state(state: CreepState) -> Option<Self> {
+        match state {
+            CreepState::Hauler(state) => Some(state),
+            _ => None,
+        }
+    }
+
+    fn into_state(self) -> CreepState {
+        CreepState::Hauler(self)
+    }
+}
+
+impl EnergyCycle for HaulerState {
+    const FILLING: Self = HaulerState::Collecting;
+    const SPENDING: Self = HaulerState::Delivering;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        world::mock::{position, MockWorld},
+        zerg::CreepMemory,
+    };
+    use screeps::Part;
+
+    fn add_creep(world: &MockWorld, state: Option<CreepState>, carry: u32) -> CreepInfo {
+        world.add_creep(
+            "creep",
+            position("W1N1", 10, 10),
+            &[Part::Work, Part::Carry, Part::Move],
+            CreepMemory {
+                overlord: "work-W1N1".to_string(),
+                role: "worker".to_string(),
+                colony: "W1N1".to_string(),
+                state,
+                task: None,
+            },
+        );
+        world.set_creep_energy("creep", carry);
+        world.creep("creep").unwrap()
+    }
+
+    #[test]
+    fn cycles_between_filling_and_spending() {
+        let world = MockWorld::new();
+        let mut creep = add_creep(&world, None, 50);
+        let state = run_state_machine(&world, &mut creep, WorkerState::cycle);
+        assert_eq!(state, Some(WorkerState::Building));
+        assert_eq!(
+            world.creep("creep").unwrap().memory.unwrap().state,
+            Some(CreepState::Worker(WorkerState::Building))
+        );
+
+        let mut creep = add_creep(&world, state.map(RoleState::into_state), 20);
+        let state = run_state_machine(&world, &mut creep, WorkerState::cycle);
+        assert_eq!(state, Some(WorkerState::Building));
+        let mut creep = add_creep(&world, state.map(RoleState::into_state), 0);
+        let state = run_state_machine(&world, &mut creep, WorkerState::cycle);
+        assert_eq!(state, Some(WorkerState::Collecting));
+    }
+
+    #[test]
+    fn state_of_another_role_starts_over() {
+        let world = MockWorld::new();
+        let mut creep = add_creep(&world, Some(CreepState::Hauler(HaulerState::Delivering)), 20);
+        let state = run_state_machine(&world, &mut creep, |state: MinerState, _| state);
+        assert_eq!(state, Some(MinerState::Transfering));
+    }
+
+    #[test]
+    fn state_is_tagged_by_role() {
+        let memory = CreepMemory {
+            overlord: "mine".to_string(),
+            role: "drone".to_string(),
+            colony: "W1N1".to_string(),
+            state: Some(CreepState::Miner(MinerState::Mining)),
+            task: None,
+        };
+        let json = serde_json::to_string(&memory).unwrap();
+        assert!(json.contains(r#""state":{"role":"miner","state":"mining"}"#));
+        assert_eq!(serde_json::from_str::<CreepMemory>(&json).unwrap(), memory);
+
+        let legacy = r#"{"overlord":"mine","role":"drone","state":"transfering"}"#;
+        let memory = serde_json::from_str::<CreepMemory>(legacy).unwrap();
+        assert_eq!(memory.state, None);
+        assert_eq!(memory.colony, "");
+    }
+}